@@ -0,0 +1,10 @@
+// Uniqueness for everything we look nodes up by. Each constraint also gives us an index.
+CREATE CONSTRAINT user_id IF NOT EXISTS FOR (u:User) REQUIRE u.id IS UNIQUE;
+CREATE CONSTRAINT user_username IF NOT EXISTS FOR (u:User) REQUIRE u.username IS UNIQUE;
+CREATE CONSTRAINT user_email IF NOT EXISTS FOR (u:User) REQUIRE u.email IS UNIQUE;
+CREATE CONSTRAINT recipe_id IF NOT EXISTS FOR (r:Recipe) REQUIRE r.id IS UNIQUE;
+CREATE CONSTRAINT migration_version IF NOT EXISTS FOR (m:Migration) REQUIRE m.version IS UNIQUE;
+
+// Ingredients are merged by name and tipo so the name alone can't be unique, it still gets
+// searched on a lot.
+CREATE INDEX ingredient_name IF NOT EXISTS FOR (i:Ingredient) ON (i.name);
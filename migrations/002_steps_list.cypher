@@ -0,0 +1,11 @@
+// Steps used to be saved as one "1. foo\n2. bar\n" string. Turn them into a list and drop the
+// numbering we added. STARTS WITH is null for anything that isn't a string so recipes that
+// already have a list are left alone.
+MATCH (r:Recipe)
+WHERE r.steps STARTS WITH ''
+WITH r, [line IN split(r.steps, '\n') WHERE trim(line) <> ''] AS lines
+SET r.steps = [line IN lines |
+    CASE WHEN line =~ '[0-9]+\\. .*'
+        THEN substring(line, size(split(line, '. ')[0]) + 2)
+        ELSE line
+    END];
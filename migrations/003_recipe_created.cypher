@@ -0,0 +1,5 @@
+// Recipes get a creation date so the listings can be sorted by it. Older recipes never had one,
+// they get the date of the migration.
+MATCH (r:Recipe)
+WHERE r.created IS NULL
+SET r.created = localdatetime();
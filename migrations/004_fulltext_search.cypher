@@ -0,0 +1,12 @@
+// Fulltext indexes for /api/recipes/search. The index can't look inside the steps list so the
+// steps also get stored joined in `steps_text`, the stores keep both in sync from here on.
+MATCH (r:Recipe)
+WHERE r.steps IS NOT NULL AND r.steps_text IS NULL
+SET r.steps_text = reduce(text = '', step IN r.steps |
+    CASE text WHEN '' THEN step ELSE text + '\n' + step END);
+
+CREATE FULLTEXT INDEX recipe_text IF NOT EXISTS
+FOR (r:Recipe) ON EACH [r.name, r.tipo, r.steps_text];
+
+CREATE FULLTEXT INDEX ingredient_names IF NOT EXISTS
+FOR (i:Ingredient) ON EACH [i.name];
@@ -0,0 +1,3 @@
+// USES relationships get a parsed `quantity` and `unit` next to the raw `amount`. The parsing
+// lives in rust so there's nothing to run here, the existing relationships are filled in by the
+// IngredientQuantities backfill in src/migrations.rs.
@@ -0,0 +1,15 @@
+// Servings used to be free text like "4" or "4 personas". The number goes to `servings` and
+// whatever came after it to `yield_unit`. Text that doesn't start with a number is kept whole as
+// the yield unit, and 0 servings means unknown. Same STARTS WITH trick as the 002 migration so
+// recipes that already have a number are left alone.
+MATCH (r:Recipe)
+WHERE r.servings STARTS WITH ''
+WITH r, trim(r.servings) AS text
+WITH r, text, [word IN split(text, ' ') WHERE word <> ''] AS words
+WITH r, text, words, toInteger(head(words)) AS servings
+SET r.servings = coalesce(servings, 0),
+    r.yield_unit = CASE
+        WHEN servings IS NULL THEN text
+        ELSE reduce(unit = '', word IN tail(words) |
+            CASE unit WHEN '' THEN word ELSE unit + ' ' + word END)
+    END;
@@ -0,0 +1,25 @@
+// CHOSEN relationships are now meal plan entries with an id, a date and a slot. A plan is a week
+// of dinners so only the last 7 a user picked stay in it, the older ones go to the history as
+// PLANNED on the day they were picked, same as `archive_plan` would have left them.
+MATCH (u:User)-[c:CHOSEN]->(r:Recipe)
+WHERE c.date IS NULL
+WITH u, c ORDER BY c.created, r.name
+WITH u, collect(c) AS chosen
+WHERE size(chosen) > 7
+UNWIND chosen[..size(chosen) - 7] AS c
+WITH u, c, endNode(c) AS r
+CREATE (u)-[:PLANNED {id: randomUUID(), date: date(c.created), slot: 'dinner',
+    created: c.created, archived: localdatetime()}]->(r)
+DELETE c;
+
+// The ones left get laid out the way /weekly does it, one dinner a day in the order they were
+// picked, starting on the day the first of them was picked so they all fall in the same week.
+MATCH (u:User)-[c:CHOSEN]->(r:Recipe)
+WHERE c.date IS NULL
+WITH u, c ORDER BY c.created, r.name
+WITH u, collect(c) AS chosen
+UNWIND range(0, size(chosen) - 1) AS idx
+WITH chosen[idx] AS c, idx, date(head(chosen).created) AS first
+SET c.id = randomUUID(),
+    c.date = first + duration({days: idx}),
+    c.slot = 'dinner';
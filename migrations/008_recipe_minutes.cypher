@@ -0,0 +1,3 @@
+// Recipes get the minutes parsed out of their free text `time` in `minutes`, that's what sorting
+// by time goes by. The parsing lives in rust so there's nothing to run here, the existing recipes
+// are filled in by the RecipeMinutes backfill in src/migrations.rs.
@@ -0,0 +1,154 @@
+use rocket::http::Status;
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::Json;
+use serde::Serialize;
+
+pub type ApiResult<T> = std::result::Result<T, ApiError>;
+
+// All the client gets to see of a driver error, the details only go to the server log
+const DRIVER_ERROR: &str = "Database error";
+
+// Every error the API can send back. They all get turned into the same JSON body so the
+// frontend only has to deal with one shape, eg. {"code": "not_found", "message": "...",
+// "status": 404}
+#[derive(Clone, Debug)]
+pub enum ApiError {
+    Internal(String),
+    Database(String),
+    NotFound(String),
+    Validation(String),
+    Unauthorized(String),
+    Forbidden(String),
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorBody {
+    pub code: &'static str,
+    pub message: String,
+    pub status: u16,
+}
+
+impl ApiError {
+    pub fn not_found(what: &str) -> Self {
+        ApiError::NotFound(format!("{} not found", what))
+    }
+
+    pub fn status(&self) -> Status {
+        match self {
+            ApiError::Internal(_) | ApiError::Database(_) => Status::InternalServerError,
+            ApiError::NotFound(_) => Status::NotFound,
+            ApiError::Validation(_) => Status::BadRequest,
+            ApiError::Unauthorized(_) => Status::Unauthorized,
+            ApiError::Forbidden(_) => Status::Forbidden,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::Internal(_) => "internal_error",
+            ApiError::Database(_) => "database_error",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Validation(_) => "validation_error",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            ApiError::Internal(msg)
+            | ApiError::Database(msg)
+            | ApiError::NotFound(msg)
+            | ApiError::Validation(msg)
+            | ApiError::Unauthorized(msg)
+            | ApiError::Forbidden(msg) => msg,
+        }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        ErrorBody {
+            code: self.code(),
+            message: self.message().to_string(),
+            status: self.status().code,
+        }
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code(), self.message())
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+// Anything the driver complains about is on our side, the client can't do much about it. Its
+// messages can carry the cypher, constraint names or connection details so they stay in the log.
+impl From<neo4rs::Error> for ApiError {
+    fn from(e: neo4rs::Error) -> Self {
+        eprintln!("Database error: {:?}", e);
+        ApiError::Database(DRIVER_ERROR.to_string())
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        // Driver errors were logged with their details when they got converted
+        match &self {
+            ApiError::Database(msg) if msg != DRIVER_ERROR => eprintln!("Database error: {}", msg),
+            _ => {}
+        }
+        Response::build_from(Json(self.body()).respond_to(request)?)
+            .status(self.status())
+            .ok()
+    }
+}
+
+// Catchers for the failures that never reach a handler, mostly request guards failing. Rocket
+// doesn't hand us the guard's error here so the message is a generic one for the status.
+#[catch(400)]
+pub fn bad_request() -> ApiError {
+    ApiError::Validation("Bad request".to_string())
+}
+
+#[catch(401)]
+pub fn unauthorized() -> ApiError {
+    ApiError::Unauthorized("You need to be logged in".to_string())
+}
+
+#[catch(403)]
+pub fn forbidden() -> ApiError {
+    ApiError::Forbidden("Forbidden".to_string())
+}
+
+#[catch(404)]
+pub fn not_found() -> ApiError {
+    ApiError::NotFound("Not found".to_string())
+}
+
+#[catch(422)]
+pub fn unprocessable_entity() -> ApiError {
+    ApiError::Validation("Couldn't parse the request body".to_string())
+}
+
+#[catch(500)]
+pub fn internal_error() -> ApiError {
+    ApiError::Internal("Internal server error".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn driver_errors_keep_their_details_out_of_the_body() {
+        let error = ApiError::from(neo4rs::Error::UnexpectedMessage(
+            "Node(0) already exists with label `User` and property `email` = 'a@b.co'".to_string(),
+        ));
+        let body = error.body();
+        assert_eq!(body.code, "database_error");
+        assert_eq!(body.message, "Database error");
+        assert_eq!(body.status, 500);
+    }
+}
@@ -1,2 +1,8 @@
+pub mod picker;
+pub mod plan;
+pub mod quantity;
 pub mod recipes;
+pub mod search;
+pub mod shopping;
+pub mod units;
 pub mod users;
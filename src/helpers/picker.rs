@@ -0,0 +1,487 @@
+use crate::errors::{ApiError, ApiResult};
+use crate::models::{PickerWeights, Recipe, UnmetConstraint, WeeklyQuery};
+use chrono::NaiveDate;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+const DEFAULT_WEEKLY_AMOUNT: usize = 7;
+const DAYS_IN_WEEK: f64 = 7.0;
+// How far off the calorie and protein targets can be and still count as met
+const TARGET_TOLERANCE: f64 = 0.1;
+// Swaps tried before settling for the best pick found so far
+const SEARCH_STEPS: usize = 2000;
+// Weeks since a recipe was last eaten that still make a difference, never counts as this many
+pub const RECENCY_WEEKS: u16 = 8;
+// How much more a favorite weighs with the favorites knob at 1
+const FAVORITE_BOOST: f64 = 2.0;
+// The knobs go from minus this to this
+pub const MAX_PICKER_WEIGHT: f64 = 10.0;
+
+#[derive(Clone, Copy)]
+enum Nutrient {
+    Calories,
+    Protein,
+}
+
+// A WeeklyQuery worked out into what the search checks. `recent` are the recipes that were in the
+// plan in the last `no_repeat_weeks` weeks.
+pub struct WeeklyConstraints {
+    amount: usize,
+    targets: Vec<(&'static str, Nutrient, f64)>,
+    mix: Vec<(String, usize)>,
+    max_time: Option<u32>,
+    recent: HashSet<Uuid>,
+}
+
+pub fn weekly_constraints(
+    query: &WeeklyQuery,
+    recent: HashSet<Uuid>,
+) -> ApiResult<WeeklyConstraints> {
+    let targets = [
+        (
+            "daily_calories",
+            Nutrient::Calories,
+            query.daily_calories,
+            DAYS_IN_WEEK,
+        ),
+        (
+            "weekly_calories",
+            Nutrient::Calories,
+            query.weekly_calories,
+            1.0,
+        ),
+        (
+            "daily_protein",
+            Nutrient::Protein,
+            query.daily_protein,
+            DAYS_IN_WEEK,
+        ),
+        (
+            "weekly_protein",
+            Nutrient::Protein,
+            query.weekly_protein,
+            1.0,
+        ),
+    ]
+    .iter()
+    .filter_map(|&(name, nutrient, target, days)| {
+        target
+            .filter(|target| *target > 0.0)
+            .map(|target| (name, nutrient, target as f64 * days))
+    })
+    .collect();
+
+    let mix = query
+        .mix
+        .iter()
+        .flat_map(|entry| entry.split(','))
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            entry
+                .split_once(':')
+                .and_then(|(meal_type, count)| {
+                    let count = count.trim().parse().ok()?;
+                    Some((meal_type.trim().to_lowercase(), count))
+                })
+                .ok_or_else(|| ApiError::Validation(format!("{} should look like dinner:4", entry)))
+        })
+        .collect::<ApiResult<_>>()?;
+
+    Ok(WeeklyConstraints {
+        amount: query.amount.unwrap_or(DEFAULT_WEEKLY_AMOUNT),
+        targets,
+        mix,
+        max_time: query.max_time,
+        recent,
+    })
+}
+
+// What the weekly suggestion leans on besides chance. `last_eaten` only needs the last
+// RECENCY_WEEKS weeks of the plan, its history and the cook log.
+pub struct Weighting {
+    pub weights: PickerWeights,
+    pub likes: HashMap<Uuid, usize>,
+    pub last_eaten: HashMap<Uuid, NaiveDate>,
+    pub favorites: HashSet<Uuid>,
+    pub today: NaiveDate,
+}
+
+// Every knob multiplies the weight by (1 + what it measures) to the power of the knob, so 0 makes
+// it count for nothing. Likes are the users liking the recipe and recency the weeks since it was
+// last planned or cooked.
+fn recipe_weight(weighting: &Weighting, recipe: &Recipe) -> f64 {
+    let id = match recipe.id {
+        Some(id) => id,
+        None => return 1.0,
+    };
+    let likes = weighting.likes.get(&id).copied().unwrap_or(0) as f64;
+    let max_days = i64::from(RECENCY_WEEKS) * 7;
+    let days = weighting.last_eaten.get(&id).map_or(max_days, |last| {
+        (weighting.today - *last).num_days().clamp(0, max_days)
+    });
+    let favorite = if weighting.favorites.contains(&id) {
+        FAVORITE_BOOST
+    } else {
+        1.0
+    };
+    let weights = &weighting.weights;
+    (1.0 + likes).powf(weights.likes)
+        * (1.0 + days as f64 / 7.0).powf(weights.recency)
+        * favorite.powf(weights.favorites)
+}
+
+// Minutes in the free text `time` of a recipe, eg. "45", "45 min", "1h 30m", "1:30" or
+// "1.5 hours". A bare number is minutes and a range like "30-45 min" or "1 to 2 hours" goes by
+// its upper end.
+pub fn recipe_minutes(time: &str) -> Option<u32> {
+    let text = time.trim().to_lowercase();
+    let text = text
+        .rsplit(" to ")
+        .next()
+        .and_then(|upper| upper.rsplit(&['-', '–'][..]).next())
+        .unwrap_or_default()
+        .trim();
+    if let Some((hours, minutes)) = text.split_once(':') {
+        if let (Ok(hours), Ok(minutes)) =
+            (hours.trim().parse::<u32>(), minutes.trim().parse::<u32>())
+        {
+            return Some(hours * 60 + minutes);
+        }
+    }
+    let mut total: Option<f64> = None;
+    let mut rest = text;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+        rest = &rest[start..];
+        let len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+            .unwrap_or(rest.len());
+        let number: f64 = rest[..len].replace(',', ".").parse().ok()?;
+        rest = rest[len..].trim_start();
+        let factor = if rest.starts_with('h') { 60.0 } else { 1.0 };
+        *total.get_or_insert(0.0) += number * factor;
+    }
+    total.map(|minutes| minutes.round() as u32)
+}
+
+// Nutrition is for all the servings of the recipe, the targets are for one person
+fn per_person(recipe: &Recipe, nutrient: Nutrient) -> f64 {
+    let value = match nutrient {
+        Nutrient::Calories => recipe.calories.map(f64::from),
+        Nutrient::Protein => recipe.protein.map(f64::from),
+    };
+    value.unwrap_or(0.0) / f64::from(recipe.servings.unwrap_or(1).max(1))
+}
+
+fn unmet(constraint: &str, target: f64, actual: f64) -> UnmetConstraint {
+    UnmetConstraint {
+        constraint: constraint.to_string(),
+        target,
+        actual,
+    }
+}
+
+// Every constraint the pick misses with how badly it does, an empty list means all of them are
+// met. Recipes without a time we can read count as no time at all.
+fn misses(constraints: &WeeklyConstraints, picked: &[&Recipe]) -> Vec<(UnmetConstraint, f64)> {
+    let mut misses = Vec::new();
+    for &(name, nutrient, target) in &constraints.targets {
+        let actual: f64 = picked.iter().map(|r| per_person(r, nutrient)).sum();
+        let off = (actual - target).abs() / target;
+        if off > TARGET_TOLERANCE {
+            misses.push((unmet(name, target, actual), off - TARGET_TOLERANCE));
+        }
+    }
+    for (meal_type, wanted) in &constraints.mix {
+        let count = picked
+            .iter()
+            .filter(|r| {
+                r.meal_type
+                    .as_deref()
+                    .is_some_and(|t| t.eq_ignore_ascii_case(meal_type))
+            })
+            .count();
+        if count < *wanted {
+            misses.push((
+                unmet(&format!("mix:{}", meal_type), *wanted as f64, count as f64),
+                (wanted - count) as f64,
+            ));
+        }
+    }
+    if let Some(max_time) = constraints.max_time {
+        let total: u32 = picked
+            .iter()
+            .filter_map(|r| r.time.as_deref().and_then(recipe_minutes))
+            .sum();
+        if total > max_time {
+            let over = f64::from(total - max_time) / f64::from(max_time.max(1));
+            misses.push((unmet("max_time", max_time.into(), total.into()), over));
+        }
+    }
+    let repeats = picked
+        .iter()
+        .filter(|r| r.id.is_some_and(|id| constraints.recent.contains(&id)))
+        .count();
+    if repeats > 0 {
+        misses.push((
+            unmet("no_repeat_weeks", 0.0, repeats as f64),
+            repeats as f64,
+        ));
+    }
+    misses
+}
+
+fn penalty(constraints: &WeeklyConstraints, candidates: &[Recipe], picked: &[usize]) -> f64 {
+    let picked: Vec<&Recipe> = picked.iter().map(|&i| &candidates[i]).collect();
+    misses(constraints, &picked)
+        .iter()
+        .map(|(_, off)| off)
+        .sum()
+}
+
+// Weighted shuffle of the candidates from `from` on, each gets random^(1 / weight) as its key and
+// the biggest keys go first. The ones that weigh nothing are left out altogether.
+fn weighted_order<R: Rng + ?Sized>(weights: &[f64], from: usize, rng: &mut R) -> Vec<usize> {
+    let mut keyed: Vec<(f64, usize)> = (from..weights.len())
+        .filter(|&i| weights[i] > 0.0)
+        .map(|i| (rng.gen::<f64>().powf(1.0 / weights[i]), i))
+        .collect();
+    keyed.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal));
+    keyed.into_iter().map(|(_, i)| i).collect()
+}
+
+// Starts from the pinned recipes plus a weighted random pick for the other slots and keeps
+// swapping a random unpinned recipe for a weighted random one left out, as long as that doesn't
+// make things worse. Swaps that don't change anything are kept too so it can wander off plateaus.
+// Without constraints the first pick is already good.
+pub fn pick_weekly<R: Rng + ?Sized>(
+    mut candidates: Vec<Recipe>,
+    pinned: Vec<Recipe>,
+    constraints: &WeeklyConstraints,
+    weighting: &Weighting,
+    rng: &mut R,
+) -> (Vec<Recipe>, Vec<UnmetConstraint>) {
+    // Sorted so a seed picks the same recipes whatever order the store returned them in
+    candidates.retain(|recipe| !pinned.iter().any(|pin| pin.id == recipe.id));
+    candidates.sort_by_key(|recipe| recipe.id);
+    let pins = pinned.len();
+    candidates.splice(0..0, pinned);
+
+    let weights: Vec<f64> = candidates
+        .iter()
+        .map(|recipe| recipe_weight(weighting, recipe))
+        .collect();
+    let mut order = weighted_order(&weights, pins, rng);
+    let amount = constraints.amount.max(pins).min(pins + order.len());
+    let mut rest = order.split_off(amount - pins);
+    let mut picked: Vec<usize> = (0..pins).chain(order).collect();
+
+    let mut current = penalty(constraints, &candidates, &picked);
+    for _ in 0..SEARCH_STEPS {
+        if current == 0.0 || picked.len() == pins || rest.is_empty() {
+            break;
+        }
+        let i = rng.gen_range(pins..picked.len());
+        let j = match WeightedIndex::new(rest.iter().map(|&i| weights[i])) {
+            Ok(by_weight) => by_weight.sample(rng),
+            Err(_) => rng.gen_range(0..rest.len()),
+        };
+        std::mem::swap(&mut picked[i], &mut rest[j]);
+        let swapped = penalty(constraints, &candidates, &picked);
+        if swapped <= current {
+            current = swapped;
+        } else {
+            std::mem::swap(&mut picked[i], &mut rest[j]);
+        }
+    }
+
+    let recipes: Vec<&Recipe> = picked.iter().map(|&i| &candidates[i]).collect();
+    let mut unmet_constraints: Vec<UnmetConstraint> = misses(constraints, &recipes)
+        .into_iter()
+        .map(|(unmet, _)| unmet)
+        .collect();
+    if amount < constraints.amount {
+        unmet_constraints.insert(0, unmet("amount", constraints.amount as f64, amount as f64));
+    }
+    (recipes.into_iter().cloned().collect(), unmet_constraints)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn recipe(name: &str, time: &str) -> Recipe {
+        Recipe {
+            id: Some(Uuid::new_v4()),
+            name: name.to_string(),
+            time: Some(time.to_string()),
+            ..Recipe::default()
+        }
+    }
+
+    // Seven quick ones and seven slow ones
+    fn week_of_candidates() -> Vec<Recipe> {
+        (0..14)
+            .map(|i| {
+                let time = if i % 2 == 0 { "10 min" } else { "1h 30" };
+                recipe(&format!("Recipe {}", i), time)
+            })
+            .collect()
+    }
+
+    fn even_weighting() -> Weighting {
+        Weighting {
+            weights: PickerWeights::default(),
+            likes: HashMap::new(),
+            last_eaten: HashMap::new(),
+            favorites: HashSet::new(),
+            today: NaiveDate::from_ymd_opt(2026, 3, 2).unwrap(),
+        }
+    }
+
+    fn pick(
+        candidates: Vec<Recipe>,
+        pinned: Vec<Recipe>,
+        query: &WeeklyQuery,
+        recent: HashSet<Uuid>,
+        seed: u64,
+    ) -> (Vec<String>, Vec<String>) {
+        let constraints = weekly_constraints(query, recent).unwrap();
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (recipes, unmet) = pick_weekly(
+            candidates,
+            pinned,
+            &constraints,
+            &even_weighting(),
+            &mut rng,
+        );
+        (
+            recipes.into_iter().map(|recipe| recipe.name).collect(),
+            unmet.into_iter().map(|unmet| unmet.constraint).collect(),
+        )
+    }
+
+    #[test]
+    fn the_same_seed_picks_the_same_week() {
+        let candidates = week_of_candidates();
+        let mut reversed = candidates.clone();
+        reversed.reverse();
+        let query = WeeklyQuery::default();
+        let (first, _) = pick(candidates, vec![], &query, HashSet::new(), 42);
+        let (again, _) = pick(reversed, vec![], &query, HashSet::new(), 42);
+        assert_eq!(first.len(), 7);
+        assert_eq!(first, again);
+    }
+
+    #[test]
+    fn pinned_recipes_keep_their_day() {
+        let candidates = week_of_candidates();
+        let pinned = vec![candidates[5].clone(), candidates[2].clone()];
+        for seed in 0..20 {
+            let (names, _) = pick(
+                candidates.clone(),
+                pinned.clone(),
+                &WeeklyQuery::default(),
+                HashSet::new(),
+                seed,
+            );
+            assert_eq!(names[..2], ["Recipe 5", "Recipe 2"]);
+            assert_eq!(names.iter().collect::<HashSet<_>>().len(), 7);
+        }
+    }
+
+    #[test]
+    fn max_time_is_met_when_it_can_be() {
+        let query = WeeklyQuery {
+            max_time: Some(100),
+            ..WeeklyQuery::default()
+        };
+        for seed in 0..20 {
+            let candidates = week_of_candidates();
+            let quick: HashSet<String> = candidates
+                .iter()
+                .step_by(2)
+                .map(|recipe| recipe.name.clone())
+                .collect();
+            let (names, unmet) = pick(candidates, vec![], &query, HashSet::new(), seed);
+            assert!(names.iter().all(|name| quick.contains(name)), "{:?}", names);
+            assert!(unmet.is_empty());
+        }
+        let impossible = WeeklyQuery {
+            max_time: Some(30),
+            ..WeeklyQuery::default()
+        };
+        let (names, unmet) = pick(week_of_candidates(), vec![], &impossible, HashSet::new(), 1);
+        assert_eq!(names.len(), 7);
+        assert_eq!(unmet, ["max_time"]);
+    }
+
+    #[test]
+    fn recent_recipes_are_not_repeated_when_they_can_be_avoided() {
+        let candidates = week_of_candidates();
+        let recent: HashSet<Uuid> = candidates.iter().take(7).filter_map(|r| r.id).collect();
+        let query = WeeklyQuery::default();
+        for seed in 0..20 {
+            let (names, unmet) = pick(candidates.clone(), vec![], &query, recent.clone(), seed);
+            let repeated = candidates[..7].iter().filter(|r| names.contains(&r.name));
+            assert_eq!(repeated.count(), 0);
+            assert!(unmet.is_empty());
+        }
+        let (_, unmet) = pick(candidates[..8].to_vec(), vec![], &query, recent, 1);
+        assert_eq!(unmet, ["no_repeat_weeks"]);
+    }
+
+    #[test]
+    fn recipes_that_weigh_nothing_are_never_picked() {
+        let weights = [1.0, 0.0, 2.0, 0.0, 0.5];
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut order = weighted_order(&weights, 0, &mut rng);
+            order.sort_unstable();
+            assert_eq!(order, [0, 2, 4]);
+        }
+        let mut rng = StdRng::seed_from_u64(7);
+        assert_eq!(weighted_order(&weights, 3, &mut rng), [4]);
+    }
+
+    #[test]
+    fn minutes_in_the_usual_times() {
+        for (time, minutes) in &[
+            ("45", 45),
+            ("45 min", 45),
+            ("1h 30", 90),
+            ("1h 30m", 90),
+            ("1:15", 75),
+            ("1.5 hours", 90),
+            ("2 Hours", 120),
+        ] {
+            assert_eq!(recipe_minutes(time), Some(*minutes), "{}", time);
+        }
+    }
+
+    #[test]
+    fn ranges_go_by_the_upper_end() {
+        for (time, minutes) in &[
+            ("30-45 min", 45),
+            ("30 – 45 minutes", 45),
+            ("1-2 hours", 120),
+            ("1 to 2 hours", 120),
+            ("45 min - 1h", 60),
+            ("1:00-1:15", 75),
+        ] {
+            assert_eq!(recipe_minutes(time), Some(*minutes), "{}", time);
+        }
+    }
+
+    #[test]
+    fn unparseable_times_have_no_minutes() {
+        for time in &["a while", "", "overnight", "30-"] {
+            assert_eq!(recipe_minutes(time), None, "{}", time);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use crate::errors::{ApiError, ApiResult};
+use crate::models::PlanSettings;
+use crate::store::StorePool;
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use chrono_tz::Tz;
+
+// Time zones are the IANA names, eg. "Europe/Madrid"
+pub fn parse_time_zone(name: &str) -> ApiResult<Tz> {
+    name.parse()
+        .map_err(|_| ApiError::Validation(format!("Unknown time zone {}", name)))
+}
+
+// Dates in the urls are plain 2024-01-31
+pub fn parse_date(date: &str) -> ApiResult<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| ApiError::Validation(format!("{} isn't a YYYY-MM-DD date", date)))
+}
+
+// The date it is for the user at `now` (UTC). Anything stored before the time zone was checked
+// counts as UTC.
+pub fn local_today(settings: &PlanSettings, now: NaiveDateTime) -> NaiveDate {
+    let tz = parse_time_zone(&settings.time_zone).unwrap_or(Tz::UTC);
+    tz.from_utc_datetime(&now).date_naive()
+}
+
+// When `date` starts for the user, in UTC. Midnights a clock change skips count from the first
+// hour that exists.
+pub fn local_midnight(settings: &PlanSettings, date: NaiveDate) -> NaiveDateTime {
+    let tz = parse_time_zone(&settings.time_zone).unwrap_or(Tz::UTC);
+    let midnight = date.and_time(NaiveTime::MIN);
+    (0..24)
+        .find_map(|hour| {
+            tz.from_local_datetime(&(midnight + Duration::hours(hour)))
+                .earliest()
+        })
+        .map_or(midnight, |start| start.naive_utc())
+}
+
+// First day of the week `date` is in
+pub fn week_start(settings: &PlanSettings, date: NaiveDate) -> NaiveDate {
+    let days_in = (7 + date.weekday().num_days_from_monday()
+        - settings.week_start.num_days_from_monday())
+        % 7;
+    date - Duration::days(days_in as i64)
+}
+
+// Plan days before this one are done with
+pub fn expiry_cutoff(settings: &PlanSettings, now: NaiveDateTime) -> NaiveDate {
+    local_today(settings, now) - Duration::days(settings.retention_days as i64)
+}
+
+// Moves the expired days of every user's plan to their history, returns how many meals moved.
+// Run by the background task in main.
+pub async fn archive_expired_plans(store: &StorePool, now: NaiveDateTime) -> ApiResult<usize> {
+    let mut archived = 0;
+    for user in store.all_users().await? {
+        let u_id = match user.id {
+            Some(id) => id.to_string(),
+            None => continue,
+        };
+        let settings = store.plan_settings(&u_id).await?;
+        archived += store
+            .archive_plan(&u_id, expiry_cutoff(&settings, now), now)
+            .await?;
+    }
+    Ok(archived)
+}
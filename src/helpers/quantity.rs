@@ -0,0 +1,255 @@
+// Free text amounts, eg. "1 1/2 cups", "200g", "2 tbsp" or "a pinch", turned into a number and a
+// unit we can work with. Whatever we can't make sense of is left as None, the original text always
+// stays on the ingredient so nothing gets lost.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParsedAmount {
+    pub quantity: Option<f64>,
+    pub unit: Option<String>,
+}
+
+// The units we know and how people write them. The first column is what gets stored.
+const UNITS: &[(&str, &[&str])] = &[
+    (
+        "g",
+        &["g", "gr", "grs", "gram", "grams", "gramme", "grammes"],
+    ),
+    (
+        "kg",
+        &["kg", "kgs", "kilo", "kilos", "kilogram", "kilograms"],
+    ),
+    ("mg", &["mg", "milligram", "milligrams"]),
+    (
+        "ml",
+        &[
+            "ml",
+            "milliliter",
+            "milliliters",
+            "millilitre",
+            "millilitres",
+        ],
+    ),
+    (
+        "cl",
+        &[
+            "cl",
+            "centiliter",
+            "centiliters",
+            "centilitre",
+            "centilitres",
+        ],
+    ),
+    (
+        "dl",
+        &["dl", "deciliter", "deciliters", "decilitre", "decilitres"],
+    ),
+    ("l", &["l", "lt", "liter", "liters", "litre", "litres"]),
+    ("tsp", &["tsp", "tsps", "teaspoon", "teaspoons"]),
+    (
+        "tbsp",
+        &["tbsp", "tbsps", "tbs", "tbl", "tablespoon", "tablespoons"],
+    ),
+    ("cup", &["cup", "cups"]),
+    ("fl oz", &["fl oz", "floz", "fluid ounce", "fluid ounces"]),
+    ("oz", &["oz", "ounce", "ounces"]),
+    ("lb", &["lb", "lbs", "pound", "pounds"]),
+    ("pint", &["pint", "pints", "pt"]),
+    ("quart", &["quart", "quarts", "qt"]),
+    ("gallon", &["gallon", "gallons", "gal"]),
+    ("pinch", &["pinch", "pinches"]),
+    ("dash", &["dash", "dashes"]),
+    ("clove", &["clove", "cloves"]),
+    ("slice", &["slice", "slices"]),
+    ("can", &["can", "cans", "tin", "tins"]),
+];
+
+// "a pinch", "one onion", "half lemon"
+const NUMBER_WORDS: &[(&str, f64)] = &[("a", 1.0), ("an", 1.0), ("one", 1.0), ("half", 0.5)];
+
+pub fn parse_amount(amount: &str) -> ParsedAmount {
+    let text = amount.trim().to_lowercase();
+    let (quantity, rest) = match leading_number(&text) {
+        Some((quantity, rest)) => (Some(quantity), skip_range(rest)),
+        None => match number_word(&text) {
+            Some((quantity, rest)) => (Some(quantity), rest),
+            None => (None, text.as_str()),
+        },
+    };
+    let unit = leading_unit(rest);
+    // A unit on its own, like "pinch of salt", is one of it
+    let quantity = quantity.or_else(|| unit.map(|_| 1.0));
+
+    ParsedAmount {
+        quantity,
+        unit: unit.map(str::to_string),
+    }
+}
+
+fn vulgar_fraction(c: char) -> Option<f64> {
+    let fraction = match c {
+        '½' => 1.0 / 2.0,
+        '⅓' => 1.0 / 3.0,
+        '⅔' => 2.0 / 3.0,
+        '¼' => 1.0 / 4.0,
+        '¾' => 3.0 / 4.0,
+        '⅕' => 1.0 / 5.0,
+        '⅙' => 1.0 / 6.0,
+        '⅛' => 1.0 / 8.0,
+        '⅜' => 3.0 / 8.0,
+        '⅝' => 5.0 / 8.0,
+        '⅞' => 7.0 / 8.0,
+        _ => return None,
+    };
+    Some(fraction)
+}
+
+fn digits(text: &str) -> usize {
+    text.find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len())
+}
+
+// Plain numbers, decimals with a dot or a comma, fractions, mixed numbers and the unicode
+// fractions, eg. "2", "1.5", "1,5", "1/2", "1 1/2", "½" or "1½". Returns the text after it.
+fn leading_number(text: &str) -> Option<(f64, &str)> {
+    let int_len = digits(text);
+    if int_len == 0 {
+        let c = text.chars().next()?;
+        return vulgar_fraction(c).map(|fraction| (fraction, &text[c.len_utf8()..]));
+    }
+    let mut value: f64 = text[..int_len].parse().ok()?;
+    let mut rest = &text[int_len..];
+
+    if let Some(after) = rest.strip_prefix('.').or_else(|| rest.strip_prefix(',')) {
+        let decimals = digits(after);
+        if decimals > 0 {
+            value = format!("{}.{}", &text[..int_len], &after[..decimals])
+                .parse()
+                .ok()?;
+            return Some((value, &after[decimals..]));
+        }
+    }
+
+    if let Some(after) = rest.strip_prefix('/') {
+        let denominator_len = digits(after);
+        let denominator: f64 = after[..denominator_len].parse().unwrap_or(0.0);
+        if denominator > 0.0 {
+            return Some((value / denominator, &after[denominator_len..]));
+        }
+    }
+
+    if let Some(c) = rest.chars().next() {
+        if let Some(fraction) = vulgar_fraction(c) {
+            return Some((value + fraction, &rest[c.len_utf8()..]));
+        }
+    }
+
+    // The fraction of a mixed number like "1 1/2"
+    let trimmed = rest.trim_start();
+    if trimmed.len() < rest.len() {
+        if let Some((fraction, after)) = leading_number(trimmed) {
+            if fraction < 1.0 {
+                value += fraction;
+                rest = after;
+            }
+        }
+    }
+    Some((value, rest))
+}
+
+// Ranges like "2-3 cups" or "2 to 3 cups" keep the lower end
+fn skip_range(rest: &str) -> &str {
+    let trimmed = rest.trim_start();
+    let upper = trimmed
+        .strip_prefix('-')
+        .or_else(|| trimmed.strip_prefix('–'))
+        .or_else(|| trimmed.strip_prefix("to "));
+    match upper.and_then(|upper| leading_number(upper.trim_start())) {
+        Some((_, after)) => after,
+        None => rest,
+    }
+}
+
+fn number_word(text: &str) -> Option<(f64, &str)> {
+    let (word, rest) = text.split_once(' ').unwrap_or((text, ""));
+    NUMBER_WORDS
+        .iter()
+        .find(|(number, _)| *number == word)
+        .map(|(_, value)| (*value, rest))
+}
+
+// Looks at the first two words first so "fl oz" wins over "fl"
+fn leading_unit(text: &str) -> Option<&'static str> {
+    let words: Vec<&str> = text
+        .split_whitespace()
+        .take(2)
+        .map(|word| word.trim_end_matches(['.', ',']))
+        .collect();
+    (1..=words.len()).rev().find_map(|len| {
+        let candidate = words[..len].join(" ");
+        UNITS
+            .iter()
+            .find(|(_, aliases)| aliases.contains(&candidate.as_str()))
+            .map(|(unit, _)| *unit)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(quantity: Option<f64>, unit: Option<&str>) -> ParsedAmount {
+        ParsedAmount {
+            quantity,
+            unit: unit.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn the_usual_amounts() {
+        assert_eq!(parse_amount("1 1/2 cups"), parsed(Some(1.5), Some("cup")));
+        assert_eq!(parse_amount("200g"), parsed(Some(200.0), Some("g")));
+        assert_eq!(parse_amount("2 tbsp"), parsed(Some(2.0), Some("tbsp")));
+        assert_eq!(parse_amount("a pinch"), parsed(Some(1.0), Some("pinch")));
+        assert_eq!(
+            parse_amount("  3 Cloves "),
+            parsed(Some(3.0), Some("clove"))
+        );
+        assert_eq!(parse_amount("1 fl oz"), parsed(Some(1.0), Some("fl oz")));
+        assert_eq!(parse_amount("2 eggs"), parsed(Some(2.0), None));
+        assert_eq!(
+            parse_amount("pinch of salt"),
+            parsed(Some(1.0), Some("pinch"))
+        );
+    }
+
+    #[test]
+    fn fractions_and_mixed_numbers() {
+        assert_eq!(parse_amount("1/2 cup"), parsed(Some(0.5), Some("cup")));
+        assert_eq!(parse_amount("2 3/4 cups"), parsed(Some(2.75), Some("cup")));
+        assert_eq!(parse_amount("½ tsp"), parsed(Some(0.5), Some("tsp")));
+        assert_eq!(parse_amount("1½ l"), parsed(Some(1.5), Some("l")));
+        assert_eq!(parse_amount("half lemon"), parsed(Some(0.5), None));
+        // A zero denominator isn't a fraction, the number before it is all we get
+        assert_eq!(parse_amount("1/0 cup").quantity, Some(1.0));
+    }
+
+    #[test]
+    fn decimal_points_and_commas() {
+        assert_eq!(parse_amount("1.5 kg"), parsed(Some(1.5), Some("kg")));
+        assert_eq!(parse_amount("1,5 kg"), parsed(Some(1.5), Some("kg")));
+        assert_eq!(parse_amount("0,25l"), parsed(Some(0.25), Some("l")));
+    }
+
+    #[test]
+    fn ranges_keep_the_lower_end() {
+        assert_eq!(parse_amount("2-3 cups"), parsed(Some(2.0), Some("cup")));
+        assert_eq!(parse_amount("2 to 3 cups"), parsed(Some(2.0), Some("cup")));
+    }
+
+    #[test]
+    fn unparseable_text_has_nothing() {
+        assert_eq!(parse_amount(""), ParsedAmount::default());
+        assert_eq!(parse_amount("to taste"), ParsedAmount::default());
+        assert_eq!(parse_amount("some"), ParsedAmount::default());
+        assert_eq!(parse_amount("lots of love"), ParsedAmount::default());
+    }
+}
@@ -1,79 +1,566 @@
-use crate::models::{GraphPool, Ingredient, Recipe};
+use crate::errors::{ApiError, ApiResult};
+use crate::helpers::picker::recipe_minutes;
+use crate::helpers::quantity::parse_amount;
+use crate::models::{
+    CookEntry, CookableRecipe, CookedRecipe, Ingredient, MealSlot, PlannedMeal, Recipe,
+    RecipeQuery, RecipeSort, RecipeUpdate, Review,
+};
+use chrono::{NaiveDate, NaiveDateTime};
 use neo4rs::*;
+use std::convert::TryFrom;
 use uuid::Uuid;
 
-pub fn process_steps(steps_string: String) -> Option<Vec<String>> {
-    let split_string: Vec<_> = steps_string.lines().map(|s| s.to_string()).collect();
-    Option::from(split_string)
+// For rows and nodes that don't look like we expect, that's always bad data on our side.
+fn malformed(what: &str) -> ApiError {
+    ApiError::Database(format!("Malformed {} in the database", what))
 }
 
-pub fn format_recipes(row: Row) -> Recipe {
-    let node = row.get::<Node>("r").expect("Empty row");
-    let id = node.get::<String>("id").expect("No id found for node");
+// Returned next to `r` so the ingredients of every recipe come back in the same round trip
+// instead of asking for them recipe by recipe. The names are picked so they don't clash with the
+// `u` users get matched as.
+pub const RECIPE_INGREDIENTS: &str = "[(r)-[uses:USES]->(ing:Ingredient) | \
+    [ing.name, coalesce(ing.tipo, ''), coalesce(uses.amount, ''), \
+    coalesce(toString(uses.quantity), ''), coalesce(uses.unit, '')]] AS ingredients";
+
+// Builds a query for the recipes matched as `r`, with their ingredients.
+pub fn recipes_query(match_clause: &str) -> Query {
+    query(&format!(
+        "{} RETURN r, {}",
+        match_clause, RECIPE_INGREDIENTS
+    ))
+}
+
+enum FilterValue {
+    Text(String),
+    Number(f64),
+    List(Vec<String>),
+}
+
+// Condition dropping the recipes matched as `r` that use any of the ingredients in the param.
+pub fn excluded_clause(param: &str) -> String {
+    format!(
+        "NOT EXISTS {{ MATCH (r)-[:USES]->(excluded:Ingredient) WHERE excluded.name IN ${} }}",
+        param
+    )
+}
+
+// The WHERE conditions for the filters in a RecipeQuery. The values are kept around instead of
+// going through ParamSetters since they get bound to both the page and the count query.
+struct RecipeFilter {
+    clauses: Vec<String>,
+    params: Vec<(String, FilterValue)>,
+}
+
+impl RecipeFilter {
+    fn new(list: &RecipeQuery) -> Self {
+        let mut filter = RecipeFilter {
+            clauses: Vec::new(),
+            params: Vec::new(),
+        };
+        if let Some(meal_type) = &list.meal_type {
+            filter.push("meal_type", "=", FilterValue::Text(meal_type.clone()));
+        }
+        if let Some(tipo) = &list.tipo {
+            filter.push("tipo", "=", FilterValue::Text(tipo.clone()));
+        }
+        let ranges = [
+            (
+                "calories",
+                list.min_calories.map(f64::from),
+                list.max_calories.map(f64::from),
+            ),
+            (
+                "carbohydrates",
+                list.min_carbohydrates.map(f64::from),
+                list.max_carbohydrates.map(f64::from),
+            ),
+            (
+                "fat",
+                list.min_fat.map(f64::from),
+                list.max_fat.map(f64::from),
+            ),
+            (
+                "protein",
+                list.min_protein.map(f64::from),
+                list.max_protein.map(f64::from),
+            ),
+        ];
+        for (field, min, max) in ranges.iter() {
+            if let Some(min) = min {
+                filter.push(field, ">=", FilterValue::Number(*min));
+            }
+            if let Some(max) = max {
+                filter.push(field, "<=", FilterValue::Number(*max));
+            }
+        }
+        if !list.exclude.is_empty() {
+            let param = format!("exclude_{}", filter.params.len());
+            filter.clauses.push(excluded_clause(&param));
+            filter
+                .params
+                .push((param, FilterValue::List(list.exclude.clone())));
+        }
+        filter
+    }
+
+    // Params are named after the property and their position so a min and a max on the same
+    // property don't clash, eg. `r.calories >= $calories_2`.
+    fn push(&mut self, property: &str, operator: &str, value: FilterValue) {
+        let param = format!("{}_{}", property, self.params.len());
+        self.clauses
+            .push(format!("r.{} {} ${}", property, operator, param));
+        self.params.push((param, value));
+    }
+
+    fn where_clause(&self) -> String {
+        if self.clauses.is_empty() {
+            return String::new();
+        }
+        format!("WHERE {}", self.clauses.join(" AND "))
+    }
+
+    fn bind(&self, mut recipes_query: Query) -> Query {
+        for (param, value) in &self.params {
+            recipes_query = match value {
+                FilterValue::Text(text) => recipes_query.param(param.as_str(), text.clone()),
+                FilterValue::Number(number) => recipes_query.param(param.as_str(), *number),
+                FilterValue::List(list) => recipes_query.param(param.as_str(), list.clone()),
+            };
+        }
+        recipes_query
+    }
+}
+
+// `time` is free text so sorting by it goes by the minutes parsed out of it. Times that didn't
+// parse have no minutes and go last whichever way the list is sorted.
+fn order_by(sort: RecipeSort, direction: &str) -> String {
+    let property = match sort {
+        RecipeSort::Name => "r.name",
+        RecipeSort::Calories => "r.calories",
+        RecipeSort::Protein => "r.protein",
+        RecipeSort::Time => return format!("r.minutes IS NULL, r.minutes {}", direction),
+        RecipeSort::Created => "r.created",
+    };
+    format!("{} {}", property, direction)
+}
+
+// Builds the query for one page of the recipes matched as `r` plus a query counting all of them.
+// The filters go in their own WITH so they don't mix with whatever the MATCH has in its WHERE,
+// callers bind their own params on both queries.
+pub fn paged_recipe_queries(match_clause: &str, list: &RecipeQuery) -> (Query, Query) {
+    let filter = RecipeFilter::new(list);
+    let matched = format!("{} WITH DISTINCT r {}", match_clause, filter.where_clause());
+    let direction = if list.descending() { "DESC" } else { "ASC" };
+
+    let page_query = query(&format!(
+        "{} WITH r ORDER BY {}, r.id SKIP $offset LIMIT $limit RETURN r, {}",
+        matched,
+        order_by(list.sort(), direction),
+        RECIPE_INGREDIENTS
+    ))
+    .param("offset", list.offset() as i64)
+    .param("limit", list.limit() as i64);
+    let count_query = query(&format!("{} RETURN count(r) AS total", matched));
+
+    (filter.bind(page_query), filter.bind(count_query))
+}
+
+// Steps are a list which the fulltext index can't search, so they're also kept joined in
+// `steps_text`. Anything writing `steps` writes this too.
+pub fn steps_text(steps: &[String]) -> String {
+    steps.join("\n")
+}
+
+// Rows without an `ingredients` column leave `Recipe.ingredients` empty.
+pub fn format_recipes(row: Row) -> ApiResult<Recipe> {
+    let node = row
+        .get::<Node>("r")
+        .ok_or_else(|| malformed("recipe row"))?;
+    let id = node
+        .get::<String>("id")
+        .ok_or_else(|| malformed("recipe id"))?;
     let name = node
         .get::<String>("name")
         .unwrap_or_else(|| "No name found for node".to_string());
-    let steps = node
-        .get::<String>("steps")
-        .unwrap_or_else(|| "".to_string());
+    // Stored as a list in the order they were sent, see the 002 migration for older recipes.
+    let steps = node.get::<Vec<String>>("steps").unwrap_or_default();
     let public = node.get::<bool>("public");
     let tipo = node.get::<String>("tipo");
     let calories = node.get("calories").unwrap_or(0);
     let carbohydrates = node.get("carbohydrates").unwrap_or(0.0);
     let fat = node.get("fat").unwrap_or(0.0);
     let protein = node.get("protein").unwrap_or(0.0);
-    let servings = node.get::<String>("servings");
+    // 0 is what gets stored when the recipe didn't say, see `Recipe.servings`
+    let servings = node
+        .get::<i64>("servings")
+        .filter(|servings| *servings > 0)
+        .map(|servings| servings as u16);
+    let yield_unit = node.get::<String>("yield_unit");
     let meal_type = node.get::<String>("meal_type");
     let time = node.get::<String>("time");
-
-    let steps = process_steps(steps);
+    let created = node.get::<NaiveDateTime>("created");
+    // Only there once someone cooked the recipe, see `log_cooked`
+    let last_cooked = node.get::<NaiveDateTime>("last_cooked");
+    let times_cooked = node.get::<i64>("times_cooked").unwrap_or(0);
+    // Same for the ratings, see RATING_TOTALS
+    let average_rating = node.get::<f64>("average_rating");
+    let rating_count = node.get::<i64>("rating_count").unwrap_or(0);
+    let ingredients = row
+        .get::<Vec<Vec<String>>>("ingredients")
+        .map(format_ingredients)
+        .transpose()?;
 
     let recipe = Recipe {
-        id: Option::from(Uuid::parse_str(id.as_str()).expect("Couldn't parse uuid")),
+        id: Option::from(Uuid::parse_str(id.as_str()).map_err(|_| malformed("recipe id"))?),
         name,
         public,
-        steps,
+        steps: Option::from(steps),
         tipo,
         calories: Option::from(calories as u16),
         carbohydrates: Option::from(carbohydrates as f32),
         fat: Option::from(fat as f32),
         protein: Option::from(protein as f32),
         servings,
+        yield_unit,
         meal_type,
-        ingredients: None,
+        ingredients,
         time,
+        created,
+        last_cooked,
+        times_cooked: Option::from(times_cooked as u32),
+        average_rating: average_rating.map(|average| average as f32),
+        rating_count: Option::from(rating_count as u32),
     };
 
+    Ok(recipe)
+}
+
+// A `recipes_query` row that also returns the CHOSEN relationship the recipe is planned with as `c`
+pub fn format_planned_meal(row: Row) -> ApiResult<PlannedMeal> {
+    let chosen = row
+        .get::<Relation>("c")
+        .ok_or_else(|| malformed("plan entry"))?;
+    let id = chosen
+        .get::<String>("id")
+        .and_then(|id| Uuid::parse_str(&id).ok())
+        .ok_or_else(|| malformed("plan entry id"))?;
+    let date = chosen
+        .get::<NaiveDate>("date")
+        .ok_or_else(|| malformed("plan entry date"))?;
+    let slot = chosen
+        .get::<String>("slot")
+        .and_then(|slot| MealSlot::from_name(&slot))
+        .ok_or_else(|| malformed("plan entry slot"))?;
+    Ok(PlannedMeal {
+        id,
+        date,
+        slot,
+        recipe: format_recipes(row)?,
+    })
+}
+
+// A `recipes_query` row that also returns the COOKED relationship of the cook log entry as `c`
+pub fn format_cooked_recipe(row: Row) -> ApiResult<CookedRecipe> {
+    let cooked = row
+        .get::<Relation>("c")
+        .ok_or_else(|| malformed("cook log entry"))?;
+    let id = cooked
+        .get::<String>("id")
+        .and_then(|id| Uuid::parse_str(&id).ok())
+        .ok_or_else(|| malformed("cook log entry id"))?;
+    let at = cooked
+        .get::<NaiveDateTime>("at")
+        .ok_or_else(|| malformed("cook log entry date"))?;
+    Ok(CookedRecipe {
+        id,
+        cooked: CookEntry {
+            at,
+            servings: cooked
+                .get::<i64>("servings")
+                .map(|servings| servings as u16),
+            notes: cooked.get::<String>("notes"),
+            rating: cooked.get::<i64>("rating").map(|rating| rating as u8),
+        },
+        recipe: format_recipes(row)?,
+    })
+}
+
+// Goes after whatever changed the RATED relationships of the recipe matched as `r`. The average
+// ends up unset once the last rating is gone.
+pub const RATING_TOTALS: &str = "WITH DISTINCT r \
+    OPTIONAL MATCH (r)<-[rated:RATED]-(:User) \
+    WITH r, avg(rated.stars) AS average, count(rated) AS ratings \
+    SET r.average_rating = average, r.rating_count = ratings";
+
+// A row with the reviewer as `username` and the RATED relationship as `rated`
+pub fn format_review(row: Row) -> ApiResult<Review> {
+    let rated = row
+        .get::<Relation>("rated")
+        .ok_or_else(|| malformed("review"))?;
+    Ok(Review {
+        username: row
+            .get::<String>("username")
+            .ok_or_else(|| malformed("review"))?,
+        stars: rated
+            .get::<i64>("stars")
+            .map(|stars| stars as u8)
+            .ok_or_else(|| malformed("review stars"))?,
+        review: rated.get::<String>("review").unwrap_or_default(),
+        at: rated
+            .get::<NaiveDateTime>("at")
+            .ok_or_else(|| malformed("review date"))?,
+    })
+}
+
+// Each entry of the `ingredients` column is a [name, tipo, amount, quantity, unit] list, see
+// RECIPE_INGREDIENTS. Relationships the 005 migration couldn't parse have no quantity or unit.
+fn format_ingredients(rows: Vec<Vec<String>>) -> ApiResult<Vec<Ingredient>> {
+    rows.into_iter()
+        .map(|row| match <[String; 5]>::try_from(row) {
+            Ok([name, tipo, amount, quantity, unit]) => Ok(Ingredient {
+                name,
+                tipo: Option::from(tipo),
+                amount,
+                quantity: quantity.parse().ok(),
+                unit: Some(unit).filter(|unit| !unit.is_empty()),
+            }),
+            Err(_) => Err(malformed("ingredient")),
+        })
+        .collect()
+}
+
+// Ingredient names are stored lowercased, names coming from the user get the same treatment
+// before comparing. Blank ones are dropped.
+pub fn ingredient_names(ingredients: &[String]) -> Vec<String> {
+    let mut names: Vec<String> = ingredients
+        .iter()
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+pub fn uses_any(recipe: &Recipe, names: &[String]) -> bool {
     recipe
+        .ingredients
+        .iter()
+        .flatten()
+        .any(|i| names.contains(&i.name))
 }
 
-pub fn format_ingredients(row: Row) -> Ingredient {
-    let node = row.get::<Node>("i").expect("Empty ingredient node");
-    let name = node.get::<String>("name").expect("No ingredient name");
-    let tipo = node.get::<String>("tipo").unwrap_or_else(|| "".to_string());
-    let relation = row.get::<Relation>("u").expect("No relation");
-    let amount = relation.get::<String>("amount").expect("No amount");
+// How much of the recipe is covered by the names from `ingredient_names` and what's still missing.
+// Ingredients are counted by name so the same one listed twice only counts once.
+pub fn cookable_recipe(recipe: Recipe, on_hand: &[String]) -> CookableRecipe {
+    let mut names: Vec<&str> = recipe
+        .ingredients
+        .iter()
+        .flatten()
+        .map(|i| i.name.as_str())
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    let have = names
+        .iter()
+        .filter(|name| on_hand.iter().any(|n| n == *name))
+        .count();
+    let coverage = if names.is_empty() {
+        0.0
+    } else {
+        have as f32 / names.len() as f32
+    };
 
-    Ingredient {
-        name,
-        tipo: Option::from(tipo),
-        amount,
+    let mut missing: Vec<Ingredient> = Vec::new();
+    for ingredient in recipe.ingredients.iter().flatten() {
+        let at_hand = on_hand.contains(&ingredient.name);
+        if !at_hand && !missing.iter().any(|i| i.name == ingredient.name) {
+            missing.push(ingredient.clone());
+        }
+    }
+
+    CookableRecipe {
+        recipe,
+        coverage,
+        missing,
+    }
+}
+
+// Drops every USES relationship of the recipe and links it again to the given ingredients. The
+// Ingredient nodes themselves are shared between recipes so those are only ever merged. All the
+// ingredients go in a single UNWIND, neo4rs can't send maps as params so they are passed as one
+// list per field and zipped back together by index.
+pub async fn set_recipe_ingredients(
+    txn: &Txn,
+    r_id: &str,
+    ingredients: &[Ingredient],
+) -> ApiResult<()> {
+    txn.run(
+        query("MATCH (r:Recipe)-[u:USES]->(:Ingredient) WHERE r.id = $id DELETE u")
+            .param("id", r_id),
+    )
+    .await?;
+
+    if ingredients.is_empty() {
+        return Ok(());
+    }
+
+    let names: Vec<String> = ingredients.iter().map(|i| i.name.to_lowercase()).collect();
+    let tipos: Vec<String> = ingredients
+        .iter()
+        .map(|i| i.tipo.clone().unwrap_or_default().to_lowercase())
+        .collect();
+    let amounts: Vec<String> = ingredients.iter().map(|i| i.amount.clone()).collect();
+
+    let uses = query(&format!(
+        "MATCH (r:Recipe {{id: $id}}) \
+        UNWIND range(0, size($names) - 1) AS idx \
+        MERGE (i:Ingredient {{name: $names[idx], tipo: $tipos[idx]}}) \
+        CREATE (r)-[:USES {{amount: $amounts[idx], {}}}]->(i)",
+        parsed_amount()
+    ))
+    .param("id", r_id)
+    .param("names", names)
+    .param("tipos", tipos);
+    txn.run(with_parsed_amounts(uses, &amounts).param("amounts", amounts))
+        .await?;
+    Ok(())
+}
+
+// neo4rs can't send nulls, so a value that may be missing goes with a `<name>_set` flag and is
+// only stored when the flag is true. That way an empty string or a zero is stored like any other
+// value. `at` is the index for the lists zipped together, eg. "[idx]", and "" otherwise.
+pub fn optional(name: &str, at: &str) -> String {
+    format!("CASE WHEN ${0}_set{1} THEN ${0}{1} END", name, at)
+}
+
+// The parsed quantity and unit of the amount at `idx`, from the lists `with_parsed_amounts` sends
+pub fn parsed_amount() -> String {
+    format!(
+        "quantity: {}, unit: {}",
+        optional("quantities", "[idx]"),
+        optional("units", "[idx]")
+    )
+}
+
+pub fn with_parsed_amounts(q: Query, amounts: &[String]) -> Query {
+    let parsed: Vec<_> = amounts.iter().map(|amount| parse_amount(amount)).collect();
+    let quantities: Vec<f64> = parsed.iter().map(|p| p.quantity.unwrap_or(0.0)).collect();
+    let quantities_set: Vec<bool> = parsed.iter().map(|p| p.quantity.is_some()).collect();
+    let units: Vec<String> = parsed
+        .iter()
+        .map(|p| p.unit.clone().unwrap_or_default())
+        .collect();
+    let units_set: Vec<bool> = parsed.iter().map(|p| p.unit.is_some()).collect();
+    q.param("quantities", quantities)
+        .param("quantities_set", quantities_set)
+        .param("units", units)
+        .param("units_set", units_set)
+}
+
+// The minutes parsed out of the `time` of a recipe, kept in `minutes` for sorting
+pub fn with_minutes(q: Query, time: Option<&str>) -> Query {
+    let minutes = time.and_then(recipe_minutes).map(i64::from);
+    q.param("minutes", minutes.unwrap_or(0))
+        .param("minutes_set", minutes.is_some())
+}
+
+// Commits when everything went through, otherwise the transaction is rolled back so a failed
+// write never leaves half a recipe behind.
+pub async fn finish_txn<T>(txn: Txn, result: ApiResult<T>) -> ApiResult<T> {
+    match result {
+        Ok(value) => {
+            txn.commit().await?;
+            Ok(value)
+        }
+        Err(e) => {
+            if let Err(rollback_error) = txn.rollback().await {
+                eprintln!("Couldn't roll back the transaction: {:?}", rollback_error);
+            }
+            Err(e)
+        }
     }
 }
 
-pub async fn get_ingredients_from_db(graph: GraphPool, recipe: &mut Recipe) {
-    let mut response = graph
-        .execute(
-            query("MATCH (r:Recipe)-[u:USES]->(i:Ingredient) WHERE r.id = $rid RETURN i, u")
-                .param("rid", recipe.id.unwrap().to_string()),
-        )
-        .await
-        .expect("Couldn't query the ingredients");
+type ParamSetter<'a> = Box<dyn FnOnce(Query) -> Query + Send + 'a>;
+
+// Only the fields present in the update end up in the SET clause, everything else is left as is.
+// neo4rs doesn't export BoltType so we can't keep the values around in a list, instead every
+// field present adds a closure that binds its own param once the query string is known.
+pub async fn update_recipe_in_db(txn: &Txn, r_id: &str, update: &RecipeUpdate) -> ApiResult<()> {
+    let mut setters: Vec<(&str, ParamSetter)> = Vec::new();
 
-    let mut ingredients_vector = Vec::new();
+    if let Some(name) = update.name.clone() {
+        setters.push(("name", Box::new(|q| q.param("name", name))));
+    }
+    if let Some(public) = update.public {
+        setters.push(("public", Box::new(move |q| q.param("public", public))));
+    }
+    if let Some(steps) = update.steps.clone() {
+        let text = steps_text(&steps);
+        setters.push(("steps", Box::new(|q| q.param("steps", steps))));
+        setters.push(("steps_text", Box::new(|q| q.param("steps_text", text))));
+    }
+    if let Some(tipo) = update.tipo.clone() {
+        setters.push(("tipo", Box::new(|q| q.param("tipo", tipo))));
+    }
+    if let Some(calories) = update.calories {
+        setters.push(("calories", Box::new(move |q| q.param("calories", calories))));
+    }
+    if let Some(carbs) = update.carbohydrates {
+        setters.push((
+            "carbohydrates",
+            Box::new(move |q| q.param("carbohydrates", carbs)),
+        ));
+    }
+    if let Some(fat) = update.fat {
+        setters.push(("fat", Box::new(move |q| q.param("fat", fat))));
+    }
+    if let Some(protein) = update.protein {
+        setters.push(("protein", Box::new(move |q| q.param("protein", protein))));
+    }
+    if let Some(servings) = update.servings {
+        setters.push(("servings", Box::new(move |q| q.param("servings", servings))));
+    }
+    if let Some(yield_unit) = update.yield_unit.clone() {
+        setters.push((
+            "yield_unit",
+            Box::new(|q| q.param("yield_unit", yield_unit)),
+        ));
+    }
+    if let Some(meal_type) = update.meal_type.clone() {
+        setters.push(("meal_type", Box::new(|q| q.param("meal_type", meal_type))));
+    }
+    if let Some(time) = update.time.clone() {
+        let minutes = time.clone();
+        setters.push(("time", Box::new(|q| q.param("time", time))));
+        setters.push((
+            "minutes",
+            Box::new(move |q| with_minutes(q, Some(&minutes))),
+        ));
+    }
+
+    if !setters.is_empty() {
+        let set_clauses: Vec<_> = setters
+            .iter()
+            .map(|(key, _)| match *key {
+                "minutes" => format!("r.minutes = {}", optional("minutes", "")),
+                key => format!("r.{key} = ${key}", key = key),
+            })
+            .collect();
+        let query_string = format!(
+            "MATCH (r:Recipe) WHERE r.id = $id SET {}",
+            set_clauses.join(", ")
+        );
+        let mut update_query = query(query_string.as_str()).param("id", r_id);
+        for (_, setter) in setters {
+            update_query = setter(update_query);
+        }
+        txn.run(update_query).await?;
+    }
 
-    while let Ok(Some(row)) = response.next().await {
-        ingredients_vector.push(format_ingredients(row))
+    if let Some(ingredients) = &update.ingredients {
+        set_recipe_ingredients(txn, r_id, ingredients).await?;
     }
-    recipe.ingredients = Option::from(ingredients_vector)
+    Ok(())
 }
@@ -0,0 +1,192 @@
+use crate::models::{Recipe, Snippet};
+use itertools::Itertools;
+
+pub const DEFAULT_SEARCH_LIMIT: usize = 20;
+
+// Words kept around the first match of a snippet, the rest of the text gets cut with "…".
+const SNIPPET_WORDS_BEFORE: usize = 6;
+const SNIPPET_WORDS: usize = 20;
+const MAX_SNIPPETS: usize = 3;
+
+// The words of the search, lowercased and without repeats. Anything that isn't a letter or a digit
+// splits words, so nothing of the lucene syntax makes it into the fulltext query.
+pub fn search_terms(q: &str) -> Vec<String> {
+    q.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_lowercase)
+        .unique()
+        .collect()
+}
+
+// Query for the fulltext indexes. Every term matches as a whole word or as the start of one, whole
+// words end up scoring higher since they match twice.
+pub fn fulltext_query(terms: &[String]) -> String {
+    terms
+        .iter()
+        .map(|term| format!("{term} {term}*", term = term))
+        .join(" ")
+}
+
+// Byte ranges of the words in the text, split the same way as `search_terms`.
+fn words(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                spans.push((s, i));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        spans.push((s, text.len()));
+    }
+    spans
+}
+
+fn word_score(word: &str, terms: &[String]) -> f32 {
+    let word = word.to_lowercase();
+    if terms.contains(&word) {
+        2.0
+    } else if terms.iter().any(|term| word.starts_with(term.as_str())) {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+// The searched fields with what a matching word in each one is worth, a hit in the name counts
+// the most. Ingredients are searched by name only.
+fn searched_fields(recipe: &Recipe) -> Vec<(&'static str, f32, Vec<String>)> {
+    let ingredients = recipe
+        .ingredients
+        .iter()
+        .flatten()
+        .map(|i| i.name.as_str())
+        .join(", ");
+    vec![
+        ("name", 4.0, vec![recipe.name.clone()]),
+        ("tipo", 2.0, recipe.tipo.iter().cloned().collect()),
+        ("ingredients", 2.0, vec![ingredients]),
+        ("steps", 1.0, recipe.steps.clone().unwrap_or_default()),
+    ]
+}
+
+// Relevance of a recipe for the memory store, 0 when nothing matches. The neo4j store uses the
+// fulltext score instead so the numbers aren't comparable between the two.
+pub fn recipe_score(recipe: &Recipe, terms: &[String]) -> f32 {
+    searched_fields(recipe)
+        .iter()
+        .flat_map(|(_, weight, texts)| {
+            texts.iter().flat_map(move |text| {
+                words(text)
+                    .into_iter()
+                    .map(move |(s, e)| weight * word_score(&text[s..e], terms))
+            })
+        })
+        .sum()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// The part of the text around its first match with every matching word wrapped in <mark>. The
+// text itself is escaped so the frontend can drop the snippet straight into the page.
+fn highlight(text: &str, terms: &[String]) -> Option<String> {
+    let spans = words(text);
+    let is_match = |&(s, e): &(usize, usize)| word_score(&text[s..e], terms) > 0.0;
+    let first = spans.iter().position(is_match)?;
+    let from = first.saturating_sub(SNIPPET_WORDS_BEFORE);
+    let to = (from + SNIPPET_WORDS).min(spans.len());
+    let start = if from == 0 { 0 } else { spans[from].0 };
+    let end = if to == spans.len() {
+        text.len()
+    } else {
+        spans[to - 1].1
+    };
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    let mut position = start;
+    for &(s, e) in spans[from..to].iter().filter(|span| is_match(span)) {
+        snippet.push_str(&escape_html(&text[position..s]));
+        snippet.push_str("<mark>");
+        snippet.push_str(&escape_html(&text[s..e]));
+        snippet.push_str("</mark>");
+        position = e;
+    }
+    snippet.push_str(&escape_html(&text[position..end]));
+    if end < text.len() {
+        snippet.push('…');
+    }
+    Some(snippet)
+}
+
+// One snippet per field that matched, from the first text in it that did.
+pub fn recipe_snippets(recipe: &Recipe, terms: &[String]) -> Vec<Snippet> {
+    searched_fields(recipe)
+        .into_iter()
+        .filter_map(|(field, _, texts)| {
+            texts
+                .iter()
+                .find_map(|text| highlight(text, terms))
+                .map(|text| Snippet {
+                    field: field.to_string(),
+                    text,
+                })
+        })
+        .take(MAX_SNIPPETS)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recipe(name: &str, steps: &[&str]) -> Recipe {
+        Recipe {
+            name: name.to_string(),
+            steps: Some(steps.iter().map(|step| step.to_string()).collect()),
+            ..Recipe::default()
+        }
+    }
+
+    #[test]
+    fn whole_words_and_names_score_higher() {
+        let terms = search_terms("Tomato, tomato!");
+        assert_eq!(terms, ["tomato"]);
+        let named = recipe("Tomato soup", &["Blend it"]);
+        let in_steps = recipe("Soup", &["Add the tomato"]);
+        let prefix = recipe("Soup", &["Add the tomatoes"]);
+        let nothing = recipe("Soup", &["Blend it"]);
+        assert!(recipe_score(&named, &terms) > recipe_score(&in_steps, &terms));
+        assert!(recipe_score(&in_steps, &terms) > recipe_score(&prefix, &terms));
+        assert!(recipe_score(&prefix, &terms) > 0.0);
+        assert_eq!(recipe_score(&nothing, &terms), 0.0);
+    }
+
+    #[test]
+    fn long_texts_are_cut_around_the_first_match() {
+        let step = "one two three four five six seven eight nine ten eleven twelve thirteen \
+            fourteen fifteen sixteen seventeen eighteen nineteen twenty tomato and the rest";
+        let snippets = recipe_snippets(
+            &recipe("Soup", &["Blend it", step]),
+            &search_terms("tomato"),
+        );
+        assert_eq!(snippets.len(), 1);
+        assert_eq!(snippets[0].field, "steps");
+        assert_eq!(
+            snippets[0].text,
+            "…fifteen sixteen seventeen eighteen nineteen twenty <mark>tomato</mark> and the rest"
+        );
+    }
+}
@@ -0,0 +1,419 @@
+use crate::errors::{ApiError, ApiResult};
+use crate::helpers::units::{convert_quantity, format_amount, round_quantity};
+use crate::models::{
+    PlannedMeal, Recipe, SavedShoppingList, ShoppingAisle, ShoppingItem, ShoppingList,
+    ShoppingListItem,
+};
+use chrono::{NaiveDateTime, Utc};
+use neo4rs::{Node, Row};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
+use uuid::Uuid;
+
+// Where ingredients without a tipo end up, always last
+const OTHER_AISLE: &str = "other";
+
+// What one ingredient adds up to so far. Quantities get added to the first unit of the same kind
+// that showed up, so cups and tablespoons end up in cups. Amounts that didn't parse are kept as
+// they were written.
+#[derive(Default)]
+struct Tally {
+    tipo: String,
+    quantities: Vec<(Option<String>, f64)>,
+    unparsed: Vec<String>,
+    recipes: Vec<String>,
+}
+
+impl Tally {
+    fn add(&mut self, quantity: f64, unit: Option<&str>) {
+        for (total_unit, total) in self.quantities.iter_mut() {
+            let converted = match (total_unit.as_deref(), unit) {
+                (to, from) if to == from => Some(quantity),
+                (Some(to), Some(from)) => convert_quantity(quantity, from, to),
+                _ => None,
+            };
+            if let Some(converted) = converted {
+                *total += converted;
+                return;
+            }
+        }
+        self.quantities.push((unit.map(str::to_string), quantity));
+    }
+
+    fn amounts(&self) -> Vec<String> {
+        self.quantities
+            .iter()
+            .map(|(unit, total)| {
+                let unit = unit.as_deref();
+                format_amount(round_quantity(*total, unit), unit)
+            })
+            .chain(self.unparsed.iter().cloned())
+            .collect()
+    }
+}
+
+// Walks the ingredients of every recipe, the same recipe chosen twice counts twice.
+pub fn shopping_list(recipes: &[Recipe]) -> ShoppingList {
+    let mut tallies: BTreeMap<String, Tally> = BTreeMap::new();
+    for recipe in recipes {
+        for ingredient in recipe.ingredients.iter().flatten() {
+            let tally = tallies.entry(ingredient.name.clone()).or_default();
+            if tally.tipo.is_empty() {
+                tally.tipo = ingredient.tipo.clone().unwrap_or_default();
+            }
+            match ingredient.quantity {
+                Some(quantity) => tally.add(quantity, ingredient.unit.as_deref()),
+                None => {
+                    let amount = ingredient.amount.trim();
+                    if !amount.is_empty() && !tally.unparsed.iter().any(|a| a == amount) {
+                        tally.unparsed.push(amount.to_string());
+                    }
+                }
+            }
+            if !tally.recipes.contains(&recipe.name) {
+                tally.recipes.push(recipe.name.clone());
+            }
+        }
+    }
+
+    let mut by_tipo: BTreeMap<String, Vec<ShoppingItem>> = BTreeMap::new();
+    for (name, tally) in tallies {
+        let tipo = match tally.tipo.as_str() {
+            "" => OTHER_AISLE.to_string(),
+            tipo => tipo.to_string(),
+        };
+        by_tipo.entry(tipo).or_default().push(ShoppingItem {
+            name,
+            amounts: tally.amounts(),
+            recipes: tally.recipes,
+        });
+    }
+    let mut aisles: Vec<ShoppingAisle> = by_tipo
+        .into_iter()
+        .map(|(tipo, items)| ShoppingAisle { tipo, items })
+        .collect();
+    aisles.sort_by_key(|aisle| aisle.tipo == OTHER_AISLE);
+
+    let markdown = render_markdown(&aisles);
+    ShoppingList { aisles, markdown }
+}
+
+// eg.
+// ## Verdura
+// - [ ] tomato: 5, 200 g (Tomato soup, Pasta)
+fn render_markdown(aisles: &[ShoppingAisle]) -> String {
+    let mut markdown = String::from("# Shopping list\n");
+    if aisles.is_empty() {
+        markdown.push_str("\nNothing to buy\n");
+    }
+    for aisle in aisles {
+        markdown.push_str(&format!("\n## {}\n\n", capitalize(&aisle.tipo)));
+        for item in &aisle.items {
+            markdown.push_str(&format!("- [ ] {}", item.name));
+            if !item.amounts.is_empty() {
+                markdown.push_str(&format!(": {}", item.amounts.join(", ")));
+            }
+            markdown.push_str(&format!(" ({})\n", item.recipes.join(", ")));
+        }
+    }
+    markdown
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+// Tells whether the meal plan changed since a list was made from it. Adding, removing, moving or
+// archiving a meal all change it, None is an empty plan. The hasher isn't guaranteed to stay the
+// same across rust versions, the worst a change does is flag every list as outdated once.
+pub fn plan_fingerprint(meals: &[PlannedMeal]) -> Option<String> {
+    if meals.is_empty() {
+        return None;
+    }
+    let mut entries: Vec<_> = meals
+        .iter()
+        .map(|meal| (meal.id, meal.date, meal.slot.as_str()))
+        .collect();
+    entries.sort();
+    let mut hasher = DefaultHasher::new();
+    entries.hash(&mut hasher);
+    Some(format!("{:016x}", hasher.finish()))
+}
+
+// A saved list from the chosen recipes, or the old one brought up to date when there is one. The
+// manual items stay as they are and generated items that are still needed keep their id and
+// whether they were checked. Ingredients a manual item already covers aren't added again.
+pub fn generate_saved_list(
+    meals: &[PlannedMeal],
+    previous: Option<SavedShoppingList>,
+) -> SavedShoppingList {
+    let (id, created, old_items) = match previous {
+        Some(list) => (list.id, list.created, list.items),
+        None => (Uuid::new_v4(), Utc::now().naive_utc(), Vec::new()),
+    };
+    let (mut items, generated): (Vec<_>, Vec<_>) = old_items.into_iter().partition(|i| i.manual);
+
+    let recipes: Vec<_> = meals.iter().map(|meal| meal.recipe.clone()).collect();
+    for aisle in shopping_list(&recipes).aisles {
+        for item in aisle.items {
+            if items.iter().any(|i| i.manual && i.name == item.name) {
+                continue;
+            }
+            let old = generated.iter().find(|i| i.name == item.name);
+            items.push(ShoppingListItem {
+                id: old.map(|i| i.id).unwrap_or_else(Uuid::new_v4),
+                name: item.name,
+                tipo: aisle.tipo.clone(),
+                amount: item.amounts.join(", "),
+                checked: old.map(|i| i.checked).unwrap_or(false),
+                manual: false,
+            });
+        }
+    }
+
+    SavedShoppingList {
+        id,
+        created,
+        plan: plan_fingerprint(meals),
+        items,
+    }
+}
+
+// The `items` column is a [id, name, tipo, amount, checked, manual, position] list per item, see
+// the neo4j store's shopping_list.
+pub fn format_shopping_list(row: Row) -> ApiResult<SavedShoppingList> {
+    let malformed = || ApiError::Database("Malformed shopping list in the database".to_string());
+    let node = row.get::<Node>("l").ok_or_else(malformed)?;
+    let id = node
+        .get::<String>("id")
+        .and_then(|id| Uuid::parse_str(&id).ok())
+        .ok_or_else(malformed)?;
+    let created = node.get::<NaiveDateTime>("created").ok_or_else(malformed)?;
+    let plan = node.get::<String>("plan");
+
+    let mut rows = Vec::new();
+    for item in row.get::<Vec<Vec<String>>>("items").unwrap_or_default() {
+        let [id, name, tipo, amount, checked, manual, position] =
+            <[String; 7]>::try_from(item).map_err(|_| malformed())?;
+        let item = ShoppingListItem {
+            id: Uuid::parse_str(&id).map_err(|_| malformed())?,
+            name,
+            tipo,
+            amount,
+            checked: checked == "true",
+            manual: manual == "true",
+        };
+        rows.push((position.parse::<i64>().unwrap_or(0), item));
+    }
+    rows.sort_by_key(|(position, _)| *position);
+
+    Ok(SavedShoppingList {
+        id,
+        created,
+        plan,
+        items: rows.into_iter().map(|(_, item)| item).collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Ingredient, MealSlot};
+    use chrono::NaiveDate;
+
+    fn ingredient(name: &str, tipo: &str, amount: &str, quantity: Option<f64>) -> Ingredient {
+        let unit = amount.split_once(' ').map(|(_, unit)| unit.to_string());
+        Ingredient {
+            name: name.to_string(),
+            tipo: Some(tipo.to_string()).filter(|tipo| !tipo.is_empty()),
+            amount: amount.to_string(),
+            quantity,
+            unit: quantity.and(unit),
+        }
+    }
+
+    fn recipe(name: &str, ingredients: Vec<Ingredient>) -> Recipe {
+        Recipe {
+            name: name.to_string(),
+            ingredients: Some(ingredients),
+            ..Recipe::default()
+        }
+    }
+
+    fn soup_and_pasta() -> Vec<Recipe> {
+        vec![
+            recipe(
+                "Soup",
+                vec![
+                    ingredient("tomato", "verdura", "2", Some(2.0)),
+                    ingredient("salt", "", "to taste", None),
+                ],
+            ),
+            recipe(
+                "Pasta",
+                vec![
+                    ingredient("tomato", "verdura", "3", Some(3.0)),
+                    ingredient("pasta", "pasta", "200 g", Some(200.0)),
+                ],
+            ),
+        ]
+    }
+
+    fn items(list: &ShoppingList) -> Vec<(String, Vec<String>, Vec<String>)> {
+        list.aisles
+            .iter()
+            .flat_map(|aisle| aisle.items.iter())
+            .map(|item| {
+                (
+                    item.name.clone(),
+                    item.amounts.clone(),
+                    item.recipes.clone(),
+                )
+            })
+            .collect()
+    }
+
+    fn strings(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|text| text.to_string()).collect()
+    }
+
+    #[test]
+    fn the_same_ingredient_adds_up_across_recipes() {
+        let list = shopping_list(&soup_and_pasta());
+        assert_eq!(
+            items(&list),
+            vec![
+                (
+                    "pasta".to_string(),
+                    strings(&["200 g"]),
+                    strings(&["Pasta"])
+                ),
+                (
+                    "tomato".to_string(),
+                    strings(&["5"]),
+                    strings(&["Soup", "Pasta"])
+                ),
+                (
+                    "salt".to_string(),
+                    strings(&["to taste"]),
+                    strings(&["Soup"])
+                ),
+            ]
+        );
+        // The same recipe twice needs twice as much
+        let twice = vec![soup_and_pasta().remove(0), soup_and_pasta().remove(0)];
+        assert_eq!(items(&shopping_list(&twice))[0].1, strings(&["4"]));
+    }
+
+    // Units of the same kind go into the first one, the rest stay next to each other
+    #[test]
+    fn units_are_merged_when_they_convert() {
+        let recipes = vec![
+            recipe(
+                "Cake",
+                vec![
+                    ingredient("flour", "", "1 cup", Some(1.0)),
+                    ingredient("butter", "", "100 g", Some(100.0)),
+                ],
+            ),
+            recipe(
+                "Crepes",
+                vec![
+                    ingredient("flour", "", "2 tbsp", Some(2.0)),
+                    ingredient("butter", "", "1 tbsp", Some(1.0)),
+                    ingredient("butter", "", "a knob", None),
+                ],
+            ),
+        ];
+        assert_eq!(
+            items(&shopping_list(&recipes)),
+            vec![
+                (
+                    "butter".to_string(),
+                    strings(&["100 g", "1 tbsp", "a knob"]),
+                    strings(&["Cake", "Crepes"])
+                ),
+                (
+                    "flour".to_string(),
+                    strings(&["1 1/8 cup"]),
+                    strings(&["Cake", "Crepes"])
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn markdown_by_aisle_with_other_last() {
+        assert_eq!(
+            shopping_list(&soup_and_pasta()).markdown,
+            "# Shopping list\n\
+            \n## Pasta\n\n- [ ] pasta: 200 g (Pasta)\n\
+            \n## Verdura\n\n- [ ] tomato: 5 (Soup, Pasta)\n\
+            \n## Other\n\n- [ ] salt: to taste (Soup)\n"
+        );
+        assert_eq!(
+            shopping_list(&[]).markdown,
+            "# Shopping list\n\nNothing to buy\n"
+        );
+    }
+
+    fn meals(recipes: Vec<Recipe>) -> Vec<PlannedMeal> {
+        let monday = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+        recipes
+            .into_iter()
+            .map(|recipe| PlannedMeal {
+                id: Uuid::new_v4(),
+                date: monday,
+                slot: MealSlot::Dinner,
+                recipe,
+            })
+            .collect()
+    }
+
+    // Regenerating keeps the manual items and what was checked, and drops what's not needed
+    #[test]
+    fn regenerating_keeps_manual_and_checked_items() {
+        let plan = meals(soup_and_pasta());
+        let mut list = generate_saved_list(&plan, None);
+        assert_eq!(list.plan, plan_fingerprint(&plan));
+        let tomato = list.items.iter_mut().find(|i| i.name == "tomato").unwrap();
+        tomato.checked = true;
+        let tomato_id = tomato.id;
+        let salt = list.items.iter_mut().find(|i| i.name == "salt").unwrap();
+        salt.amount = "a lot".to_string();
+        salt.manual = true;
+
+        let soup_only = meals(vec![soup_and_pasta().remove(0)]);
+        let list = generate_saved_list(&soup_only, Some(list));
+        let kept: Vec<_> = list
+            .items
+            .iter()
+            .map(|i| (i.name.as_str(), i.amount.as_str(), i.checked, i.manual))
+            .collect();
+        assert_eq!(
+            kept,
+            vec![("salt", "a lot", false, true), ("tomato", "2", true, false)]
+        );
+        assert_eq!(list.items[1].id, tomato_id);
+        assert_eq!(list.plan, plan_fingerprint(&soup_only));
+    }
+
+    #[test]
+    fn any_change_to_the_plan_changes_the_fingerprint() {
+        assert_eq!(plan_fingerprint(&[]), None);
+        let plan = meals(soup_and_pasta());
+        let mut moved = plan.clone();
+        moved[0].slot = MealSlot::Lunch;
+        let mut reordered = plan.clone();
+        reordered.reverse();
+        assert_eq!(plan_fingerprint(&reordered), plan_fingerprint(&plan));
+        assert_ne!(plan_fingerprint(&moved), plan_fingerprint(&plan));
+        assert_ne!(plan_fingerprint(&plan[..1]), plan_fingerprint(&plan));
+    }
+}
@@ -0,0 +1,488 @@
+use crate::models::{Ingredient, Recipe, UnitSystem};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Dimension {
+    Mass,
+    Volume,
+}
+
+// Every unit we can convert with how many grams or millilitres one of it is, and the system it
+// belongs to. Spoons are used everywhere so they're left alone whatever system is asked for.
+const CONVERSIONS: &[(&str, Dimension, f64, Option<UnitSystem>)] = &[
+    ("mg", Dimension::Mass, 0.001, Some(UnitSystem::Metric)),
+    ("g", Dimension::Mass, 1.0, Some(UnitSystem::Metric)),
+    ("kg", Dimension::Mass, 1000.0, Some(UnitSystem::Metric)),
+    (
+        "oz",
+        Dimension::Mass,
+        28.349523125,
+        Some(UnitSystem::Imperial),
+    ),
+    ("lb", Dimension::Mass, 453.59237, Some(UnitSystem::Imperial)),
+    ("ml", Dimension::Volume, 1.0, Some(UnitSystem::Metric)),
+    ("cl", Dimension::Volume, 10.0, Some(UnitSystem::Metric)),
+    ("dl", Dimension::Volume, 100.0, Some(UnitSystem::Metric)),
+    ("l", Dimension::Volume, 1000.0, Some(UnitSystem::Metric)),
+    ("tsp", Dimension::Volume, 4.92892159375, None),
+    ("tbsp", Dimension::Volume, 14.78676478125, None),
+    (
+        "fl oz",
+        Dimension::Volume,
+        29.5735295625,
+        Some(UnitSystem::Imperial),
+    ),
+    (
+        "cup",
+        Dimension::Volume,
+        236.5882365,
+        Some(UnitSystem::Imperial),
+    ),
+    (
+        "pint",
+        Dimension::Volume,
+        473.176473,
+        Some(UnitSystem::Imperial),
+    ),
+    (
+        "quart",
+        Dimension::Volume,
+        946.352946,
+        Some(UnitSystem::Imperial),
+    ),
+    (
+        "gallon",
+        Dimension::Volume,
+        3785.411784,
+        Some(UnitSystem::Imperial),
+    ),
+];
+
+// Grams per millilitre for the usual suspects, used when the Ingredient node doesn't have its own
+// `density`.
+const COMMON_DENSITIES: &[(&str, f64)] = &[
+    ("water", 1.0),
+    ("milk", 1.03),
+    ("cream", 1.01),
+    ("yogurt", 1.03),
+    ("butter", 0.96),
+    ("oil", 0.92),
+    ("olive oil", 0.91),
+    ("honey", 1.42),
+    ("flour", 0.53),
+    ("sugar", 0.85),
+    ("brown sugar", 0.93),
+    ("powdered sugar", 0.56),
+    ("salt", 1.2),
+    ("rice", 0.85),
+    ("oats", 0.41),
+    ("cocoa", 0.42),
+];
+
+pub fn common_density(ingredient: &str) -> Option<f64> {
+    COMMON_DENSITIES
+        .iter()
+        .find(|(name, _)| *name == ingredient)
+        .map(|(_, density)| *density)
+}
+
+// Rewrites the parsed quantity, the unit and the amount text of the ingredient in the given
+// system. Volumes become masses in metric and masses become volumes in imperial when we know the
+// ingredient's density, since that's how each side measures. Anything we can't convert, or that
+// is already in the right system, is left as it was.
+pub fn convert_ingredient(ingredient: &mut Ingredient, system: UnitSystem, density: Option<f64>) {
+    let (quantity, unit) = match (ingredient.quantity, ingredient.unit.as_deref()) {
+        (Some(quantity), Some(unit)) => (quantity, unit),
+        _ => return,
+    };
+    if let Some((quantity, unit)) = convert(quantity, unit, system, density) {
+        ingredient.unit = Some(unit.to_string());
+        set_quantity(ingredient, quantity);
+    }
+}
+
+// Scales the amounts and the nutrition of the recipe to the servings asked for. Returns false
+// when the recipe doesn't say how many servings it makes, there's nothing to scale from then.
+pub fn scale_recipe(recipe: &mut Recipe, servings: u16) -> bool {
+    let factor = match recipe.servings {
+        Some(current) if current > 0 => f64::from(servings) / f64::from(current),
+        _ => return false,
+    };
+    for ingredient in recipe.ingredients.iter_mut().flatten() {
+        if let Some(quantity) = ingredient.quantity {
+            set_quantity(ingredient, quantity * factor);
+        }
+    }
+    recipe.calories = recipe
+        .calories
+        .map(|calories| (f64::from(calories) * factor).round() as u16);
+    for nutrient in [
+        &mut recipe.carbohydrates,
+        &mut recipe.fat,
+        &mut recipe.protein,
+    ] {
+        *nutrient =
+            nutrient.map(|grams| ((f64::from(grams) * factor * 10.0).round() / 10.0) as f32);
+    }
+    recipe.servings = Some(servings);
+    true
+}
+
+// Rounds the quantity for the ingredient's unit and writes the amount text to match
+fn set_quantity(ingredient: &mut Ingredient, quantity: f64) {
+    let unit = ingredient.unit.as_deref();
+    let quantity = round_quantity(quantity, unit);
+    ingredient.amount = format_amount(quantity, unit);
+    ingredient.quantity = Some(quantity);
+}
+
+// The quantity in `from` expressed in `to`, as long as both measure the same thing
+pub fn convert_quantity(quantity: f64, from: &str, to: &str) -> Option<f64> {
+    let (_, from_dimension, from_factor, _) = CONVERSIONS.iter().find(|(u, ..)| *u == from)?;
+    let (_, to_dimension, to_factor, _) = CONVERSIONS.iter().find(|(u, ..)| *u == to)?;
+    if from_dimension != to_dimension {
+        return None;
+    }
+    Some(quantity * from_factor / to_factor)
+}
+
+fn convert(
+    quantity: f64,
+    unit: &str,
+    system: UnitSystem,
+    density: Option<f64>,
+) -> Option<(f64, &'static str)> {
+    let (_, dimension, factor, unit_system) = CONVERSIONS.iter().find(|(u, ..)| *u == unit)?;
+    if unit_system.is_none_or(|unit_system| unit_system == system) {
+        return None;
+    }
+    let base = quantity * factor;
+    let converted = match (system, dimension, density) {
+        (UnitSystem::Metric, Dimension::Mass, _) => metric_mass(base),
+        (UnitSystem::Metric, Dimension::Volume, Some(density)) => metric_mass(base * density),
+        (UnitSystem::Metric, Dimension::Volume, None) => metric_volume(base),
+        (UnitSystem::Imperial, Dimension::Volume, _) => imperial_volume(base),
+        (UnitSystem::Imperial, Dimension::Mass, Some(density)) => imperial_volume(base / density),
+        (UnitSystem::Imperial, Dimension::Mass, None) => imperial_mass(base),
+    };
+    Some(converted)
+}
+
+fn metric_mass(grams: f64) -> (f64, &'static str) {
+    if grams >= 1000.0 {
+        return (grams / 1000.0, "kg");
+    }
+    (grams, "g")
+}
+
+fn metric_volume(millilitres: f64) -> (f64, &'static str) {
+    if millilitres >= 1000.0 {
+        return (millilitres / 1000.0, "l");
+    }
+    (millilitres, "ml")
+}
+
+fn imperial_mass(grams: f64) -> (f64, &'static str) {
+    let ounces = grams / 28.349523125;
+    if ounces >= 16.0 {
+        return (ounces / 16.0, "lb");
+    }
+    (ounces, "oz")
+}
+
+// Cups down to a quarter of one, spoons below that
+fn imperial_volume(millilitres: f64) -> (f64, &'static str) {
+    let cups = millilitres / 236.5882365;
+    if cups >= 0.25 {
+        return (cups, "cup");
+    }
+    let tablespoons = millilitres / 14.78676478125;
+    if tablespoons >= 1.0 {
+        return (tablespoons, "tbsp");
+    }
+    (millilitres / 4.92892159375, "tsp")
+}
+
+// The fractions cups, spoons and whole things get rounded to
+const KITCHEN_FRACTIONS: &[(f64, &str)] = &[
+    (0.0, ""),
+    (1.0 / 8.0, "1/8"),
+    (1.0 / 4.0, "1/4"),
+    (1.0 / 3.0, "1/3"),
+    (3.0 / 8.0, "3/8"),
+    (1.0 / 2.0, "1/2"),
+    (5.0 / 8.0, "5/8"),
+    (2.0 / 3.0, "2/3"),
+    (3.0 / 4.0, "3/4"),
+    (7.0 / 8.0, "7/8"),
+    (1.0, ""),
+];
+
+fn is_metric(unit: Option<&str>) -> bool {
+    CONVERSIONS
+        .iter()
+        .any(|(u, _, _, system)| Some(*u) == unit && *system == Some(UnitSystem::Metric))
+}
+
+// Whole part and the closest kitchen fraction, never rounding something down to nothing
+fn kitchen_fraction(quantity: f64) -> (f64, &'static str) {
+    let whole = quantity.trunc();
+    let (fraction, text) = KITCHEN_FRACTIONS
+        .iter()
+        .min_by(|(a, _), (b, _)| {
+            let a = (quantity - whole - a).abs();
+            let b = (quantity - whole - b).abs();
+            a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .copied()
+        .unwrap_or((0.0, ""));
+    if whole == 0.0 && fraction == 0.0 && quantity > 0.0 {
+        return (1.0 / 8.0, "1/8");
+    }
+    (whole + fraction, text)
+}
+
+// Nobody weighs 236.588 grams of anything. Metric amounts get decimals, everything else the
+// fractions people actually measure with.
+pub fn round_quantity(quantity: f64, unit: Option<&str>) -> f64 {
+    if quantity >= 10.0 {
+        return quantity.round();
+    }
+    if is_metric(unit) {
+        return (quantity * 10.0).round() / 10.0;
+    }
+    kitchen_fraction(quantity).0
+}
+
+// eg. "250 g", "1 1/2 cup", "1/3 tsp" or just "3" for things without a unit
+pub fn format_amount(quantity: f64, unit: Option<&str>) -> String {
+    let number = if quantity >= 10.0 || is_metric(unit) {
+        quantity.to_string()
+    } else {
+        match kitchen_fraction(quantity) {
+            (value, "") => value.to_string(),
+            (value, fraction) if value < 1.0 => fraction.to_string(),
+            (value, fraction) => format!("{} {}", value.trunc(), fraction),
+        }
+    };
+    match unit {
+        Some(unit) => format!("{} {}", number, unit),
+        None => number,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ingredient(name: &str, quantity: Option<f64>, unit: Option<&str>) -> Ingredient {
+        Ingredient {
+            name: name.to_string(),
+            tipo: None,
+            amount: "as sent".to_string(),
+            quantity,
+            unit: unit.map(str::to_string),
+        }
+    }
+
+    fn converted(
+        quantity: f64,
+        unit: &str,
+        system: UnitSystem,
+        density: Option<f64>,
+    ) -> (Option<f64>, Option<String>, String) {
+        let mut converted = ingredient("flour", Some(quantity), Some(unit));
+        convert_ingredient(&mut converted, system, density);
+        (converted.quantity, converted.unit, converted.amount)
+    }
+
+    #[test]
+    fn imperial_goes_metric() {
+        assert_eq!(
+            converted(2.0, "lb", UnitSystem::Metric, None),
+            (Some(907.0), Some("g".to_string()), "907 g".to_string())
+        );
+        assert_eq!(
+            converted(3.0, "lb", UnitSystem::Metric, None),
+            (Some(1.4), Some("kg".to_string()), "1.4 kg".to_string())
+        );
+        assert_eq!(
+            converted(2.0, "fl oz", UnitSystem::Metric, None),
+            (Some(59.0), Some("ml".to_string()), "59 ml".to_string())
+        );
+    }
+
+    #[test]
+    fn metric_goes_imperial() {
+        assert_eq!(
+            converted(200.0, "g", UnitSystem::Imperial, None),
+            (Some(7.0), Some("oz".to_string()), "7 oz".to_string())
+        );
+        assert_eq!(
+            converted(500.0, "g", UnitSystem::Imperial, None),
+            (Some(1.125), Some("lb".to_string()), "1 1/8 lb".to_string())
+        );
+        assert_eq!(
+            converted(1.5, "l", UnitSystem::Imperial, None),
+            (
+                Some(6.0 + 1.0 / 3.0),
+                Some("cup".to_string()),
+                "6 1/3 cup".to_string()
+            )
+        );
+        assert_eq!(
+            converted(10.0, "ml", UnitSystem::Imperial, None),
+            (Some(2.0), Some("tsp".to_string()), "2 tsp".to_string())
+        );
+    }
+
+    // Metric measures by weight and imperial by volume once the density is known
+    #[test]
+    fn density_switches_between_weight_and_volume() {
+        let flour = common_density("flour");
+        assert_eq!(flour, Some(0.53));
+        assert_eq!(
+            converted(1.0, "cup", UnitSystem::Metric, flour),
+            (Some(125.0), Some("g".to_string()), "125 g".to_string())
+        );
+        assert_eq!(
+            converted(100.0, "g", UnitSystem::Imperial, flour),
+            (Some(0.75), Some("cup".to_string()), "3/4 cup".to_string())
+        );
+    }
+
+    #[test]
+    fn unknown_ingredients_have_no_density() {
+        assert_eq!(common_density("dragon fruit"), None);
+        // Only exact names, the store lowercases them before they get here
+        assert_eq!(common_density("Flour"), None);
+    }
+
+    // Without a density there's no going between weight and volume, the ingredient keeps
+    // measuring the same thing in the other system
+    #[test]
+    fn no_density_keeps_the_dimension() {
+        assert_eq!(
+            converted(1.0, "cup", UnitSystem::Metric, None),
+            (Some(237.0), Some("ml".to_string()), "237 ml".to_string())
+        );
+        assert_eq!(
+            converted(100.0, "g", UnitSystem::Imperial, None),
+            (Some(3.5), Some("oz".to_string()), "3 1/2 oz".to_string())
+        );
+    }
+
+    #[test]
+    fn what_cant_be_converted_is_left_alone() {
+        let unchanged = |quantity: Option<f64>, unit: Option<&str>, system| {
+            let mut after = ingredient("salt", quantity, unit);
+            convert_ingredient(&mut after, system, Some(1.2));
+            after.quantity == quantity && after.unit.as_deref() == unit && after.amount == "as sent"
+        };
+        // Already in the system asked for
+        assert!(unchanged(Some(250.0), Some("g"), UnitSystem::Metric));
+        assert!(unchanged(Some(2.0), Some("cup"), UnitSystem::Imperial));
+        // Spoons belong to both
+        assert!(unchanged(Some(1.0), Some("tsp"), UnitSystem::Metric));
+        // Units we can't convert, no unit or no quantity
+        assert!(unchanged(Some(2.0), Some("pinch"), UnitSystem::Metric));
+        assert!(unchanged(Some(3.0), None, UnitSystem::Imperial));
+        assert!(unchanged(None, Some("cup"), UnitSystem::Metric));
+    }
+
+    fn pasta(servings: Option<u16>) -> Recipe {
+        let mut unparsed = ingredient("pepper", None, None);
+        unparsed.amount = "to taste".to_string();
+        let mut eggs = ingredient("egg", Some(3.0), None);
+        eggs.amount = "3".to_string();
+        Recipe {
+            name: "Pasta".to_string(),
+            servings,
+            calories: Some(450),
+            protein: Some(12.5),
+            ingredients: Some(vec![
+                ingredient("pasta", Some(200.0), Some("g")),
+                ingredient("milk", Some(1.0), Some("cup")),
+                eggs,
+                unparsed,
+            ]),
+            ..Recipe::default()
+        }
+    }
+
+    fn amounts(recipe: &Recipe) -> Vec<(Option<f64>, String)> {
+        recipe
+            .ingredients
+            .iter()
+            .flatten()
+            .map(|i| (i.quantity, i.amount.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn scaling_up() {
+        let mut recipe = pasta(Some(2));
+        assert!(scale_recipe(&mut recipe, 4));
+        assert_eq!(recipe.servings, Some(4));
+        assert_eq!(recipe.calories, Some(900));
+        assert_eq!(recipe.protein, Some(25.0));
+        assert_eq!(
+            amounts(&recipe),
+            vec![
+                (Some(400.0), "400 g".to_string()),
+                (Some(2.0), "2 cup".to_string()),
+                (Some(6.0), "6".to_string()),
+                (None, "to taste".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn scaling_down() {
+        let mut recipe = pasta(Some(2));
+        assert!(scale_recipe(&mut recipe, 1));
+        assert_eq!(recipe.servings, Some(1));
+        assert_eq!(recipe.calories, Some(225));
+        assert_eq!(recipe.protein, Some(6.3));
+        assert_eq!(
+            amounts(&recipe),
+            vec![
+                (Some(100.0), "100 g".to_string()),
+                (Some(0.5), "1/2 cup".to_string()),
+                (Some(1.5), "1 1/2".to_string()),
+                (None, "to taste".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn nothing_to_scale_from() {
+        for servings in &[None, Some(0)] {
+            let mut recipe = pasta(*servings);
+            assert!(!scale_recipe(&mut recipe, 4));
+            assert_eq!(recipe.servings, *servings);
+            assert_eq!(recipe.calories, Some(450));
+            assert_eq!(amounts(&recipe), amounts(&pasta(None)));
+        }
+    }
+
+    #[test]
+    fn kitchen_fractions() {
+        assert_eq!(format_amount(0.25, Some("cup")), "1/4 cup");
+        assert_eq!(format_amount(0.3, Some("tsp")), "1/3 tsp");
+        assert_eq!(format_amount(0.5, Some("tbsp")), "1/2 tbsp");
+        assert_eq!(format_amount(0.7, Some("cup")), "2/3 cup");
+        assert_eq!(format_amount(2.9, None), "2 7/8");
+        assert_eq!(format_amount(0.97, Some("cup")), "1 cup");
+        // Something is never rounded down to nothing
+        assert_eq!(round_quantity(0.01, Some("cup")), 0.125);
+        assert_eq!(format_amount(0.01, Some("cup")), "1/8 cup");
+    }
+
+    #[test]
+    fn metric_and_big_amounts_skip_the_fractions() {
+        assert_eq!(round_quantity(2.345, Some("g")), 2.3);
+        assert_eq!(format_amount(2.3, Some("g")), "2.3 g");
+        assert_eq!(round_quantity(12.4, Some("cup")), 12.0);
+        assert_eq!(format_amount(12.0, Some("cup")), "12 cup");
+    }
+}
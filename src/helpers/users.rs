@@ -1,36 +1,50 @@
+use crate::errors::{ApiError, ApiResult};
+use crate::models::{GraphPool, User};
 use neo4rs::*;
-// use uuid::Uuid;
-use crate::models::GraphPool;
-use rocket::http::{Cookie, Cookies, SameSite};
+use rocket::http::{Cookie, CookieJar, SameSite};
+use rocket::time::{Duration, OffsetDateTime};
+use uuid::Uuid;
 
-pub async fn get_user_from_db(graph: GraphPool, u_id: &str) -> Option<Node> {
+pub async fn get_user_from_db(graph: GraphPool, u_id: &str) -> ApiResult<Option<Node>> {
     let mut res = graph
         .execute(query("MATCH (u:User) WHERE u.id = $id RETURN u").param("id", u_id))
-        .await
-        .expect("Couldn't find that Uuid");
+        .await?;
 
-    let row = res.next().await.expect("Couldn't fetch row");
-    row.as_ref()?.get::<Node>("u")
+    let row = res.next().await?;
+    Ok(row.and_then(|row| row.get::<Node>("u")))
 }
 
-pub fn set_user_cookies(mut cookies: Cookies, id: String, username: String) {
-    let cookie = Cookie::build("user_id", id)
+// Keeps the password hash, callers that send the user anywhere need to blank it.
+pub fn format_user(node: Node) -> ApiResult<User> {
+    let malformed = || ApiError::Database("Malformed user in the database".to_string());
+    let id_string: String = node.get("id").ok_or_else(malformed)?;
+    Ok(User {
+        id: Some(Uuid::parse_str(id_string.as_str()).map_err(|_| malformed())?),
+        username: node.get("username").ok_or_else(malformed)?,
+        password: node.get("password").unwrap_or_default(),
+        email: node.get("email"),
+        role: node.get("role"),
+    })
+}
+
+pub fn set_user_cookies(cookies: &CookieJar<'_>, id: String, username: String) {
+    // Rocket would give the private cookie a week on its own but it doesn't tell us the date
+    // until the response goes out, so we pick it ourselves and use it for both.
+    let exp_date = OffsetDateTime::now_utc() + Duration::weeks(1);
+    let cookie = Cookie::build(("user_id", id))
         .path("/")
         .same_site(SameSite::None)
-        .secure(true)
-        .finish();
+        .expires(exp_date)
+        .secure(true);
     cookies.add_private(cookie);
     // Note that we are specifically changing the path of this public cookie to "/" so that
     // svelte is able to see it. I don't know if this a svelte problem or a me problem. But
     // if we leave it blank rocket will set the path to "/api" and then svelte won't see it
-    let exp_date = cookies.get_private("user_id").unwrap().expires().unwrap();
-    // .copy();
-    let u_cookie = Cookie::build("username", username)
+    let u_cookie = Cookie::build(("username", username))
         .path("/")
         .same_site(SameSite::None)
         .expires(exp_date)
         .secure(true)
-        .http_only(true)
-        .finish();
+        .http_only(true);
     cookies.add(u_cookie);
 }
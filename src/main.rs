@@ -1,9 +1,10 @@
-#![feature(proc_macro_hygiene, decl_macro)]
-
+mod errors;
 mod guards;
 mod helpers;
+mod migrations;
 mod models;
 mod routes;
+mod store;
 
 #[macro_use]
 extern crate rocket;
@@ -13,8 +14,14 @@ use neo4rs::*;
 use rocket_cors::{AllowedHeaders, AllowedOrigins};
 // use rocket::State;
 use rocket::http::Method;
+use rocket::fairing::AdHoc;
+use rocket::{Build, Rocket};
 use std::sync::Arc;
-use tokio::runtime::Runtime;
+use std::time::Duration;
+use chrono::Utc;
+use helpers::plan::archive_expired_plans;
+use models::GraphPool;
+use store::{MemoryStore, Neo4jStore, StorePool};
 // use std::collections::HashSet;
 // use crate::models::{Recipe};
 // use crate::users::{new_user, login, query_users};
@@ -27,6 +34,10 @@ use tokio::runtime::Runtime;
 const USER_MOUNT: &str = "/api/users";
 const ROOT_MOUNT: &str = "/api";
 const RECIPES_MOUNT: &str = "/api/recipes";
+const SHOPPING_MOUNT: &str = "/api/shopping-list";
+const PLAN_MOUNT: &str = "/api/plan";
+// Seconds between runs of the plan expiry, PLAN_EXPIRY_INTERVAL overrides it
+const PLAN_EXPIRY_INTERVAL: u64 = 60 * 60;
 
 #[get("/")]
 fn index() -> &'static str {
@@ -39,14 +50,56 @@ async fn create_graph(uri: String, user: String, pass: String) -> Graph {
         .expect("Couldn't connect")
 }
 
-fn main() {
-    dotenv().ok();
+async fn connect_graph() -> GraphPool {
     let uri = std::env::var("DB_URI").expect("set DB_URI");
     let user = std::env::var("DB_USER").expect("set DB_USER");
     let pass = std::env::var("DB_PASS").expect("set DB_PASS");
-    let rt = Runtime::new().expect("Unable to create rt");
-    let graph = Arc::new(rt.block_on(create_graph(uri, user, pass)));
+    Arc::new(create_graph(uri, user, pass).await)
+}
+
+// A migration that can't run leaves the graph at the version before it, so stop with its message
+// instead of serving on a half migrated graph.
+async fn migrate(graph: &GraphPool) {
+    match migrations::run_migrations(graph).await {
+        Ok(version) => println!("Graph schema is at version {}", version),
+        Err(e) => {
+            eprintln!("Couldn't run the migrations: {}", e.message());
+            std::process::exit(1);
+        }
+    }
+}
 
+// RECIPES_STORE=memory runs everything without a database, anything else connects to neo4j and
+// brings its schema up to date before serving anything.
+async fn create_store() -> StorePool {
+    if std::env::var("RECIPES_STORE").as_deref() == Ok("memory") {
+        return Arc::new(MemoryStore::new());
+    }
+    let graph = connect_graph().await;
+    migrate(&graph).await;
+    Arc::new(Neo4jStore::new(graph))
+}
+
+// Moves the expired days of every meal plan to the history for as long as the server runs. The
+// first run is right at launch so a server that was down catches up.
+async fn expire_plans(store: StorePool) {
+    let seconds = std::env::var("PLAN_EXPIRY_INTERVAL")
+        .ok()
+        .and_then(|seconds| seconds.parse().ok())
+        .unwrap_or(PLAN_EXPIRY_INTERVAL);
+    let mut interval = rocket::tokio::time::interval(Duration::from_secs(seconds));
+    loop {
+        interval.tick().await;
+        match archive_expired_plans(&store, Utc::now().naive_utc()).await {
+            Ok(0) => {}
+            Ok(archived) => println!("Archived {} expired meal plan entries", archived),
+            Err(e) => eprintln!("Couldn't archive the expired meal plans: {}", e),
+        }
+    }
+}
+
+// Everything but the launch, the tests build the same app on top of a MemoryStore.
+fn rocket(store: StorePool) -> Rocket<Build> {
     // In theory these are needed because the app is working as an API. If i can figure out how
     // to work with the static sites from Svelte I could maybe get rid of this and server
     // everything from here. Lack of documentation is killing me.
@@ -54,7 +107,14 @@ fn main() {
     // let allowed_origins = AllowedOrigins::all();
     let cors = rocket_cors::CorsOptions {
         allowed_origins,
-        allowed_methods: vec![Method::Post, Method::Options, Method::Get, Method::Delete]
+        allowed_methods: vec![
+            Method::Post,
+            Method::Options,
+            Method::Get,
+            Method::Delete,
+            Method::Put,
+            Method::Patch,
+        ]
             .into_iter()
             .map(From::from)
             .collect(),
@@ -72,7 +132,7 @@ fn main() {
     .to_cors()
     .expect("Cant make cors");
 
-    rocket::ignite()
+    rocket::build()
         .mount(
             RECIPES_MOUNT,
             routes![
@@ -81,13 +141,27 @@ fn main() {
                 routes::recipes::random_recipes,
                 routes::recipes::choose_recipes,
                 routes::recipes::chosen_recipes,
+                routes::recipes::chosen_shopping_list,
                 routes::recipes::recipes_by_ingredient,
                 routes::recipes::remove_recipe,
                 routes::recipes::get_recipe,
+                routes::recipes::replace_recipe,
+                routes::recipes::update_recipe,
                 routes::recipes::reset_all_chosen,
+                routes::recipes::cook_recipe,
+                routes::recipes::cook_log,
+                routes::recipes::rate_recipe,
+                routes::recipes::remove_rating,
+                routes::recipes::recipe_reviews,
                 routes::recipes::recipe_list,
+                routes::recipes::search_recipes,
+                routes::recipes::cookable_recipes,
+                routes::recipes::set_allergens,
+                routes::recipes::ingredient_allergens,
+                routes::recipes::set_density,
                 routes::recipes::share_recipe,
                 routes::recipes::like_recipe,
+                routes::recipes::favorite_recipe,
                 routes::recipes::public_recipes,
                 routes::recipes::get_public_recipe,
             ],
@@ -98,9 +172,39 @@ fn main() {
                 routes::users::new_user,
                 routes::users::query_users,
                 routes::users::get_user,
+                routes::users::get_exclusions,
+                routes::users::set_exclusions,
+                routes::users::get_plan_settings,
+                routes::users::set_plan_settings,
+                routes::users::get_picker_weights,
+                routes::users::set_picker_weights,
                 // routes::users::get_user_redirect,
             ],
         )
+        .mount(
+            PLAN_MOUNT,
+            routes![
+                routes::plan::get_week,
+                routes::plan::plan_history,
+                routes::plan::add_to_plan,
+                routes::plan::move_meal,
+                routes::plan::swap_meals,
+                routes::plan::clear_day,
+            ],
+        )
+        .mount(
+            SHOPPING_MOUNT,
+            routes![
+                routes::shopping::get_shopping_list,
+                routes::shopping::create_shopping_list,
+                routes::shopping::regenerate_shopping_list,
+                routes::shopping::remove_shopping_list,
+                routes::shopping::add_shopping_item,
+                routes::shopping::update_shopping_item,
+                routes::shopping::toggle_shopping_item,
+                routes::shopping::remove_shopping_item,
+            ],
+        )
         .mount(
             ROOT_MOUNT,
             routes![
@@ -110,9 +214,47 @@ fn main() {
                 routes::users::logout,
             ],
         )
+        .register(
+            "/",
+            catchers![
+                errors::bad_request,
+                errors::unauthorized,
+                errors::forbidden,
+                errors::not_found,
+                errors::unprocessable_entity,
+                errors::internal_error,
+            ],
+        )
         // .mount("/", StaticFiles::from(concat!(env!("CARGO_MANIFEST_DIR"), "/static")))
-        .manage(rt)
-        .manage(graph)
+        .manage(store)
         .attach(cors)
-        .launch();
+        .attach(AdHoc::on_liftoff("Plan expiry", |rocket| {
+            Box::pin(async move {
+                let store = rocket
+                    .state::<StorePool>()
+                    .expect("The store is managed before launch")
+                    .clone();
+                rocket::tokio::spawn(expire_plans(store));
+            })
+        }))
 }
+
+#[rocket::main]
+async fn main() {
+    dotenv().ok();
+
+    // `cargo run -- migrate` only runs the pending migrations and exits.
+    if std::env::args().nth(1).as_deref() == Some("migrate") {
+        migrate(&connect_graph().await).await;
+        return;
+    }
+    let store = create_store().await;
+
+    rocket(store)
+        .launch()
+        .await
+        .expect("Couldn't launch rocket");
+}
+
+#[cfg(test)]
+mod tests;
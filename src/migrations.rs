@@ -0,0 +1,375 @@
+use crate::errors::{ApiError, ApiResult};
+use crate::helpers::picker::recipe_minutes;
+use crate::helpers::recipes::{optional, parsed_amount, with_parsed_amounts};
+use crate::models::GraphPool;
+use neo4rs::*;
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub cypher: &'static str,
+    // For the data cypher can't fix by itself, runs after the script
+    pub backfill: Option<Backfill>,
+    // For the data the script would fail on, runs before it and stops the migration with a report
+    pub check: Option<Check>,
+}
+
+pub enum Backfill {
+    IngredientQuantities,
+    RecipeMinutes,
+}
+
+pub enum Check {
+    UniqueUsers,
+}
+
+// Add new scripts at the end with the next version, never edit one that already shipped since
+// the graphs that ran it won't run it again.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "constraints",
+        cypher: include_str!("../migrations/001_constraints.cypher"),
+        backfill: None,
+        check: Some(Check::UniqueUsers),
+    },
+    Migration {
+        version: 2,
+        name: "steps_list",
+        cypher: include_str!("../migrations/002_steps_list.cypher"),
+        backfill: None,
+        check: None,
+    },
+    Migration {
+        version: 3,
+        name: "recipe_created",
+        cypher: include_str!("../migrations/003_recipe_created.cypher"),
+        backfill: None,
+        check: None,
+    },
+    Migration {
+        version: 4,
+        name: "fulltext_search",
+        cypher: include_str!("../migrations/004_fulltext_search.cypher"),
+        backfill: None,
+        check: None,
+    },
+    Migration {
+        version: 5,
+        name: "ingredient_quantities",
+        cypher: include_str!("../migrations/005_ingredient_quantities.cypher"),
+        backfill: Some(Backfill::IngredientQuantities),
+        check: None,
+    },
+    Migration {
+        version: 6,
+        name: "servings_number",
+        cypher: include_str!("../migrations/006_servings_number.cypher"),
+        backfill: None,
+        check: None,
+    },
+    Migration {
+        version: 7,
+        name: "meal_plan",
+        cypher: include_str!("../migrations/007_meal_plan.cypher"),
+        backfill: None,
+        check: None,
+    },
+    Migration {
+        version: 8,
+        name: "recipe_minutes",
+        cypher: include_str!("../migrations/008_recipe_minutes.cypher"),
+        backfill: Some(Backfill::RecipeMinutes),
+        check: None,
+    },
+];
+
+// The driver only takes one statement per query so the scripts are split on a `;` ending a line,
+// one anywhere else is left alone since it could be in a string. Lines starting with `//` are
+// comments.
+fn statements(cypher: &str) -> Vec<String> {
+    let mut statements = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    for line in cypher.lines() {
+        if line.trim_start().starts_with("//") {
+            continue;
+        }
+        match line.trim_end().strip_suffix(';') {
+            Some(last) => {
+                current.push(last);
+                statements.push(current.join("\n"));
+                current.clear();
+            }
+            None => current.push(line),
+        }
+    }
+    statements.push(current.join("\n"));
+    statements
+        .iter()
+        .map(|statement| statement.trim())
+        .filter(|statement| !statement.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+// Every applied migration leaves a (:Migration) node behind, the highest version is where the
+// graph is at. 0 means nothing ran yet.
+pub async fn current_version(graph: &GraphPool) -> ApiResult<i64> {
+    let mut res = graph
+        .execute(query(
+            "MATCH (m:Migration) RETURN coalesce(max(m.version), 0) AS version",
+        ))
+        .await?;
+    let row = res.next().await?;
+    Ok(row.and_then(|row| row.get::<i64>("version")).unwrap_or(0))
+}
+
+// Runs the migrations the graph hasn't seen yet, in order, and returns the version it ends up
+// at. Schema changes can't share a transaction with writes so every statement runs on its own,
+// the version is only recorded once all of them went through.
+pub async fn run_migrations(graph: &GraphPool) -> ApiResult<i64> {
+    let applied = current_version(graph).await?;
+    let mut version = applied;
+
+    for migration in MIGRATIONS.iter().filter(|m| m.version > applied) {
+        println!(
+            "Running migration {} ({})",
+            migration.version, migration.name
+        );
+        if let Some(check) = &migration.check {
+            run_check(graph, check).await?;
+        }
+        for statement in statements(migration.cypher) {
+            graph.run(query(&statement)).await?;
+        }
+        if let Some(backfill) = &migration.backfill {
+            run_backfill(graph, backfill).await?;
+        }
+        graph
+            .run(
+                query("CREATE (:Migration {version: $version, name: $name, applied: datetime()})")
+                    .param("version", migration.version)
+                    .param("name", migration.name),
+            )
+            .await?;
+        version = migration.version;
+    }
+    Ok(version)
+}
+
+async fn run_check(graph: &GraphPool, check: &Check) -> ApiResult<()> {
+    match check {
+        Check::UniqueUsers => check_unique_users(graph).await,
+    }
+}
+
+// The user constraints can't be created while two users share an id, username or email. Which
+// one to keep is up to whoever runs the migration so nothing gets merged here, the duplicates are
+// listed and the migration stops before touching anything. To clean up find them with eg.
+// `MATCH (u:User {email: 'ann@example.com'}) RETURN u`, move the recipes, CHOSEN and the rest
+// over to the user that stays, DETACH DELETE the other one (or give it a new username/email)
+// and run `migrate` again.
+async fn check_unique_users(graph: &GraphPool) -> ApiResult<()> {
+    let mut res = graph
+        .execute(query(
+            "MATCH (u:User) WITH 'id' AS property, u.id AS value, count(*) AS users \
+            WHERE users > 1 RETURN property, value, users \
+            UNION ALL \
+            MATCH (u:User) WITH 'username' AS property, u.username AS value, count(*) AS users \
+            WHERE users > 1 RETURN property, value, users \
+            UNION ALL \
+            MATCH (u:User) WHERE u.email IS NOT NULL \
+            WITH 'email' AS property, u.email AS value, count(*) AS users \
+            WHERE users > 1 RETURN property, value, users",
+        ))
+        .await?;
+    let mut duplicates = Vec::new();
+    while let Some(row) = res.next().await? {
+        if let (Some(property), Some(value), Some(users)) = (
+            row.get::<String>("property"),
+            row.get::<String>("value"),
+            row.get::<i64>("users"),
+        ) {
+            duplicates.push(Duplicate {
+                property,
+                value,
+                users,
+            });
+        }
+    }
+    match duplicates_report(&duplicates) {
+        Some(report) => Err(ApiError::Internal(report)),
+        None => Ok(()),
+    }
+}
+
+struct Duplicate {
+    property: String,
+    value: String,
+    users: i64,
+}
+
+fn duplicates_report(duplicates: &[Duplicate]) -> Option<String> {
+    if duplicates.is_empty() {
+        return None;
+    }
+    let lines: Vec<_> = duplicates
+        .iter()
+        .map(|d| {
+            format!(
+                "  {} {:?} is used by {} users",
+                d.property, d.value, d.users
+            )
+        })
+        .collect();
+    Some(format!(
+        "Users share values that have to be unique, keep one user for each and run the \
+        migration again (see `check_unique_users`):\n{}",
+        lines.join("\n")
+    ))
+}
+
+async fn run_backfill(graph: &GraphPool, backfill: &Backfill) -> ApiResult<()> {
+    match backfill {
+        Backfill::IngredientQuantities => backfill_ingredient_quantities(graph).await,
+        Backfill::RecipeMinutes => backfill_recipe_minutes(graph).await,
+    }
+}
+
+// The amounts are parsed in rust, see `parse_amount`. Every distinct amount gets parsed once and
+// set on all the USES relationships with it.
+async fn backfill_ingredient_quantities(graph: &GraphPool) -> ApiResult<()> {
+    let mut res = graph
+        .execute(query(
+            "MATCH (:Recipe)-[uses:USES]->(:Ingredient) \
+            WHERE uses.amount IS NOT NULL \
+            RETURN DISTINCT uses.amount AS amount",
+        ))
+        .await?;
+    let mut amounts = Vec::new();
+    while let Some(row) = res.next().await? {
+        if let Some(amount) = row.get::<String>("amount") {
+            amounts.push(amount);
+        }
+    }
+    if amounts.is_empty() {
+        return Ok(());
+    }
+
+    let backfill = query(&format!(
+        "UNWIND range(0, size($amounts) - 1) AS idx \
+        MATCH (:Recipe)-[uses:USES]->(:Ingredient) \
+        WHERE uses.amount = $amounts[idx] \
+        SET uses += {{{}}}",
+        parsed_amount()
+    ));
+    graph
+        .run(with_parsed_amounts(backfill, &amounts).param("amounts", amounts))
+        .await?;
+    Ok(())
+}
+
+// Same as the amounts, every distinct time gets parsed once with `recipe_minutes` and set on all
+// the recipes with it.
+async fn backfill_recipe_minutes(graph: &GraphPool) -> ApiResult<()> {
+    let mut res = graph
+        .execute(query(
+            "MATCH (r:Recipe) WHERE r.time IS NOT NULL RETURN DISTINCT r.time AS time",
+        ))
+        .await?;
+    let mut times = Vec::new();
+    while let Some(row) = res.next().await? {
+        if let Some(time) = row.get::<String>("time") {
+            times.push(time);
+        }
+    }
+    if times.is_empty() {
+        return Ok(());
+    }
+
+    let minutes: Vec<Option<u32>> = times.iter().map(|time| recipe_minutes(time)).collect();
+    let minutes_set: Vec<bool> = minutes.iter().map(Option::is_some).collect();
+    let minutes: Vec<i64> = minutes
+        .into_iter()
+        .map(|m| i64::from(m.unwrap_or(0)))
+        .collect();
+    graph
+        .run(
+            query(&format!(
+                "UNWIND range(0, size($times) - 1) AS idx \
+                MATCH (r:Recipe) WHERE r.time = $times[idx] \
+                SET r.minutes = {}",
+                optional("minutes", "[idx]")
+            ))
+            .param("times", times)
+            .param("minutes", minutes)
+            .param("minutes_set", minutes_set),
+        )
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn statements_split_on_semicolons_ending_a_line() {
+        let cypher = "// one; two\n\
+            MATCH (n:Note) WHERE n.text = 'a; b'\n\
+            SET n.done = true;\n\
+            \n\
+            CREATE (:Note {text: 'c;'});\n\
+            RETURN 1";
+        assert_eq!(
+            statements(cypher),
+            vec![
+                "MATCH (n:Note) WHERE n.text = 'a; b'\nSET n.done = true",
+                "CREATE (:Note {text: 'c;'})",
+                "RETURN 1",
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicates_are_reported_one_per_line() {
+        assert_eq!(duplicates_report(&[]), None);
+        let report = duplicates_report(&[
+            Duplicate {
+                property: "username".to_string(),
+                value: "ann".to_string(),
+                users: 2,
+            },
+            Duplicate {
+                property: "email".to_string(),
+                value: "ann@example.com".to_string(),
+                users: 3,
+            },
+        ])
+        .unwrap();
+        let lines: Vec<_> = report.lines().skip(1).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "  username \"ann\" is used by 2 users",
+                "  email \"ann@example.com\" is used by 3 users",
+            ]
+        );
+    }
+
+    // Only the ones done entirely by a backfill have nothing to run
+    #[test]
+    fn shipped_migrations_have_statements() {
+        for migration in MIGRATIONS {
+            let statements = statements(migration.cypher);
+            assert!(
+                !statements.is_empty() || migration.backfill.is_some(),
+                "{} is empty",
+                migration.name
+            );
+            assert!(statements
+                .iter()
+                .all(|statement| !statement.contains(";\n")));
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use chrono::{NaiveDate, NaiveDateTime, Utc, Weekday};
 use neo4rs::Graph;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
@@ -19,7 +20,103 @@ pub struct IdsVec {
 pub struct RecipeVec {
     pub recipes: Vec<Recipe>,
     // pub rels: Option<Vec<(Uuid, String)>>
-    pub rels: Option<RecipeRelationships>
+    pub rels: Option<RecipeRelationships>,
+    // Only set by the endpoints that take a RecipeQuery
+    pub page: Option<PageInfo>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PageInfo {
+    pub offset: usize,
+    pub limit: usize,
+    // Everything matching the filters, not just what's in this page
+    pub total: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, FromFormField)]
+pub enum RecipeSort {
+    #[field(value = "name")]
+    Name,
+    #[field(value = "calories")]
+    Calories,
+    #[field(value = "protein")]
+    Protein,
+    #[field(value = "time")]
+    Time,
+    #[field(value = "created")]
+    Created,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, FromFormField)]
+pub enum SortOrder {
+    #[field(value = "asc")]
+    Asc,
+    #[field(value = "desc")]
+    Desc,
+}
+
+// What the recipe endpoints convert the ingredient amounts to when asked with `units=`
+#[derive(Clone, Copy, Debug, PartialEq, FromFormField)]
+pub enum UnitSystem {
+    #[field(value = "metric")]
+    Metric,
+    #[field(value = "imperial")]
+    Imperial,
+}
+
+// Query string for the recipe listings, eg. /list?sort=calories&order=desc&max_calories=500.
+// Everything is optional, by default the first page is sorted by name.
+#[derive(Clone, Debug, Default, FromForm)]
+pub struct RecipeQuery {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+    pub sort: Option<RecipeSort>,
+    pub order: Option<SortOrder>,
+    pub meal_type: Option<String>,
+    pub tipo: Option<String>,
+    pub min_calories: Option<u16>,
+    pub max_calories: Option<u16>,
+    pub min_carbohydrates: Option<f32>,
+    pub max_carbohydrates: Option<f32>,
+    pub min_fat: Option<f32>,
+    pub max_fat: Option<f32>,
+    pub min_protein: Option<f32>,
+    pub max_protein: Option<f32>,
+    // Ingredient names or allergens, repeated or comma separated, eg. exclude=nuts,pork. The
+    // routes swap these for the ingredient names they resolve to, see `excluded_ingredients`.
+    pub exclude: Vec<String>,
+    pub units: Option<UnitSystem>,
+}
+
+// Query string for the reviews of a recipe, newest first
+#[derive(Clone, Debug, Default, FromForm)]
+pub struct ReviewQuery {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+// Query string for /weekly, eg. /weekly?amount=5&daily_calories=600&mix=dinner:4,lunch:1. Every
+// constraint is optional, without any it's a plain random pick. Calories and protein are per
+// person, daily targets are for the 7 days of the week.
+#[derive(Clone, Debug, Default, FromForm)]
+pub struct WeeklyQuery {
+    pub amount: Option<usize>,
+    pub exclude: Vec<String>,
+    pub daily_calories: Option<f32>,
+    pub weekly_calories: Option<f32>,
+    pub daily_protein: Option<f32>,
+    pub weekly_protein: Option<f32>,
+    // How many recipes of each meal type, as meal_type:count, repeated or comma separated
+    pub mix: Vec<String>,
+    // Minutes for all the recipes together
+    pub max_time: Option<u32>,
+    // Leaves out what was in the plan in the last N weeks
+    pub no_repeat_weeks: Option<u16>,
+    // Recipe ids to keep in the suggestion, repeated or comma separated. The other slots get
+    // picked again.
+    pub pin: Vec<String>,
+    // Same seed, pins and recipes give the same suggestion
+    pub seed: Option<u64>,
 }
 
 // #[derive(Debug, Deserialize, Serialize)]
@@ -33,7 +130,7 @@ pub struct RecipeRelationships {
     pub likes: Vec<Uuid>
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize, Validate)]
+#[derive(Clone, Debug, Default, Deserialize, Serialize, Validate)]
 #[serde(rename_all = "camelCase")]
 pub struct Recipe {
     pub id: Option<Uuid>,
@@ -45,13 +142,45 @@ pub struct Recipe {
     pub carbohydrates: Option<f32>,
     pub fat: Option<f32>,
     pub protein: Option<f32>,
-    pub servings: Option<String>,
+    // How many servings the amounts and the nutrition above are for, 0 is the same as not saying.
+    // The yield unit is for recipes that make something else than servings, eg. 12 "cookies".
+    pub servings: Option<u16>,
+    pub yield_unit: Option<String>,
     pub meal_type: Option<String>,
     pub ingredients: Option<Vec<Ingredient>>,
     pub time: Option<String>,
+    // Set by the store when the recipe gets created, anything sent here is ignored
+    pub created: Option<NaiveDateTime>,
+    // Kept by the store from the cook log of every user, anything sent here is ignored too
+    pub last_cooked: Option<NaiveDateTime>,
+    pub times_cooked: Option<u32>,
+    // Kept by the store from the RATED relationships, there's no average until someone rates it.
+    // Only public recipes get rated since owners can't rate their own.
+    pub average_rating: Option<f32>,
+    pub rating_count: Option<u32>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Validate)]
+// Body for editing a recipe. Every field is optional so a PATCH only touches what was sent, a
+// PUT gets turned into one of these with every field filled in.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecipeUpdate {
+    pub name: Option<String>,
+    pub public: Option<bool>,
+    pub steps: Option<Vec<String>>,
+    pub tipo: Option<String>,
+    pub calories: Option<u16>,
+    pub carbohydrates: Option<f32>,
+    pub fat: Option<f32>,
+    pub protein: Option<f32>,
+    pub servings: Option<u16>,
+    pub yield_unit: Option<String>,
+    pub meal_type: Option<String>,
+    pub ingredients: Option<Vec<Ingredient>>,
+    pub time: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Validate)]
 pub struct User {
     pub id: Option<Uuid>,
     #[validate(length(min = 3))]
@@ -68,6 +197,9 @@ pub struct Ingredient {
     pub name: String,
     pub tipo: Option<String>,
     pub amount: String,
+    // Parsed from `amount` by the store when the recipe is saved, anything sent here is ignored
+    pub quantity: Option<f64>,
+    pub unit: Option<String>,
 }
 
 #[derive(Debug, FromForm)]
@@ -76,27 +208,431 @@ pub struct LoginCredentials {
     pub password: String,
 }
 
+// Body for /cook, the names of the ingredients the user has at hand.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct OnHand {
+    pub ingredients: Vec<String>,
+}
+
+// A recipe sharing at least one ingredient with what the user has. Coverage is the share of the
+// recipe's ingredients already at hand, from 0 to 1.
+#[derive(Debug, Serialize)]
+pub struct CookableRecipe {
+    pub recipe: Recipe,
+    pub coverage: f32,
+    pub missing: Vec<Ingredient>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CookableRecipes {
+    pub recipes: Vec<CookableRecipe>,
+}
+
+// Ingredient names or allergens the user never wants to see recipes with.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Exclusions {
+    pub exclude: Vec<String>,
+}
+
+// How the user's meal plan gets cleaned up. Days older than `retention_days` before today, today
+// being the one in `time_zone`, move from the plan to the plan history. The week start is the day
+// /plan shows weeks from.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanSettings {
+    pub retention_days: u16,
+    pub week_start: Weekday,
+    pub time_zone: String,
+}
+
+// How much each thing leans the weekly suggestion towards a recipe. 0 turns a knob off, higher
+// values lean harder and negative ones the other way, eg. a negative recency keeps suggesting what
+// was eaten lately.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PickerWeights {
+    pub likes: f64,
+    pub recency: f64,
+    pub favorites: f64,
+}
+
+// Allergen tags of an ingredient, eg. {"allergens": ["nuts"]}. They're kept per ingredient name
+// so every recipe using it gets them.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Allergens {
+    pub allergens: Vec<String>,
+}
+
+// Grams per millilitre of an ingredient, used to turn cups into grams and back
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Density {
+    pub density: f64,
+}
+
+// Everything the CHOSEN recipes need, one item per ingredient grouped by tipo. `markdown` is the
+// same list as a checklist for pasting somewhere else.
+#[derive(Debug, Serialize)]
+pub struct ShoppingList {
+    pub aisles: Vec<ShoppingAisle>,
+    pub markdown: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ShoppingAisle {
+    pub tipo: String,
+    pub items: Vec<ShoppingItem>,
+}
+
+// Amounts that could be added up are, the rest are listed next to them, eg. ["450 g", "to taste"]
+#[derive(Debug, Serialize)]
+pub struct ShoppingItem {
+    pub name: String,
+    pub amounts: Vec<String>,
+    pub recipes: Vec<String>,
+}
+
+// The shopping list the user keeps and checks off, made from the CHOSEN recipes. `plan` is the
+// fingerprint of the meal plan it came from, see `plan_fingerprint`, None for an empty plan.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SavedShoppingList {
+    pub id: Uuid,
+    pub created: NaiveDateTime,
+    pub plan: Option<String>,
+    pub items: Vec<ShoppingListItem>,
+}
+
+// Manual items are the ones added or edited by hand, regenerating the list never touches those.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ShoppingListItem {
+    pub id: Uuid,
+    pub name: String,
+    pub tipo: String,
+    pub amount: String,
+    pub checked: bool,
+    pub manual: bool,
+}
+
+// What the shopping list routes send back. Outdated means the meal plan changed since the list was
+// made, regenerating it catches up.
+#[derive(Debug, Serialize)]
+pub struct ShoppingListView {
+    #[serde(flatten)]
+    pub list: SavedShoppingList,
+    pub outdated: bool,
+}
+
+// Body for adding an item by hand
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct NewShoppingItem {
+    pub name: String,
+    pub tipo: Option<String>,
+    pub amount: Option<String>,
+}
+
+// Body for editing an item, only what was sent changes
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct ShoppingItemUpdate {
+    pub name: Option<String>,
+    pub tipo: Option<String>,
+    pub amount: Option<String>,
+    pub checked: Option<bool>,
+}
+
+// The meals of a day a recipe can be planned for, in the order they're listed in the plan
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MealSlot {
+    Breakfast,
+    Lunch,
+    Dinner,
+    Snack,
+}
+
+// Body for putting a recipe in the meal plan. Every CHOSEN relationship is one of these.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlanEntry {
+    pub recipe_id: String,
+    pub date: NaiveDate,
+    pub slot: MealSlot,
+}
+
+// Body for moving a planned meal somewhere else
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PlanSlot {
+    pub date: NaiveDate,
+    pub slot: MealSlot,
+}
+
+// A recipe in the plan, the id is the one of the plan entry so the same recipe can be in there
+// more than once.
+#[derive(Clone, Debug, Serialize)]
+pub struct PlannedMeal {
+    pub id: Uuid,
+    pub date: NaiveDate,
+    pub slot: MealSlot,
+    pub recipe: Recipe,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MealPlanDay {
+    pub date: NaiveDate,
+    pub meals: Vec<PlannedMeal>,
+}
+
+// Seven days from `start`, days with nothing planned are there with no meals
+#[derive(Debug, Serialize)]
+pub struct MealPlanWeek {
+    pub start: NaiveDate,
+    pub days: Vec<MealPlanDay>,
+}
+
+// Expired plan days, from the oldest
+#[derive(Debug, Serialize)]
+pub struct PlanHistory {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub meals: Vec<PlannedMeal>,
+}
+
+// Body for logging that a recipe got cooked, everything is optional. `at` is UTC and defaults to
+// now, the rating goes from 1 to 5.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CookEntry {
+    #[serde(default = "cooked_now")]
+    pub at: NaiveDateTime,
+    pub servings: Option<u16>,
+    pub notes: Option<String>,
+    pub rating: Option<u8>,
+}
+
+fn cooked_now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+// One entry of the cook log, the id is the one of the entry so a recipe cooked twice shows up
+// twice.
+#[derive(Clone, Debug, Serialize)]
+pub struct CookedRecipe {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub cooked: CookEntry,
+    pub recipe: Recipe,
+}
+
+// What got cooked from `from` up to but not including `to`, from the oldest
+#[derive(Debug, Serialize)]
+pub struct CookLog {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub cooked: Vec<CookedRecipe>,
+}
+
+// Body for rating a recipe, 1 to 5 stars and an optional review. Rating it again replaces the
+// previous rating.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct RatingForm {
+    pub stars: u8,
+    pub review: Option<String>,
+}
+
+// A rating that came with a review, `at` is when it was last changed (UTC)
+#[derive(Clone, Debug, Serialize)]
+pub struct Review {
+    pub username: String,
+    pub stars: u8,
+    pub review: String,
+    pub at: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Reviews {
+    pub reviews: Vec<Review>,
+    pub page: PageInfo,
+}
+
+// What /weekly picked, with the constraints it couldn't meet. `constraint` is the name of the
+// query param, or mix:<meal_type> for the meal types. The seed is the one to send back to get
+// the same suggestion again.
+#[derive(Debug, Serialize)]
+pub struct WeeklySuggestion {
+    pub recipes: Vec<Recipe>,
+    pub unmet: Vec<UnmetConstraint>,
+    pub seed: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UnmetConstraint {
+    pub constraint: String,
+    pub target: f64,
+    pub actual: f64,
+}
+
+// One hit of /search, best first. The score is only meant for ordering the hits.
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+    pub recipe: Recipe,
+    pub score: f32,
+    pub snippets: Vec<Snippet>,
+}
+
+// Part of a matching field with the matched words wrapped in <mark>, the rest is HTML escaped.
+#[derive(Debug, Serialize)]
+pub struct Snippet {
+    pub field: String,
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResults {
+    pub hits: Vec<SearchHit>,
+}
+
 // Used as a sort of JWT just verifying that the user has a valid id in the db.
 #[derive(Debug)]
 pub struct UserId(pub String);
 
-// Used to return the error in outcome
+// A logged in user with the admin role, for the changes everyone sees. Nothing hands the role out,
+// it gets set on the user in the database.
 #[derive(Debug)]
-pub enum UsedIdError {
-    Missing,
-    Invalid,
-}
+pub struct Admin;
 
-#[derive(Debug)]
-pub struct ChosenDeleted(pub bool);
+pub const ADMIN_ROLE: &str = "admin";
 
-#[derive(Debug)]
-pub enum ChosenTimeError {
-    Missing,
-    Invalid,
+// What happened when toggling a like. Owners can't like their own recipes.
+#[derive(Debug, PartialEq)]
+pub enum LikeResult {
+    Liked,
+    Unliked,
+    Owned,
+}
+
+// What happened when rating a recipe. Owners can't rate their own recipes either.
+#[derive(Debug, PartialEq)]
+pub enum RatingResult {
+    Rated,
+    Updated,
+    Owned,
 }
 
 // Implementations
+impl MealSlot {
+    // How the slot is stored on the CHOSEN relationship
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MealSlot::Breakfast => "breakfast",
+            MealSlot::Lunch => "lunch",
+            MealSlot::Dinner => "dinner",
+            MealSlot::Snack => "snack",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "breakfast" => Some(MealSlot::Breakfast),
+            "lunch" => Some(MealSlot::Lunch),
+            "dinner" => Some(MealSlot::Dinner),
+            "snack" => Some(MealSlot::Snack),
+            _ => None,
+        }
+    }
+}
+
+impl Default for PickerWeights {
+    fn default() -> Self {
+        PickerWeights {
+            likes: 1.0,
+            recency: 1.0,
+            favorites: 1.0,
+        }
+    }
+}
+
+// What everybody got before the settings existed
+impl Default for PlanSettings {
+    fn default() -> Self {
+        PlanSettings {
+            retention_days: 8,
+            week_start: Weekday::Mon,
+            time_zone: "UTC".to_string(),
+        }
+    }
+}
+
+impl ReviewQuery {
+    pub const DEFAULT_LIMIT: usize = 20;
+    pub const MAX_LIMIT: usize = 100;
+
+    pub fn offset(&self) -> usize {
+        self.offset.unwrap_or(0)
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+            .unwrap_or(Self::DEFAULT_LIMIT)
+            .min(Self::MAX_LIMIT)
+    }
+
+    pub fn page_info(&self, total: usize) -> PageInfo {
+        PageInfo {
+            offset: self.offset(),
+            limit: self.limit(),
+            total,
+        }
+    }
+}
+
+impl RecipeQuery {
+    pub const DEFAULT_LIMIT: usize = 50;
+    pub const MAX_LIMIT: usize = 200;
+
+    pub fn offset(&self) -> usize {
+        self.offset.unwrap_or(0)
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+            .unwrap_or(Self::DEFAULT_LIMIT)
+            .min(Self::MAX_LIMIT)
+    }
+
+    pub fn sort(&self) -> RecipeSort {
+        self.sort.unwrap_or(RecipeSort::Name)
+    }
+
+    pub fn descending(&self) -> bool {
+        self.order == Some(SortOrder::Desc)
+    }
+
+    pub fn page_info(&self, total: usize) -> PageInfo {
+        PageInfo {
+            offset: self.offset(),
+            limit: self.limit(),
+            total,
+        }
+    }
+}
+
+// A full replace resets anything that wasn't sent to the same defaults new_recipe uses.
+impl From<Recipe> for RecipeUpdate {
+    fn from(recipe: Recipe) -> Self {
+        RecipeUpdate {
+            name: Some(recipe.name),
+            public: Some(recipe.public.unwrap_or(false)),
+            steps: Some(recipe.steps.unwrap_or_default()),
+            tipo: Some(recipe.tipo.unwrap_or_default()),
+            calories: Some(recipe.calories.unwrap_or(0)),
+            carbohydrates: Some(recipe.carbohydrates.unwrap_or(0.0)),
+            fat: Some(recipe.fat.unwrap_or(0.0)),
+            protein: Some(recipe.protein.unwrap_or(0.0)),
+            servings: Some(recipe.servings.unwrap_or(0)),
+            yield_unit: Some(recipe.yield_unit.unwrap_or_default()),
+            meal_type: Some(recipe.meal_type.unwrap_or_default()),
+            ingredients: Some(recipe.ingredients.unwrap_or_default()),
+            time: Some(recipe.time.unwrap_or_default()),
+        }
+    }
+}
+
 // Need to implement all these for Recipe so that we can sort it and dedup it later on
 impl Ord for Recipe {
     fn cmp(&self, other: &Self) -> Ordering {
@@ -109,10 +645,10 @@ impl PartialOrd for Recipe {
     }
 }
 impl PartialEq for Recipe {
-    fn eq(&self, other:&Self) -> bool {
+    fn eq(&self, other: &Self) -> bool {
         self.id == other.id
     }
 }
 // This one we leave empty, it's just telling the compiler that we are implementing it but it's
 // not really doing anything and the compiler can't check so it will use PartialEq instead
-impl Eq for Recipe {}
\ No newline at end of file
+impl Eq for Recipe {}
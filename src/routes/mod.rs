@@ -1,2 +1,4 @@
+pub mod plan;
 pub mod recipes;
+pub mod shopping;
 pub mod users;
@@ -0,0 +1,124 @@
+use crate::errors::{ApiError, ApiResult};
+use crate::helpers::plan::{local_today, parse_date, week_start};
+use crate::models::{MealPlanDay, MealPlanWeek, PlanEntry, PlanHistory, PlanSlot, UserId};
+use crate::store::StorePool;
+use chrono::prelude::*;
+use chrono::Duration;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+
+// Weeks from the day the user's plan settings say, today is the one in their time zone.
+async fn this_week(store: &StorePool, u_id: &UserId) -> ApiResult<NaiveDate> {
+    let settings = store.plan_settings(&u_id.0).await?;
+    let today = local_today(&settings, Utc::now().naive_utc());
+    Ok(week_start(&settings, today))
+}
+
+// The week starting on `start`, or the current one
+#[get("/?<start>")]
+pub async fn get_week(
+    store: &State<StorePool>,
+    u_id: UserId,
+    start: Option<String>,
+) -> ApiResult<Json<MealPlanWeek>> {
+    let start = match start {
+        Some(start) => parse_date(&start)?,
+        None => this_week(store, &u_id).await?,
+    };
+    let end = start + Duration::days(7);
+    let meals = store.meal_plan(&u_id.0, start, end).await?;
+
+    let days = start
+        .iter_days()
+        .take(7)
+        .map(|date| MealPlanDay {
+            date,
+            meals: meals
+                .iter()
+                .filter(|meal| meal.date == date)
+                .cloned()
+                .collect(),
+        })
+        .collect();
+    Ok(Json(MealPlanWeek { start, days }))
+}
+
+// What was planned before it expired, the four weeks before this one unless asked otherwise.
+// `to` is not included.
+#[get("/history?<from>&<to>")]
+pub async fn plan_history(
+    store: &State<StorePool>,
+    u_id: UserId,
+    from: Option<String>,
+    to: Option<String>,
+) -> ApiResult<Json<PlanHistory>> {
+    let to = match to {
+        Some(to) => parse_date(&to)?,
+        None => this_week(store, &u_id).await?,
+    };
+    let from = match from {
+        Some(from) => parse_date(&from)?,
+        None => to - Duration::weeks(4),
+    };
+    if from > to {
+        return Err(ApiError::Validation(format!("{} is after {}", from, to)));
+    }
+    let meals = store.plan_history(&u_id.0, from, to).await?;
+    Ok(Json(PlanHistory { from, to, meals }))
+}
+
+#[post("/", format = "application/json", data = "<entry>")]
+pub async fn add_to_plan(
+    store: &State<StorePool>,
+    u_id: UserId,
+    entry: Json<PlanEntry>,
+) -> ApiResult<Status> {
+    store
+        .get_recipe(&u_id.0, &entry.recipe_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Recipe"))?;
+    let created = Utc::now().naive_utc();
+    store
+        .choose_recipes(&u_id.0, &[entry.into_inner()], created)
+        .await?;
+    Ok(Status::Created)
+}
+
+#[put("/<m_id>", format = "application/json", data = "<to>")]
+pub async fn move_meal(
+    store: &State<StorePool>,
+    u_id: UserId,
+    m_id: String,
+    to: Json<PlanSlot>,
+) -> ApiResult<Status> {
+    if !store.move_planned_meal(&u_id.0, &m_id, &to).await? {
+        return Err(ApiError::not_found("Planned meal"));
+    }
+    Ok(Status::NoContent)
+}
+
+#[put("/swap?<first>&<second>")]
+pub async fn swap_meals(
+    store: &State<StorePool>,
+    u_id: UserId,
+    first: String,
+    second: String,
+) -> ApiResult<Status> {
+    if first == second {
+        return Err(ApiError::Validation(
+            "Can't swap a meal with itself".to_string(),
+        ));
+    }
+    if !store.swap_planned_meals(&u_id.0, &first, &second).await? {
+        return Err(ApiError::not_found("Planned meal"));
+    }
+    Ok(Status::NoContent)
+}
+
+#[delete("/day/<date>")]
+pub async fn clear_day(store: &State<StorePool>, u_id: UserId, date: String) -> ApiResult<Status> {
+    let date = parse_date(&date)?;
+    store.clear_plan_day(&u_id.0, date).await?;
+    Ok(Status::NoContent)
+}
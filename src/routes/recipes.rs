@@ -1,595 +1,708 @@
-use crate::helpers::recipes::{format_recipes, get_ingredients_from_db};
-use crate::models::{ChosenDeleted, GraphPool, IdsVec, Recipe, RecipeVec, UserId, RecipeRelationships};
+use crate::errors::{ApiError, ApiResult};
+use crate::helpers::picker::{pick_weekly, weekly_constraints, Weighting, RECENCY_WEEKS};
+use crate::helpers::plan::{local_midnight, local_today, parse_date};
+use crate::helpers::recipes::{ingredient_names, uses_any};
+use crate::helpers::search::{recipe_snippets, search_terms, DEFAULT_SEARCH_LIMIT};
+use crate::helpers::shopping::shopping_list;
+use crate::helpers::units::{common_density, convert_ingredient, scale_recipe};
+use crate::models::{
+    Admin, Allergens, CookEntry, CookLog, CookableRecipes, Density, IdsVec, LikeResult, MealSlot,
+    OnHand, PlanEntry, PlannedMeal, RatingForm, RatingResult, Recipe, RecipeQuery, RecipeUpdate,
+    RecipeVec, ReviewQuery, Reviews, SearchHit, SearchResults, ShoppingList, UnitSystem, UserId,
+    WeeklyQuery, WeeklySuggestion,
+};
+use crate::store::StorePool;
 use chrono::prelude::*;
+use chrono::Duration;
 use itertools::Itertools;
-use neo4rs::*;
-use rand::prelude::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
 use rocket::http::Status;
+use rocket::serde::json::Json;
 use rocket::State;
-use rocket_contrib::json::Json;
-use tokio::runtime::Runtime;
+use std::collections::{HashMap, HashSet};
+use std::slice;
 use uuid::Uuid;
 
+// Everything the request and the user exclude, with the allergens swapped for the ingredients
+// tagged with them. Anonymous requests only get what they asked for.
+async fn excluded_ingredients(
+    store: &StorePool,
+    u_id: Option<&UserId>,
+    exclude: &[String],
+) -> ApiResult<Vec<String>> {
+    let mut names: Vec<String> = exclude
+        .iter()
+        .flat_map(|e| e.split(','))
+        .map(str::to_string)
+        .collect();
+    if let Some(u_id) = u_id {
+        names.extend(store.exclusions(&u_id.0).await?);
+    }
+    let names = ingredient_names(&names);
+    if names.is_empty() {
+        return Ok(names);
+    }
+    store.resolve_exclusions(&names).await
+}
+
+// Converts the ingredient amounts when the request asked for a unit system, the densities only
+// get looked up then.
+async fn convert_units(
+    store: &StorePool,
+    recipes: &mut [Recipe],
+    units: Option<UnitSystem>,
+) -> ApiResult<()> {
+    let system = match units {
+        Some(system) => system,
+        None => return Ok(()),
+    };
+    let names: Vec<String> = recipes
+        .iter()
+        .flat_map(|r| r.ingredients.iter().flatten())
+        .map(|i| i.name.clone())
+        .unique()
+        .collect();
+    let densities = store.ingredient_densities(&names).await?;
+
+    for ingredient in recipes
+        .iter_mut()
+        .flat_map(|r| r.ingredients.iter_mut().flatten())
+    {
+        let density = densities
+            .get(&ingredient.name)
+            .copied()
+            .or_else(|| common_density(&ingredient.name));
+        convert_ingredient(ingredient, system, density);
+    }
+    Ok(())
+}
+
+// For the `servings=` param of the routes fetching a single recipe
+fn scale_servings(recipe: &mut Recipe, servings: Option<u16>) -> ApiResult<()> {
+    match servings {
+        None => Ok(()),
+        Some(0) => Err(ApiError::Validation(
+            "Servings have to be at least 1".to_string(),
+        )),
+        Some(servings) if scale_recipe(recipe, servings) => Ok(()),
+        Some(_) => Err(ApiError::Validation(
+            "This recipe doesn't say how many servings it makes".to_string(),
+        )),
+    }
+}
+
 #[get("/query")]
-pub fn ask_db(rt: State<Runtime>, graph: State<GraphPool>) -> String {
-    let res = rt.block_on(async {
-        let mut result = graph
-            .execute(query("MATCH (n:Recipe) RETURN n"))
-            .await
-            .unwrap();
-
-        let mut res = Vec::new();
-
-        while let Ok(Some(row)) = result.next().await {
-            let node: Node = row.get("n").unwrap();
-            let id = node.id();
-            let labels = node.labels();
-            let name: String = node.get("name").unwrap();
-            res.push(format!(
-                "Got id: {}, labels: {:?}, name: {}",
-                id, labels, name
-            ))
-        }
-        res
-    });
+pub async fn ask_db(store: &State<StorePool>) -> ApiResult<String> {
+    let res: Vec<_> = store
+        .all_recipes()
+        .await?
+        .into_iter()
+        .map(|recipe| format!("Got id: {:?}, name: {}", recipe.id, recipe.name))
+        .collect();
 
-    format!("This is the vec we got {:?}", res)
+    Ok(format!("This is the vec we got {:?}", res))
 }
 
 #[post("/new", format = "application/json", data = "<recipe_form>")]
-pub fn new_recipe(
+pub async fn new_recipe(
     recipe_form: Json<Recipe>,
-    graph: State<GraphPool>,
-    rt: State<Runtime>,
+    store: &State<StorePool>,
     u_id: UserId,
-) -> Status {
-    // Needed to turn any Options returning None into empty strings
-    let empty_string = String::new();
-    let recipe_uuid = Uuid::new_v4().to_string();
-    let recipe_name = &recipe_form.name;
-    let recipe_public = recipe_form.public.as_ref().unwrap_or(&false);
-    let recipe_tipo = recipe_form.tipo.as_ref().unwrap_or(&empty_string);
-    let recipe_calories = recipe_form.calories.as_ref().unwrap_or(&0u16);
-    let recipe_carbs = recipe_form.carbohydrates.as_ref().unwrap_or(&0f32);
-    let recipe_fat = recipe_form.fat.as_ref().unwrap_or(&0f32);
-    let recipe_protein = recipe_form.protein.as_ref().unwrap_or(&0f32);
-    let recipe_servings = recipe_form.servings.as_ref().unwrap_or(&empty_string);
-    let recipe_meal_type = recipe_form.meal_type.as_ref().unwrap_or(&empty_string);
-    let recipe_time = recipe_form.time.as_ref().unwrap_or(&empty_string);
-    let mut steps_string = String::new();
-
-    if recipe_form.steps.is_some() {
-        for (i, step) in recipe_form.steps.as_ref().unwrap().iter().enumerate() {
-            steps_string.push_str(format!("{}. {}\n", i + 1, step).as_str())
-        }
+) -> ApiResult<Status> {
+    store.create_recipe(&u_id.0, &recipe_form).await?;
+    Ok(Status::Created)
+}
+
+// Both edit routes end up here. Only the user that OWNS the recipe can change it, anyone else
+// gets a 404 same as if the recipe didn't exist.
+async fn edit_recipe(
+    store: &StorePool,
+    u_id: UserId,
+    r_id: String,
+    update: RecipeUpdate,
+) -> ApiResult<Status> {
+    if store.update_recipe(&u_id.0, &r_id, &update).await? {
+        return Ok(Status::Ok);
     }
+    Err(ApiError::not_found("Recipe"))
+}
 
-    // Using this instead of neo4rs query().param() pattern since type conversions for bolt
-    // protocol are funky right now. It only converts Strings properly, anything else throws the
-    // trait Into not implemented for this type.
-    // Any strings need to be in quotations. other types and names of params can be w/o quotes
-    let param_string = format!(
-        "id: \"{id}\", name: \"{name}\", public: {public}, tipo: \"{tipo}\", steps:
-        \"{steps}\", calories: {calories}, carbohydrates: {carbs}, fat: {fat}, \
-        protein: {protein}, servings: \"{servings}\", meal_type: \"{meal_type}\", time: \
-        \"{time}\"",
-        id = recipe_uuid,
-        name = recipe_name,
-        public = recipe_public,
-        tipo = &recipe_tipo,
-        steps = steps_string,
-        calories = recipe_calories,
-        carbs = recipe_carbs,
-        fat = recipe_fat,
-        protein = recipe_protein,
-        servings = recipe_servings,
-        meal_type = recipe_meal_type,
-        time = recipe_time
-    );
+#[put("/<r_id>", format = "application/json", data = "<recipe_form>")]
+pub async fn replace_recipe(
+    recipe_form: Json<Recipe>,
+    store: &State<StorePool>,
+    u_id: UserId,
+    r_id: String,
+) -> ApiResult<Status> {
+    edit_recipe(
+        store,
+        u_id,
+        r_id,
+        RecipeUpdate::from(recipe_form.into_inner()),
+    )
+    .await
+}
 
-    // Using this runtime since rocket runs synchronously right now. That will change with rocket
-    // 0.5 but until then we need this specific tokio runtime to run any async tasks using the
-    // neo4rs driver. Hope we can change driver when a better one comes.
-    rt.block_on(async {
-        graph
-            .run(
-                // Triple {{{}}} because {{}} turns into "{}"
-                query(
-                    format!(
-                        "MATCH (u:User) WHERE u.id = $uid \
-                    MERGE (u)-[:OWNS]->(:Recipe {{{}}})",
-                        param_string
-                    )
-                    .as_str(),
-                )
-                .param("uid", u_id.0),
-            )
-            .await
-            .expect("Couldn't add the recipe");
-
-        if recipe_form.ingredients.is_some() {
-            let ingredients_vec = recipe_form.ingredients.as_ref().unwrap();
-            for ingredient in ingredients_vec {
-                let ingredient_tipo = ingredient.tipo.as_ref().unwrap_or(&empty_string);
-
-                graph
-                    .run(
-                        query("MERGE (:Ingredient {name: $name, tipo: $tipo})")
-                            .param("name", ingredient.name.to_lowercase().clone())
-                            .param("tipo", ingredient_tipo.to_lowercase().clone()),
-                    )
-                    .await
-                    .expect("Couldn't add the ingredients");
-
-                graph
-                    .run(
-                        query(
-                            "MATCH (i:Ingredient {name: $name}), (r:Recipe {id: $id}) \
-                        CREATE (r)-[:USES {amount: $amount}]->(i)",
-                        )
-                        .param("name", ingredient.name.to_lowercase().clone())
-                        .param("id", recipe_uuid.clone())
-                        .param("amount", ingredient.amount.clone()),
-                    )
-                    .await
-                    .expect("Couldn't create the relationship")
-            }
-        }
-    });
-    println!("{:?}", &recipe_form);
-    Status::Created
+#[patch("/<r_id>", format = "application/json", data = "<recipe_form>")]
+pub async fn update_recipe(
+    recipe_form: Json<RecipeUpdate>,
+    store: &State<StorePool>,
+    u_id: UserId,
+    r_id: String,
+) -> ApiResult<Status> {
+    edit_recipe(store, u_id, r_id, recipe_form.into_inner()).await
 }
 
-#[get("/weekly?<amount>")]
-pub fn random_recipes(
-    rt: State<Runtime>,
-    graph: State<GraphPool>,
-    usr: UserId,
-    amount: Option<usize>,
-) -> Json<RecipeVec> {
-    let mut rng = &mut rand::thread_rng();
-    let recipes_vector = rt.block_on(async {
-        let mut result = graph
-            .execute(
-                query("MATCH (r:Recipe)-[:OWNS|:LIKES]-(u:User) WHERE u.id = $id RETURN r")
-                    .param("id", usr.0),
-            )
-            .await
-            .expect("Error fetching recipes");
-
-        let mut nodes_vector = Vec::new();
-
-        while let Ok(Some(row)) = result.next().await {
-            nodes_vector.push(format_recipes(row))
-        }
+// What was in the plan or its history in the last `weeks` weeks, plus what's already planned for
+// the coming one.
+async fn planned_meals_around(
+    store: &StorePool,
+    u_id: &UserId,
+    today: NaiveDate,
+    weeks: u16,
+) -> ApiResult<Vec<PlannedMeal>> {
+    let from = today - Duration::weeks(weeks as i64);
+    let to = today + Duration::weeks(1);
+    let mut meals = store.plan_history(&u_id.0, from, to).await?;
+    meals.extend(store.meal_plan(&u_id.0, from, to).await?);
+    Ok(meals)
+}
 
-        for recipe in &mut nodes_vector {
-            get_ingredients_from_db(graph.clone(), recipe).await;
+// Everything the weighted pick needs to know about the candidates besides the recipes themselves.
+// Recency goes by the last time the recipe was planned or cooked, whatever came later.
+async fn picker_weighting(
+    store: &StorePool,
+    u_id: &UserId,
+    today: NaiveDate,
+    meals: &[PlannedMeal],
+    candidates: &[Recipe],
+) -> ApiResult<Weighting> {
+    let r_ids: Vec<String> = candidates
+        .iter()
+        .filter_map(|recipe| recipe.id)
+        .map(|id| id.to_string())
+        .collect();
+    let from = today - Duration::weeks(RECENCY_WEEKS as i64);
+    let cooked = store
+        .cook_log(
+            &u_id.0,
+            from.and_time(NaiveTime::MIN),
+            Utc::now().naive_utc(),
+        )
+        .await?;
+    let eaten = meals.iter().map(|meal| (meal.recipe.id, meal.date)).chain(
+        cooked
+            .iter()
+            .map(|cooked| (cooked.recipe.id, cooked.cooked.at.date())),
+    );
+    let mut last_eaten: HashMap<Uuid, NaiveDate> = HashMap::new();
+    for (id, date) in eaten {
+        if let Some(id) = id {
+            let last = last_eaten.entry(id).or_insert(date);
+            *last = (*last).max(date);
         }
-        nodes_vector
-    });
-    let amount_of_recipes = amount.unwrap_or(7);
-    let shuffled_recipes = recipes_vector
-        .choose_multiple(&mut rng, amount_of_recipes)
-        .cloned()
+    }
+    Ok(Weighting {
+        weights: store.picker_weights(&u_id.0).await?,
+        likes: store.like_counts(&r_ids).await?,
+        last_eaten,
+        favorites: store.favorites(&u_id.0).await?.into_iter().collect(),
+        today,
+    })
+}
+
+// A suggestion for the week from what the user OWNS or LIKES, see WeeklyQuery for the constraints
+// it can be asked to meet.
+#[get("/weekly?<query..>")]
+pub async fn random_recipes(
+    store: &State<StorePool>,
+    usr: UserId,
+    query: WeeklyQuery,
+) -> ApiResult<Json<WeeklySuggestion>> {
+    let excluded = excluded_ingredients(store, Some(&usr), &query.exclude).await?;
+    let settings = store.plan_settings(&usr.0).await?;
+    let today = local_today(&settings, Utc::now().naive_utc());
+    let repeat_weeks = query.no_repeat_weeks.unwrap_or(0);
+    let meals = planned_meals_around(store, &usr, today, repeat_weeks.max(RECENCY_WEEKS)).await?;
+    let recent: HashSet<Uuid> = if repeat_weeks == 0 {
+        HashSet::new()
+    } else {
+        let from = today - Duration::weeks(repeat_weeks as i64);
+        meals
+            .iter()
+            .filter(|meal| meal.date >= from)
+            .filter_map(|meal| meal.recipe.id)
+            .collect()
+    };
+    let constraints = weekly_constraints(&query, recent)?;
+    let mut recipes_vector = store.user_recipes(&usr.0).await?;
+    recipes_vector.retain(|recipe| !uses_any(recipe, &excluded));
+
+    // Pins are kept even when the exclusions would leave them out, the user asked for them
+    let pin_ids: Vec<String> = query
+        .pin
+        .iter()
+        .flat_map(|pin| pin.split(','))
+        .map(|r_id| r_id.trim().to_string())
+        .filter(|r_id| !r_id.is_empty())
+        .unique()
         .collect();
+    let mut pinned: Vec<Recipe> = Vec::new();
+    for r_id in &pin_ids {
+        let recipe = store
+            .get_recipe(&usr.0, r_id)
+            .await?
+            .ok_or_else(|| ApiError::not_found("Recipe"))?;
+        pinned.push(recipe);
+    }
 
-    Json(RecipeVec {
-        recipes: shuffled_recipes,
-        rels: None
-    })
+    // Kept under 2^32 so it survives being a javascript number
+    let seed = query
+        .seed
+        .unwrap_or_else(|| u64::from(rand::random::<u32>()));
+    let mut rng = StdRng::seed_from_u64(seed);
+    let weighting = picker_weighting(store, &usr, today, &meals, &recipes_vector).await?;
+    let (recipes, unmet) = pick_weekly(recipes_vector, pinned, &constraints, &weighting, &mut rng);
+    Ok(Json(WeeklySuggestion {
+        recipes,
+        unmet,
+        seed,
+    }))
 }
 
-// TODO: Change the return type from string to Outcome.
+// The picked recipes go in the meal plan as dinners, one a day starting today in the user's time
+// zone. /plan has the routes to move them around afterwards.
 #[post("/weekly", format = "application/json", data = "<data>")]
-pub fn choose_recipes(
-    graph: State<GraphPool>,
-    rt: State<Runtime>,
+pub async fn choose_recipes(
+    store: &State<StorePool>,
     usr: UserId,
     data: Json<IdsVec>,
-) -> Status {
-    let u_id = usr.0;
-    let date = Utc::now().naive_utc();
-    rt.block_on(async {
-        for recipe_id in &data.ids {
-            graph
-                .run(
-                    query(
-                        "MATCH (u:User {id: $id}), (r:Recipe {id: $rid}) \
-                    CREATE (u)-[:CHOSEN {created: $exp}]->(r)",
-                    )
-                    .param("id", u_id.as_str())
-                    .param("rid", recipe_id.as_str())
-                    .param("exp", date),
-                )
-                .await
-                .expect("Couldn't query graph");
-        }
-    });
-    Status::Created
+) -> ApiResult<Status> {
+    let now = Utc::now().naive_utc();
+    let settings = store.plan_settings(&usr.0).await?;
+    let entries: Vec<PlanEntry> = data
+        .ids
+        .iter()
+        .zip(local_today(&settings, now).iter_days())
+        .map(|(r_id, date)| PlanEntry {
+            recipe_id: r_id.clone(),
+            date,
+            slot: MealSlot::Dinner,
+        })
+        .collect();
+    store.choose_recipes(&usr.0, &entries, now).await?;
+    Ok(Status::Created)
 }
 
+// Everything in the meal plan, expired days are moved out of it by the background task in main
 #[get("/chosen")]
-pub fn chosen_recipes(
-    graph: State<GraphPool>,
-    rt: State<Runtime>,
-    usr: UserId,
-    deleted: ChosenDeleted,
-) -> Json<RecipeVec> {
-    let u_id = usr.0;
-    if deleted.0 {
-        return Json(RecipeVec {
-            recipes: Vec::new(),
-            rels: None
-        });
-    }
-    let recipes_vector = rt.block_on(async {
-        let mut recipes = graph
-            .execute(
-                query("MATCH (u:User)-[:CHOSEN]-(r:Recipe) WHERE u.id = $id RETURN r")
-                    .param("id", u_id),
-            )
-            .await
-            .expect("Couldn't query graph");
-
-        let mut recipes_vector = Vec::new();
-
-        while let Ok(Some(row)) = recipes.next().await {
-            recipes_vector.push(format_recipes(row))
-        }
-        for recipe in &mut recipes_vector {
-            get_ingredients_from_db(graph.clone(), recipe).await;
-        }
-        recipes_vector
-    });
+pub async fn chosen_recipes(store: &State<StorePool>, usr: UserId) -> ApiResult<Json<RecipeVec>> {
+    let recipes_vector = store.chosen_recipes(&usr.0).await?;
 
-    Json(RecipeVec {
+    Ok(Json(RecipeVec {
         recipes: recipes_vector,
-        rels: None
-    })
+        rels: None,
+        page: None,
+    }))
+}
+
+// Everything the chosen recipes need, merged per ingredient and grouped by tipo
+#[get("/chosen/shopping-list")]
+pub async fn chosen_shopping_list(
+    store: &State<StorePool>,
+    usr: UserId,
+) -> ApiResult<Json<ShoppingList>> {
+    let recipes = store.chosen_recipes(&usr.0).await?;
+    Ok(Json(shopping_list(&recipes)))
 }
 
-#[get("/ingredient/<ingredient>")]
-pub fn recipes_by_ingredient(
-    rt: State<Runtime>,
-    graph: State<GraphPool>,
+// The listings below take the paging, sorting and filters in RecipeQuery as query params.
+#[get("/ingredient/<ingredient>?<list..>")]
+pub async fn recipes_by_ingredient(
+    store: &State<StorePool>,
     ingredient: String,
     u_id: UserId,
-) -> Json<RecipeVec> {
-    let u_id = u_id.0;
-    let recipe_vector = rt.block_on(async {
-        let mut user_recipes = graph
-            .execute(
-                query(
-                    "MATCH (u:User)-[:OWNS]->(r:Recipe)-[:USES]->(i:Ingredient) \
-                WHERE u.id = $id AND i.name = $ing \
-                RETURN r",
-                )
-                .param("id", u_id.clone())
-                .param("ing", ingredient.clone()),
-            )
-            .await
-            .expect("Couldn't run query");
-
-        let mut recipes_vec = Vec::new();
-
-        while let Ok(Some(row)) = user_recipes.next().await {
-            recipes_vec.push(format_recipes(row))
-        }
-
-        let mut public_recipes = graph
-            .execute(
-                query(
-                    "MATCH (r:Recipe)-[:USES]->(i:Ingredient) \
-                WHERE r.public = true AND i.name = $ing \
-                RETURN r",
-                )
-                .param("ing", ingredient.clone()),
-            )
-            .await
-            .expect("Couldn't run query for public");
-
-        while let Ok(Some(row)) = public_recipes.next().await {
-            recipes_vec.push(format_recipes(row))
-        }
+    mut list: RecipeQuery,
+) -> ApiResult<Json<RecipeVec>> {
+    list.exclude = excluded_ingredients(store, Some(&u_id), &list.exclude).await?;
+    let mut recipes = store
+        .recipes_by_ingredient(&u_id.0, &ingredient, &list)
+        .await?;
+    convert_units(store, &mut recipes.recipes, list.units).await?;
+    Ok(Json(recipes))
+}
 
-        let mut unique_recipes = recipes_vec
-            .iter()
-            .unique_by(|r| &r.id)
-            .cloned()
-            .collect::<Vec<_>>();
-        for recipe in &mut unique_recipes {
-            get_ingredients_from_db(graph.clone(), recipe).await;
-        }
-        unique_recipes
-    });
+#[get("/list?<list..>")]
+pub async fn recipe_list(
+    store: &State<StorePool>,
+    usr: UserId,
+    mut list: RecipeQuery,
+) -> ApiResult<Json<RecipeVec>> {
+    list.exclude = excluded_ingredients(store, Some(&usr), &list.exclude).await?;
+    let mut recipes = store.recipe_list(&usr.0, &list).await?;
+    convert_units(store, &mut recipes.recipes, list.units).await?;
+    Ok(Json(recipes))
+}
 
-    Json(RecipeVec {
-        recipes: recipe_vector,
-        rels: None
-    })
+// Searches the names, tipos, steps and ingredient names of everything the user can see, eg.
+// /search?q=tomato%20soup
+#[get("/search?<q>&<limit>&<exclude>")]
+pub async fn search_recipes(
+    store: &State<StorePool>,
+    u_id: UserId,
+    q: String,
+    limit: Option<usize>,
+    exclude: Vec<String>,
+) -> ApiResult<Json<SearchResults>> {
+    let terms = search_terms(&q);
+    if terms.is_empty() {
+        return Err(ApiError::Validation(
+            "The search needs at least one word".to_string(),
+        ));
+    }
+    let limit = limit
+        .unwrap_or(DEFAULT_SEARCH_LIMIT)
+        .min(RecipeQuery::MAX_LIMIT);
+
+    let excluded = excluded_ingredients(store, Some(&u_id), &exclude).await?;
+
+    let hits = store
+        .search_recipes(&u_id.0, &terms, &excluded, limit)
+        .await?
+        .into_iter()
+        .map(|(recipe, score)| SearchHit {
+            snippets: recipe_snippets(&recipe, &terms),
+            recipe,
+            score,
+        })
+        .collect();
+    Ok(Json(SearchResults { hits }))
 }
 
-#[get("/list")]
-pub fn recipe_list(graph: State<GraphPool>, rt: State<Runtime>, usr: UserId) -> Json<RecipeVec> {
-    let recipes_vec = rt.block_on(async {
-        let mut owned_recipes = graph
-            .execute(
-                query(
-                    "MATCH (u:User)-[c:OWNS|LIKES]->(r:Recipe) \
-            WHERE u.id = $u_id \
-            RETURN r, c",
-                )
-                .param("u_id", usr.0.clone()),
-            )
-            .await
-            .expect("Error getting the recipes!");
-
-        let mut recipes_vector = Vec::new();
-        // let mut rel_vector = Vec::new();
-        let mut rel_struct = RecipeRelationships {owns: Vec::new(), likes: Vec::new() };
-
-        while let Ok(Some(row)) = owned_recipes.next().await {
-            let relationship_node = row.get::<Relation>("c").unwrap();
-            let formatted_recipe = format_recipes(row);
-            if &relationship_node.typ() == "OWNS" {
-                rel_struct.owns.push(formatted_recipe.id.unwrap().clone())
-            }
-            if &relationship_node.typ() == "LIKES" {
-                rel_struct.likes.push(formatted_recipe.id.unwrap().clone())
-            }
-            // rel_vector.push((formatted_recipe.id.unwrap().clone(), relationship_node.typ()));
-            recipes_vector.push(formatted_recipe);
-        }
+// What can be cooked with the ingredients sent, eg. {"ingredients": ["tomato", "pasta"]}. Every
+// recipe comes with the ingredients still missing for it.
+#[post(
+    "/cook?<limit>&<exclude>",
+    format = "application/json",
+    data = "<on_hand>"
+)]
+pub async fn cookable_recipes(
+    store: &State<StorePool>,
+    u_id: UserId,
+    on_hand: Json<OnHand>,
+    limit: Option<usize>,
+    exclude: Vec<String>,
+) -> ApiResult<Json<CookableRecipes>> {
+    let names = ingredient_names(&on_hand.ingredients);
+    if names.is_empty() {
+        return Err(ApiError::Validation(
+            "Send at least one ingredient".to_string(),
+        ));
+    }
+    let limit = limit
+        .unwrap_or(RecipeQuery::DEFAULT_LIMIT)
+        .min(RecipeQuery::MAX_LIMIT);
 
-        // let mut liked_recipes = graph.execute(
-        //     query(
-        //         "MATCH (u:User)-[l:LIKES]->(r:Recipe) \
-        //         WHERE u.id = $u_id"
-        //     )
-        // )
-        // .await
-        // .expect("Couldn't get the liked recipes");
-
-        let mut public_recipes = graph.execute(
-            query(
-                "MATCH (r:Recipe)-[:OWNS]-(u:User) \
-                WHERE r.public = true AND NOT u.id = $u_id \
-                RETURN r"
-            )
-            .param("u_id", usr.0.clone())
-        )
-        .await
-        .expect("Error getting public recipes!");
+    let excluded = excluded_ingredients(store, Some(&u_id), &exclude).await?;
 
-        while let Ok(Some(row)) = public_recipes.next().await {
-            recipes_vector.push(format_recipes(row))
-        }
+    let recipes = store
+        .cookable_recipes(&u_id.0, &names, &excluded, limit)
+        .await?;
+    Ok(Json(CookableRecipes { recipes }))
+}
 
-        recipes_vector.sort();
-        recipes_vector.dedup();
+#[delete("/remove/<r_id>")]
+pub async fn remove_recipe(
+    store: &State<StorePool>,
+    u_id: UserId,
+    r_id: String,
+) -> ApiResult<Status> {
+    store.remove_recipe(&u_id.0, &r_id).await?;
+    Ok(Status::NoContent)
+}
+
+// These take `servings=` to scale the amounts and the nutrition, and `units=metric|imperial` to
+// convert the amounts. Scaling happens first.
+#[get("/<r_id>?<units>&<servings>")]
+pub async fn get_recipe(
+    store: &State<StorePool>,
+    u_id: UserId,
+    r_id: String,
+    units: Option<UnitSystem>,
+    servings: Option<u16>,
+) -> ApiResult<Json<Recipe>> {
+    let mut recipe = store
+        .get_recipe(&u_id.0, &r_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Recipe"))?;
+    scale_servings(&mut recipe, servings)?;
+    convert_units(store, slice::from_mut(&mut recipe), units).await?;
+    Ok(Json(recipe))
+}
 
-        for recipe in &mut recipes_vector {
-            get_ingredients_from_db(graph.clone(), recipe).await;
+#[get("/public/<r_id>?<units>&<servings>")]
+pub async fn get_public_recipe(
+    store: &State<StorePool>,
+    r_id: String,
+    units: Option<UnitSystem>,
+    servings: Option<u16>,
+) -> ApiResult<Json<Recipe>> {
+    match store.get_public_recipe(&r_id).await? {
+        Some(mut recipe) => {
+            scale_servings(&mut recipe, servings)?;
+            convert_units(store, slice::from_mut(&mut recipe), units).await?;
+            Ok(Json(recipe))
         }
-        (recipes_vector, rel_struct)
-    });
+        None => Err(ApiError::Unauthorized(
+            "This recipe isn't public".to_string(),
+        )),
+    }
+}
 
-    Json(RecipeVec {
-        recipes: recipes_vec.0,
-        rels: Option::from(recipes_vec.1)
-    })
+#[get("/share?<r_id>&<servings>")]
+pub async fn share_recipe(
+    store: &State<StorePool>,
+    r_id: String,
+    servings: Option<u16>,
+) -> ApiResult<Json<Recipe>> {
+    let mut recipe = store
+        .find_recipe(&r_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Recipe"))?;
+    scale_servings(&mut recipe, servings)?;
+    Ok(Json(recipe))
 }
 
-#[delete("/remove/<r_id>")]
-pub fn remove_recipe(
-    rt: State<Runtime>,
-    graph: State<GraphPool>,
+// Logs that the user cooked a recipe they can see, at whatever time the body says or now
+#[post("/<r_id>/cooked", format = "application/json", data = "<cooked>")]
+pub async fn cook_recipe(
+    store: &State<StorePool>,
     u_id: UserId,
     r_id: String,
-) -> Status {
-    // let u_id = u_id.0;
-    rt.block_on(async {
-        graph
-            .run(
-                query(
-                    "MATCH (u:User)-[:OWNS]->(r:Recipe) \
-                WHERE u.id = $u_id AND r.id = $r_id \
-                DETACH DELETE r",
-                )
-                .param("u_id", u_id.0.clone())
-                .param("r_id", r_id.clone()),
-            )
-            .await
-            .expect("Couldn't run query");
-    });
-    Status::NoContent
-}
-
-#[get("/<r_id>")]
-pub fn get_recipe(
-    graph: State<GraphPool>,
-    rt: State<Runtime>,
+    cooked: Json<CookEntry>,
+) -> ApiResult<Status> {
+    let mut cooked = cooked.into_inner();
+    if cooked
+        .rating
+        .is_some_and(|rating| !(1..=5).contains(&rating))
+    {
+        return Err(ApiError::Validation(
+            "The rating goes from 1 to 5".to_string(),
+        ));
+    }
+    if cooked.servings == Some(0) {
+        return Err(ApiError::Validation(
+            "Servings should be at least 1".to_string(),
+        ));
+    }
+    cooked.notes = cooked
+        .notes
+        .map(|notes| notes.trim().to_string())
+        .filter(|notes| !notes.is_empty());
+    store
+        .get_recipe(&u_id.0, &r_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Recipe"))?;
+    store.log_cooked(&u_id.0, &r_id, &cooked).await?;
+    Ok(Status::Created)
+}
+
+// What the user cooked, the four weeks up to today unless asked otherwise. The dates are days in
+// the user's time zone and `to` is not included.
+#[get("/cooked?<from>&<to>")]
+pub async fn cook_log(
+    store: &State<StorePool>,
+    u_id: UserId,
+    from: Option<String>,
+    to: Option<String>,
+) -> ApiResult<Json<CookLog>> {
+    let settings = store.plan_settings(&u_id.0).await?;
+    let to = match to {
+        Some(to) => parse_date(&to)?,
+        None => local_today(&settings, Utc::now().naive_utc()) + Duration::days(1),
+    };
+    let from = match from {
+        Some(from) => parse_date(&from)?,
+        None => to - Duration::weeks(4),
+    };
+    if from > to {
+        return Err(ApiError::Validation(format!("{} is after {}", from, to)));
+    }
+    let cooked = store
+        .cook_log(
+            &u_id.0,
+            local_midnight(&settings, from),
+            local_midnight(&settings, to),
+        )
+        .await?;
+    Ok(Json(CookLog { from, to, cooked }))
+}
+
+#[delete("/weeklyreset")]
+pub async fn reset_all_chosen(store: &State<StorePool>, u_id: UserId) -> ApiResult<Status> {
+    store.reset_chosen(&u_id.0).await?;
+    Ok(Status::NoContent)
+}
+
+// Favorites weigh more in the weekly suggestion, see PickerWeights. Any recipe the user can see
+// can be one.
+#[put("/favorite?<r_id>")]
+pub async fn favorite_recipe(
+    store: &State<StorePool>,
     u_id: UserId,
     r_id: String,
-) -> Json<Recipe> {
-    let recipe = rt.block_on(async {
-        let mut res = graph
-            .execute(
-                query(
-                    "MATCH (u:User)-[:OWNS]->(r:Recipe) \
-               WHERE (u.id = $u_id AND r.id = $r_id) OR (r.id = $r_id AND r.public = true) \
-               RETURN r",
-                )
-                .param("u_id", u_id.0.clone())
-                .param("r_id", r_id.clone()),
-            )
-            .await
-            .expect("Error getting the recipe");
-        let row = res.next().await;
-        let mut recipe = format_recipes(row.expect("Error in row").expect("Empty row"));
-        get_ingredients_from_db(graph.clone(), &mut recipe).await;
-        recipe
-    });
-    Json(recipe)
-}
-
-#[get("/public/<r_id>")]
-pub fn get_public_recipe(
-    graph: State<GraphPool>,
-    rt: State<Runtime>,
+) -> ApiResult<Status> {
+    store
+        .get_recipe(&u_id.0, &r_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Recipe"))?;
+    if store.toggle_favorite(&u_id.0, &r_id).await? {
+        Ok(Status::Created)
+    } else {
+        Ok(Status::Accepted)
+    }
+}
+
+// One rating per user and recipe, rating it again replaces the stars and the review. Owners
+// can't rate their own recipes so only public ones end up rated.
+#[put("/<r_id>/rating", format = "application/json", data = "<rating>")]
+pub async fn rate_recipe(
+    store: &State<StorePool>,
+    u_id: UserId,
     r_id: String,
-) -> std::result::Result<Json<Recipe>, Status> {
-    let recipe = rt.block_on(async {
-        let mut res = graph
-            .execute(
-                query(
-                    "MATCH (r:Recipe) \
-               WHERE r.id = $r_id AND r.public = true \
-               RETURN r",
-                )
-                .param("r_id", r_id.clone()),
-            )
-            .await
-            .expect("Error getting the recipe");
-        let row = res.next().await;
-        let row_option = row.expect("Error in row");
-        if row_option.is_none() {
-            return Err(Status::Unauthorized)
-        }
-        let mut recipe = format_recipes(row_option.unwrap());
-        get_ingredients_from_db(graph.clone(), &mut recipe).await;
-        Ok(recipe)
-    });
-    if recipe.is_err() {
-        return Err(recipe.err().unwrap())
+    rating: Json<RatingForm>,
+) -> ApiResult<Status> {
+    let mut rating = rating.into_inner();
+    if !(1..=5).contains(&rating.stars) {
+        return Err(ApiError::Validation("The stars go from 1 to 5".to_string()));
+    }
+    rating.review = rating
+        .review
+        .map(|review| review.trim().to_string())
+        .filter(|review| !review.is_empty());
+    store
+        .get_recipe(&u_id.0, &r_id)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Recipe"))?;
+    let rated = store
+        .rate_recipe(&u_id.0, &r_id, &rating, Utc::now().naive_utc())
+        .await?;
+    match rated {
+        RatingResult::Rated => Ok(Status::Created),
+        RatingResult::Updated => Ok(Status::Ok),
+        RatingResult::Owned => Err(ApiError::Forbidden(
+            "You can't rate your own recipe".to_string(),
+        )),
     }
-    Ok(Json(recipe.unwrap()))
-}
-
-#[get("/share?<r_id>")]
-pub fn share_recipe(graph: State<GraphPool>, rt: State<Runtime>, r_id: String) -> Json<Recipe> {
-    let recipe = rt.block_on(async {
-        let mut res = graph
-            .execute(
-                query(
-                    "MATCH (r:Recipe) \
-               WHERE r.id = $r_id \
-               RETURN r",
-                )
-                .param("r_id", r_id.clone()),
-            )
-            .await
-            .expect("Error getting the recipe");
-        let row = res.next().await;
-        let mut recipe = format_recipes(row.expect("Error in row").expect("Empty row"));
-        get_ingredients_from_db(graph.clone(), &mut recipe).await;
-        recipe
-    });
-    Json(recipe)
 }
 
-#[delete("/weeklyreset")]
-pub fn reset_all_chosen(rt: State<Runtime>, graph: State<GraphPool>, u_id: UserId) -> Status {
-    rt.block_on(async {
-        graph
-            .run(
-                query(
-                    "MATCH (u:User)-[c:CHOSEN]->() \
-                WHERE u.id = $u_id \
-                DETACH DELETE c",
-                )
-                .param("u_id", u_id.0.clone()),
-            )
-            .await
-            .expect("Couldn't delete chosen relationships");
-    });
-    Status::NoContent
+// Ranked after /remove/<r_id> which would match the same paths
+#[delete("/<r_id>/rating", rank = 2)]
+pub async fn remove_rating(
+    store: &State<StorePool>,
+    u_id: UserId,
+    r_id: String,
+) -> ApiResult<Status> {
+    store.remove_rating(&u_id.0, &r_id).await?;
+    Ok(Status::NoContent)
+}
+
+// The ratings that came with a review, anyone can read them same as the recipe
+#[get("/public/<r_id>/reviews?<page..>")]
+pub async fn recipe_reviews(
+    store: &State<StorePool>,
+    r_id: String,
+    page: ReviewQuery,
+) -> ApiResult<Json<Reviews>> {
+    if store.get_public_recipe(&r_id).await?.is_none() {
+        return Err(ApiError::Unauthorized(
+            "This recipe isn't public".to_string(),
+        ));
+    }
+    Ok(Json(store.reviews(&r_id, &page).await?))
 }
 
 #[put("/like?<r_id>")]
-pub fn like_recipe(
-    rt: State<Runtime>,
-    graph: State<GraphPool>,
+pub async fn like_recipe(
+    store: &State<StorePool>,
     u_id: UserId,
     r_id: String,
-) -> Status {
-    rt.block_on(async {
-        let mut recipe_stream =
-            graph.execute(
-                query(
-                    "MATCH (r:Recipe)-[c:LIKES|OWNS]-(u:User) WHERE u.id = $u_id AND r.id = $r_id \
-                RETURN r, c",
-                )
-                    .param("u_id", u_id.0.clone())
-                    .param("r_id", r_id.clone()),
-            )
-                .await
-                .expect("Couldn't find that recipe");
-
-        let recipe_row_result = recipe_stream.next().await;
-        let recipe_row = recipe_row_result.expect("Error fetching row");
-        if recipe_row.is_none() {
-            graph.run(
-                query(
-                    "MATCH (u:User), (r:Recipe) \
-                    WHERE u.id = $u_id AND (r.id = $r_id AND r.public = true) \
-                    MERGE (u)-[:LIKES]->(r)"
-                )
-                    .param("u_id", u_id.0.clone())
-                    .param("r_id", r_id.clone()),
-            )
-                .await
-                .expect("Couldn't like the recipe");
-            return Status::Created;
-        }
-        let relationship_node = recipe_row.unwrap().get::<Relation>("c").unwrap();
-        if &relationship_node.typ() == "OWNS" {
-            return Status::NoContent;
-        }
-        graph.run(
-            query(
-                "MATCH (u:User)-[l:LIKES]->(r:Recipe) \
-                WHERE u.id = $u_id AND r.id = $r_id \
-                DETACH DELETE l"
-            )
-                .param("u_id", u_id.0.clone())
-                .param("r_id", r_id.clone()),
-        )
-            .await
-            .expect("Couldn't unlike the recipe");
-        Status::Accepted
+) -> ApiResult<Status> {
+    Ok(match store.like_recipe(&u_id.0, &r_id).await? {
+        LikeResult::Liked => Status::Created,
+        LikeResult::Unliked => Status::Accepted,
+        LikeResult::Owned => Status::NoContent,
     })
 }
 
-#[get("/public")]
-pub fn public_recipes(
-    graph: State<GraphPool>,
-    rt: State<Runtime>,
-) -> Json<RecipeVec> {
-    let recipes_vec = rt.block_on(async {
-       let mut res = graph.execute(
-           query(
-               "MATCH (r:Recipe) WHERE r.public = true RETURN r"
-           )
-       )
-       .await
-       .expect("Error getting public recipe list");
-
-        let mut recipes_vector = Vec::new();
-
-        while let Ok(Some(row)) = res.next().await {
-            recipes_vector.push(format_recipes(row))
-        }
+// Logged in users also get their own exclusions applied
+#[get("/public?<list..>")]
+pub async fn public_recipes(
+    store: &State<StorePool>,
+    u_id: Option<UserId>,
+    mut list: RecipeQuery,
+) -> ApiResult<Json<RecipeVec>> {
+    list.exclude = excluded_ingredients(store, u_id.as_ref(), &list.exclude).await?;
+    let mut recipes = store.public_recipes(&list).await?;
+    convert_units(store, &mut recipes.recipes, list.units).await?;
+    Ok(Json(recipes))
+}
 
-        for recipe in &mut recipes_vector {
-            get_ingredients_from_db(graph.clone(), recipe).await;
-        }
-        recipes_vector
-    });
+// Allergens are shared by every recipe using the ingredient, eg. tagging "almond" with "nuts"
+// hides it from anyone excluding nuts. That touches everyone's recipes so only admins can
+// change them.
+#[put(
+    "/ingredient/<ingredient>/allergens",
+    format = "application/json",
+    data = "<allergens>"
+)]
+pub async fn set_allergens(
+    store: &State<StorePool>,
+    _admin: Admin,
+    ingredient: String,
+    allergens: Json<Allergens>,
+) -> ApiResult<Status> {
+    let ingredient = ingredient.to_lowercase();
+    let allergens = ingredient_names(&allergens.allergens);
+    if !store.set_allergens(&ingredient, &allergens).await? {
+        return Err(ApiError::not_found("Ingredient"));
+    }
+    Ok(Status::NoContent)
+}
 
-    Json(RecipeVec {
-        recipes: recipes_vec,
-        rels: None
-    })
+#[get("/ingredient/<ingredient>/allergens")]
+pub async fn ingredient_allergens(
+    store: &State<StorePool>,
+    ingredient: String,
+) -> ApiResult<Json<Allergens>> {
+    let allergens = store.allergens(&ingredient.to_lowercase()).await?;
+    Ok(Json(Allergens { allergens }))
+}
+
+// Grams per millilitre, eg. {"density": 0.53} for flour. Ingredients without one fall back to
+// the common ones in `common_density`. Shared by everyone like the allergens so admins only.
+#[put(
+    "/ingredient/<ingredient>/density",
+    format = "application/json",
+    data = "<density>"
+)]
+pub async fn set_density(
+    store: &State<StorePool>,
+    _admin: Admin,
+    ingredient: String,
+    density: Json<Density>,
+) -> ApiResult<Status> {
+    if density.density <= 0.0 {
+        return Err(ApiError::Validation(
+            "The density has to be positive".to_string(),
+        ));
+    }
+    if !store
+        .set_density(&ingredient.to_lowercase(), density.density)
+        .await?
+    {
+        return Err(ApiError::not_found("Ingredient"));
+    }
+    Ok(Status::NoContent)
 }
@@ -0,0 +1,163 @@
+use crate::errors::{ApiError, ApiResult};
+use crate::helpers::shopping::{generate_saved_list, plan_fingerprint};
+use crate::models::{
+    NewShoppingItem, SavedShoppingList, ShoppingItemUpdate, ShoppingListItem, ShoppingListView,
+    UserId,
+};
+use crate::store::StorePool;
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use rocket::State;
+use uuid::Uuid;
+
+async fn saved_list(store: &StorePool, u_id: &UserId) -> ApiResult<SavedShoppingList> {
+    store
+        .shopping_list(&u_id.0)
+        .await?
+        .ok_or_else(|| ApiError::not_found("Shopping list"))
+}
+
+async fn view(
+    store: &StorePool,
+    u_id: &UserId,
+    list: SavedShoppingList,
+) -> ApiResult<Json<ShoppingListView>> {
+    let meals = store.chosen_meals(&u_id.0).await?;
+    let outdated = list.plan != plan_fingerprint(&meals);
+    Ok(Json(ShoppingListView { list, outdated }))
+}
+
+async fn save_and_view(
+    store: &StorePool,
+    u_id: &UserId,
+    list: SavedShoppingList,
+) -> ApiResult<Json<ShoppingListView>> {
+    store.save_shopping_list(&u_id.0, &list).await?;
+    view(store, u_id, list).await
+}
+
+// The item routes change the one item in the store and send back the list as it is after that,
+// see ShoppingListStore. Nothing changed means there's no list or no such item on it.
+async fn item_changed(
+    store: &StorePool,
+    u_id: &UserId,
+    changed: bool,
+) -> ApiResult<Json<ShoppingListView>> {
+    let list = saved_list(store, u_id).await?;
+    if !changed {
+        return Err(ApiError::not_found("Item"));
+    }
+    view(store, u_id, list).await
+}
+
+// The recipes in the meal plan as a list to carry around
+async fn chosen_list(
+    store: &StorePool,
+    u_id: &UserId,
+    previous: Option<SavedShoppingList>,
+) -> ApiResult<SavedShoppingList> {
+    let meals = store.chosen_meals(&u_id.0).await?;
+    Ok(generate_saved_list(&meals, previous))
+}
+
+#[get("/")]
+pub async fn get_shopping_list(
+    store: &State<StorePool>,
+    u_id: UserId,
+) -> ApiResult<Json<ShoppingListView>> {
+    let list = saved_list(store, &u_id).await?;
+    view(store, &u_id, list).await
+}
+
+// Starts over from the current selection, whatever was on the old list is dropped
+#[post("/")]
+pub async fn create_shopping_list(
+    store: &State<StorePool>,
+    u_id: UserId,
+) -> ApiResult<(Status, Json<ShoppingListView>)> {
+    let list = chosen_list(store, &u_id, None).await?;
+    Ok((Status::Created, save_and_view(store, &u_id, list).await?))
+}
+
+// Catches up with the current selection but keeps the manual items and what was checked
+#[post("/regenerate")]
+pub async fn regenerate_shopping_list(
+    store: &State<StorePool>,
+    u_id: UserId,
+) -> ApiResult<Json<ShoppingListView>> {
+    let previous = store.shopping_list(&u_id.0).await?;
+    let list = chosen_list(store, &u_id, previous).await?;
+    save_and_view(store, &u_id, list).await
+}
+
+#[delete("/")]
+pub async fn remove_shopping_list(store: &State<StorePool>, u_id: UserId) -> ApiResult<Status> {
+    store.remove_shopping_list(&u_id.0).await?;
+    Ok(Status::NoContent)
+}
+
+#[post("/items", format = "application/json", data = "<item>")]
+pub async fn add_shopping_item(
+    store: &State<StorePool>,
+    u_id: UserId,
+    item: Json<NewShoppingItem>,
+) -> ApiResult<(Status, Json<ShoppingListView>)> {
+    let name = item.name.trim().to_lowercase();
+    if name.is_empty() {
+        return Err(ApiError::Validation("The item needs a name".to_string()));
+    }
+    let item = ShoppingListItem {
+        id: Uuid::new_v4(),
+        name,
+        tipo: item.tipo.clone().unwrap_or_default().to_lowercase(),
+        amount: item.amount.clone().unwrap_or_default(),
+        checked: false,
+        manual: true,
+    };
+    let added = store.add_shopping_item(&u_id.0, &item).await?;
+    Ok((Status::Created, item_changed(store, &u_id, added).await?))
+}
+
+// Changing anything but `checked` makes the item a manual one so regenerating doesn't undo it
+#[patch("/items/<item_id>", format = "application/json", data = "<update>")]
+pub async fn update_shopping_item(
+    store: &State<StorePool>,
+    u_id: UserId,
+    item_id: String,
+    update: Json<ShoppingItemUpdate>,
+) -> ApiResult<Json<ShoppingListView>> {
+    let mut update = update.into_inner();
+    if let Some(name) = &update.name {
+        let name = name.trim().to_lowercase();
+        if name.is_empty() {
+            return Err(ApiError::Validation("The item needs a name".to_string()));
+        }
+        update.name = Some(name);
+    }
+    update.tipo = update.tipo.map(|tipo| tipo.to_lowercase());
+    let updated = store
+        .update_shopping_item(&u_id.0, &item_id, &update)
+        .await?;
+    item_changed(store, &u_id, updated).await
+}
+
+#[put("/items/<item_id>/toggle")]
+pub async fn toggle_shopping_item(
+    store: &State<StorePool>,
+    u_id: UserId,
+    item_id: String,
+) -> ApiResult<Json<ShoppingListView>> {
+    let toggled = store.toggle_shopping_item(&u_id.0, &item_id).await?;
+    item_changed(store, &u_id, toggled).await
+}
+
+// Generated items come back on the next regenerate if the selection still needs them
+#[delete("/items/<item_id>")]
+pub async fn remove_shopping_item(
+    store: &State<StorePool>,
+    u_id: UserId,
+    item_id: String,
+) -> ApiResult<Json<ShoppingListView>> {
+    let removed = store.remove_shopping_item(&u_id.0, &item_id).await?;
+    item_changed(store, &u_id, removed).await
+}
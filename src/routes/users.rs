@@ -1,29 +1,32 @@
 // use rocket::*;
+use crate::errors::{ApiError, ApiResult};
+use crate::helpers::picker::MAX_PICKER_WEIGHT;
+use crate::helpers::plan::parse_time_zone;
+use crate::helpers::recipes::ingredient_names;
 use crate::helpers::users::set_user_cookies;
-use crate::models::{GraphPool, LoginCredentials, User, UserId};
+use crate::models::{Exclusions, LoginCredentials, PickerWeights, PlanSettings, User, UserId};
+use crate::store::StorePool;
 use argon2::{
     password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2,
 };
-use neo4rs::*;
 use rand_core::OsRng;
-use rocket::http::{Cookie, Cookies, Status};
-use rocket::request::{FlashMessage, Form};
+use rocket::form::Form;
+use rocket::http::{Cookie, CookieJar, Status};
+use rocket::request::FlashMessage;
 // use rocket::response::{Flash, Redirect};
+use rocket::serde::json::Json;
 use rocket::State;
-use rocket_contrib::json::Json;
-use tokio::runtime::Runtime;
 use uuid::Uuid;
 use validator::{validate_email, validate_length};
 
 #[post("/new", format = "application/json", data = "<user>")]
-pub fn new_user(
+pub async fn new_user(
     user: Json<User>,
-    graph: State<GraphPool>,
-    rt: State<Runtime>,
-    cookies: Cookies,
-) -> Status {
-    let id = Uuid::new_v4().to_string();
+    store: &State<StorePool>,
+    cookies: &CookieJar<'_>,
+) -> ApiResult<Status> {
+    let id = Uuid::new_v4();
     let empty_string = String::new();
     let username = &user.username;
     let email = user.email.as_ref().unwrap_or(&empty_string);
@@ -33,44 +36,9 @@ pub fn new_user(
         || !(validate_length(&user.password, Some(10), None, None))
     {
         // return Flash::error(Redirect::to(uri!("/users", query_users)), "Bad Request");
-        return Status::BadRequest;
-    }
-
-    let existing_user: Option<&str> = rt.block_on(async {
-        let mut res = graph
-            .execute(
-                query("MATCH (u:User) WHERE u.username = $u_name OR u.email = $u_mail RETURN u")
-                    .param("u_name", username.clone())
-                    .param("u_mail", email.clone()),
-            )
-            .await
-            .expect("Couldn't perform the query for the user in db");
-
-        let row_option = res.next().await.expect("Couldn't get the stream");
-        if row_option.is_none() {
-            return None;
-        }
-        let node: Node = row_option
-            .unwrap()
-            .get("u")
-            .expect("Couldn't get the User node");
-        let existing_username = node
-            .get::<String>("username")
-            .expect("Can't get the username");
-        let existing_email = node.get::<String>("email").expect("Can't get the email");
-        if &existing_username == username {
-            return Some("Username");
-        } else if &existing_email == email {
-            return Some("Email");
-        }
-        None
-    });
-
-    if existing_user.is_some() {
-        if existing_user.unwrap() == "Username" {
-            return Status::Forbidden;
-        }
-        return Status::Unauthorized;
+        return Err(ApiError::Validation(
+            "Invalid email, or the username or password are too short".to_string(),
+        ));
     }
 
     // Hashing the password
@@ -79,87 +47,71 @@ pub fn new_user(
     let argon2 = Argon2::default();
     let password_hash = argon2
         .hash_password_simple(password, salt.as_ref())
-        .expect("Couldn't hash the password")
+        .map_err(|_| ApiError::Internal("Couldn't hash the password".to_string()))?
         .to_string();
     // Making sure our hash worked with the given password.
-    let parsed_hash = PasswordHash::new(&password_hash).expect("Couldn't parse the hash");
-    assert!(argon2.verify_password(password, &parsed_hash).is_ok());
+    let parsed_hash = PasswordHash::new(&password_hash)
+        .map_err(|_| ApiError::Internal("Couldn't parse the hash".to_string()))?;
+    if argon2.verify_password(password, &parsed_hash).is_err() {
+        return Err(ApiError::Internal("Couldn't verify the hash".to_string()));
+    }
 
-    rt.block_on(async {
-        graph.run(
-            query("CREATE (:User {username: $uname, id: $uid, password: $pass, email: $mail, role:\
-            \"pentacoob\"})")
-                .param("uname", username.clone())
-                .param("uid", id.clone())
-                .param("pass", password_hash.clone())
-                .param("mail", email.clone())
-        ).await.expect("Couldn't add the User")
-    });
+    store
+        .create_user(&User {
+            id: Some(id),
+            username: username.clone(),
+            password: password_hash,
+            email: Some(email.clone()),
+            role: Some("pentacoob".to_string()),
+        })
+        .await?;
 
     // Flash::success(
     //     Redirect::to(uri!("/users", query_users)),
     //     "User added to the db.",
     // )
-    set_user_cookies(cookies, id, username.clone());
-    Status::Created
+    set_user_cookies(cookies, id.to_string(), username.clone());
+    Ok(Status::Created)
 }
 
 #[post("/login", format = "application/json", data = "<user>")]
-pub fn login(
+pub async fn login(
     user: Json<User>,
-    graph: State<GraphPool>,
-    rt: State<Runtime>,
-    cookies: Cookies,
-) -> Status {
+    store: &State<StorePool>,
+    cookies: &CookieJar<'_>,
+) -> ApiResult<Status> {
     let username = &user.username;
     let password = &user.password.as_bytes();
     let argon2 = Argon2::default();
 
-    let res_tuple: std::result::Result<(String, String, String), Status> = rt.block_on(async {
-        let mut result = graph
-            .execute(
-                query("MATCH (u:User) WHERE u.username = $name RETURN u")
-                    .param("name", username.clone()),
-            )
-            .await
-            .expect("Couldn't find that user");
-
-        let row = result.next().await.expect("Couldn't fetch row");
-        if row.is_none() {
-            return Err(Status::Unauthorized);
-        }
-        let node: Node = row.unwrap().get("u").unwrap();
-        Ok((
-            node.get::<String>("password").expect("No password found"),
-            node.get::<String>("id").expect("No id found"),
-            node.get::<String>("username").expect("No Username found"),
-        ))
-    });
-    if res_tuple.is_err() {
-        return res_tuple.err().unwrap();
-    }
-    let res_tuple = res_tuple.unwrap();
-    let password_hash = res_tuple.0;
-    let id = res_tuple.1;
-    let username = res_tuple.2;
+    let existing_user = store
+        .get_user_by_name(username)
+        .await?
+        .ok_or_else(|| ApiError::Unauthorized("Wrong credentials".to_string()))?;
+    let password_hash = existing_user.password;
+    let id = existing_user
+        .id
+        .ok_or_else(|| ApiError::Database("User without an id".to_string()))?
+        .to_string();
+    let username = existing_user.username;
 
-    let parsed_hash = PasswordHash::new(&password_hash).expect("Couldn't parse the hash");
+    let parsed_hash = PasswordHash::new(&password_hash)
+        .map_err(|_| ApiError::Database("Couldn't parse the stored hash".to_string()))?;
     if argon2.verify_password(password, &parsed_hash).is_ok() {
         set_user_cookies(cookies, id, username);
         // return Flash::success(Redirect::to(uri!("/users", query_users)), "Successfully logged in")
-        return Status::Accepted;
+        return Ok(Status::Accepted);
     }
     // Flash::error(Redirect::to(uri!("/users", query_users)), "Wrong credentials")
-    Status::Forbidden
+    Err(ApiError::Forbidden("Wrong credentials".to_string()))
 }
 
 #[post("/login", data = "<user>", rank = 2)]
-pub fn login_form(
+pub async fn login_form(
     user: Form<LoginCredentials>,
-    graph: State<GraphPool>,
-    rt: State<Runtime>,
-    cookies: Cookies,
-) -> Status {
+    store: &State<StorePool>,
+    cookies: &CookieJar<'_>,
+) -> ApiResult<Status> {
     let new_user = User {
         id: None,
         username: user.username.to_owned(),
@@ -168,74 +120,52 @@ pub fn login_form(
         role: None,
     };
     let user = Json(new_user);
-    if login(user, graph, rt, cookies) == Status::Ok {
-        return Status::Ok;
+    if login(user, store, cookies).await.ok() == Some(Status::Ok) {
+        return Ok(Status::Ok);
     }
-    Status::Unauthorized
+    Err(ApiError::Unauthorized("Wrong credentials".to_string()))
 }
 
 #[get("/")]
-pub fn query_users(
-    rt: State<Runtime>,
-    graph: State<GraphPool>,
-    flash: Option<FlashMessage>,
-) -> String {
-    let res = rt.block_on(async {
-        let mut result = graph
-            .execute(query("MATCH (u:User) RETURN u"))
-            .await
-            .unwrap();
-
-        let mut res = Vec::new();
-
-        while let Ok(Some(row)) = result.next().await {
-            let node: Node = row.get("u").unwrap();
-            let id = node.id();
-            let labels = node.labels();
-            let name: String = node.get("username").unwrap();
-            let role: String = node.get("role").unwrap();
-            let pass: String = node.get("password").unwrap();
-            res.push(format!(
-                "Got id: {}, labels: {:?}, username: {}, role: {}, pass: {}",
-                id, labels, name, role, pass
-            ))
-        }
-        res
-    });
-    format!(
+pub async fn query_users(
+    store: &State<StorePool>,
+    flash: Option<FlashMessage<'_>>,
+) -> ApiResult<String> {
+    let res: Vec<_> = store
+        .all_users()
+        .await?
+        .into_iter()
+        .map(|user| {
+            format!(
+                "Got id: {:?}, username: {}, role: {:?}, pass: {}",
+                user.id, user.username, user.role, user.password
+            )
+        })
+        .collect();
+    Ok(format!(
         "Flash was {}\n This is the user vector {:?}",
-        flash.unwrap().msg(),
+        flash.map(|f| f.message().to_string()).unwrap_or_default(),
         res
-    )
+    ))
 }
 
 #[get("/<name>")]
-pub fn get_user(
-    rt: State<Runtime>,
-    graph: State<GraphPool>,
+pub async fn get_user(
+    store: &State<StorePool>,
     name: String,
     key: UserId,
     usr: User,
-) -> Option<String> {
+) -> ApiResult<String> {
     // let key = "nothing";
     println!(
         "Authorized with key {:?}, also we got this User {:?}",
         key, usr
     );
-    let node: Option<Node> = rt.block_on(async {
-        let mut result = graph
-            .execute(query("MATCH (u:User) WHERE u.username = $name RETURN u").param("name", name))
-            .await
-            .expect("Couldn't find that user");
-
-        let row = result
-            .next()
-            .await
-            .expect("Couldn't fetch row")
-            .expect("Empty row");
-        row.get("u")
-    });
-    Some(format!("{:?}", node))
+    let user = store
+        .get_user_by_name(&name)
+        .await?
+        .ok_or_else(|| ApiError::not_found("User"))?;
+    Ok(format!("{:?}", user))
 }
 
 // #[get("/<name>", rank = 2)]
@@ -243,14 +173,76 @@ pub fn get_user(
 //     Redirect::to(uri!(login))
 // }
 
+// Ingredient names or allergens hidden from every recipe listing of the user
+#[get("/exclusions")]
+pub async fn get_exclusions(store: &State<StorePool>, u_id: UserId) -> ApiResult<Json<Exclusions>> {
+    let exclude = store.exclusions(&u_id.0).await?;
+    Ok(Json(Exclusions { exclude }))
+}
+
+#[put("/exclusions", format = "application/json", data = "<exclusions>")]
+pub async fn set_exclusions(
+    store: &State<StorePool>,
+    u_id: UserId,
+    exclusions: Json<Exclusions>,
+) -> ApiResult<Status> {
+    let exclude = ingredient_names(&exclusions.exclude);
+    store.set_exclusions(&u_id.0, &exclude).await?;
+    Ok(Status::NoContent)
+}
+
+// Retention, week start and time zone of the meal plan
+#[get("/plan-settings")]
+pub async fn get_plan_settings(
+    store: &State<StorePool>,
+    u_id: UserId,
+) -> ApiResult<Json<PlanSettings>> {
+    Ok(Json(store.plan_settings(&u_id.0).await?))
+}
+
+#[put("/plan-settings", format = "application/json", data = "<settings>")]
+pub async fn set_plan_settings(
+    store: &State<StorePool>,
+    u_id: UserId,
+    settings: Json<PlanSettings>,
+) -> ApiResult<Status> {
+    parse_time_zone(&settings.time_zone)?;
+    store.set_plan_settings(&u_id.0, &settings).await?;
+    Ok(Status::NoContent)
+}
+
+// How the weekly suggestion leans towards liked, not so recent and favorite recipes
+#[get("/picker-weights")]
+pub async fn get_picker_weights(
+    store: &State<StorePool>,
+    u_id: UserId,
+) -> ApiResult<Json<PickerWeights>> {
+    Ok(Json(store.picker_weights(&u_id.0).await?))
+}
+
+#[put("/picker-weights", format = "application/json", data = "<weights>")]
+pub async fn set_picker_weights(
+    store: &State<StorePool>,
+    u_id: UserId,
+    weights: Json<PickerWeights>,
+) -> ApiResult<Status> {
+    let knobs = [weights.likes, weights.recency, weights.favorites];
+    if knobs.iter().any(|knob| knob.abs() > MAX_PICKER_WEIGHT) {
+        return Err(ApiError::Validation(format!(
+            "The weights go from -{max} to {max}",
+            max = MAX_PICKER_WEIGHT
+        )));
+    }
+    store.set_picker_weights(&u_id.0, &weights).await?;
+    Ok(Status::NoContent)
+}
+
 #[get("/logout")]
-pub fn logout(mut cookies: Cookies) -> Status {
+pub fn logout(cookies: &CookieJar<'_>) -> Status {
     // Adding this path is needed for svelte to actually modify the cookie since cookies with a
     // mismatch in paths will be ignored. And we specifically change the path on the login route
     // to "/" for svelte to read it properly
-    let mut username_cookie = Cookie::named("username");
-    username_cookie.set_path("/");
-    cookies.remove(username_cookie);
-    cookies.remove_private(Cookie::named("user_id"));
+    cookies.remove(Cookie::build("username").path("/"));
+    cookies.remove_private(Cookie::build("user_id").path("/"));
     Status::NoContent
 }
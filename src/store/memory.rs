@@ -0,0 +1,1056 @@
+use crate::errors::{ApiError, ApiResult};
+use crate::helpers::picker::recipe_minutes;
+use crate::helpers::quantity::parse_amount;
+use crate::helpers::recipes::{cookable_recipe, uses_any};
+use crate::helpers::search::recipe_score;
+use crate::models::{
+    CookEntry, CookableRecipe, CookedRecipe, Ingredient, LikeResult, MealSlot, PageInfo,
+    PickerWeights, PlanEntry, PlanSettings, PlanSlot, PlannedMeal, RatingForm, RatingResult,
+    Recipe, RecipeQuery, RecipeRelationships, RecipeSort, RecipeUpdate, RecipeVec, Review,
+    ReviewQuery, Reviews, SavedShoppingList, ShoppingItemUpdate, ShoppingListItem, User,
+};
+use crate::store::{user_conflict, RecipeStore, ShoppingListStore, UserStore};
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard};
+use uuid::Uuid;
+
+// Same data the graph holds, just kept in plain collections. Relationships are stored by id on
+// both ends so the queries below read pretty much like the cypher ones.
+#[derive(Default)]
+struct MemoryData {
+    users: Vec<User>,
+    recipes: Vec<Recipe>,
+    // recipe id -> owner id
+    owns: HashMap<Uuid, String>,
+    likes: HashSet<(String, Uuid)>,
+    favorites: HashSet<(String, Uuid)>,
+    // The RATED relationships, one per user and recipe
+    ratings: Vec<Rated>,
+    chosen: Vec<Chosen>,
+    // Plan entries that expired, the PLANNED relationships of the graph
+    history: Vec<Chosen>,
+    // The COOKED relationships, in the order they were logged
+    cooked: Vec<Cooked>,
+    // ingredient name -> allergens
+    allergens: HashMap<String, Vec<String>>,
+    // ingredient name -> grams per millilitre
+    densities: HashMap<String, f64>,
+    // user id -> excluded ingredients and allergens
+    exclusions: HashMap<String, Vec<String>>,
+    // user id -> their plan settings, missing ones are the defaults
+    plan_settings: HashMap<String, PlanSettings>,
+    // user id -> their weekly suggestion weights, missing ones are the defaults
+    picker_weights: HashMap<String, PickerWeights>,
+    // user id -> their shopping list
+    shopping_lists: HashMap<String, SavedShoppingList>,
+}
+
+// A CHOSEN relationship, one entry of a user's meal plan
+struct Chosen {
+    user: String,
+    id: Uuid,
+    recipe: Uuid,
+    date: NaiveDate,
+    slot: MealSlot,
+}
+
+struct Rated {
+    user: String,
+    recipe: Uuid,
+    stars: u8,
+    review: Option<String>,
+    at: NaiveDateTime,
+}
+
+// A COOKED relationship, one entry of a user's cook log
+struct Cooked {
+    user: String,
+    id: Uuid,
+    recipe: Uuid,
+    entry: CookEntry,
+}
+
+impl MemoryData {
+    fn recipe(&self, r_id: Uuid) -> Option<&Recipe> {
+        self.recipes.iter().find(|r| r.id == Some(r_id))
+    }
+
+    fn owns(&self, u_id: &str, r_id: Uuid) -> bool {
+        self.owns.get(&r_id).map(String::as_str) == Some(u_id)
+    }
+
+    fn likes(&self, u_id: &str, r_id: Uuid) -> bool {
+        self.likes.contains(&(u_id.to_string(), r_id))
+    }
+
+    // The user's entries of the plan or the history with their recipes, by day and slot
+    fn planned_meals(&self, entries: &[Chosen], u_id: &str) -> Vec<PlannedMeal> {
+        let mut meals: Vec<PlannedMeal> = entries
+            .iter()
+            .filter(|chosen| chosen.user == u_id)
+            .filter_map(|chosen| {
+                self.recipe(chosen.recipe).map(|recipe| PlannedMeal {
+                    id: chosen.id,
+                    date: chosen.date,
+                    slot: chosen.slot,
+                    recipe: recipe.clone(),
+                })
+            })
+            .collect();
+        meals.sort_by_key(|meal| (meal.date, meal.slot));
+        meals
+    }
+
+    // Same as the neo4j store, the totals are kept on the recipe whenever a rating changes
+    fn update_rating_totals(&mut self, r_id: Uuid) {
+        let stars: Vec<f32> = self
+            .ratings
+            .iter()
+            .filter(|rated| rated.recipe == r_id)
+            .map(|rated| f32::from(rated.stars))
+            .collect();
+        if let Some(recipe) = self.recipes.iter_mut().find(|r| r.id == Some(r_id)) {
+            recipe.rating_count = Some(stars.len() as u32);
+            recipe.average_rating = if stars.is_empty() {
+                None
+            } else {
+                Some(stars.iter().sum::<f32>() / stars.len() as f32)
+            };
+        }
+    }
+
+    fn shopping_item_mut(&mut self, u_id: &str, item_id: &str) -> Option<&mut ShoppingListItem> {
+        let item_id = parse_id(item_id)?;
+        self.shopping_lists
+            .get_mut(u_id)?
+            .items
+            .iter_mut()
+            .find(|item| item.id == item_id)
+    }
+
+    fn chosen_mut(&mut self, u_id: &str, m_id: &str) -> Option<&mut Chosen> {
+        let m_id = parse_id(m_id)?;
+        self.chosen
+            .iter_mut()
+            .find(|chosen| chosen.user == u_id && chosen.id == m_id)
+    }
+
+    // There's no Ingredient node here, an ingredient exists as long as a recipe uses it
+    fn uses_ingredient(&self, ingredient: &str) -> bool {
+        self.recipes
+            .iter()
+            .flat_map(|r| r.ingredients.iter().flatten())
+            .any(|i| i.name == ingredient)
+    }
+}
+
+// Backend for tests and local demos, nothing survives a restart. Set RECIPES_STORE=memory to run
+// the server with it.
+#[derive(Default)]
+pub struct MemoryStore {
+    data: Mutex<MemoryData>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn data(&self) -> MutexGuard<'_, MemoryData> {
+        self.data.lock().expect("Memory store lock was poisoned")
+    }
+}
+
+fn parse_id(r_id: &str) -> Option<Uuid> {
+    Uuid::parse_str(r_id).ok()
+}
+
+fn is_public(recipe: &Recipe) -> bool {
+    recipe.public == Some(true)
+}
+
+// Writes the update into the recipe the same way the neo4j store would store it, so the
+// ingredient names get lowercased and the amounts parsed.
+fn apply_update(recipe: &mut Recipe, update: &RecipeUpdate) {
+    if let Some(name) = &update.name {
+        recipe.name = name.clone();
+    }
+    if let Some(public) = update.public {
+        recipe.public = Some(public);
+    }
+    if let Some(steps) = &update.steps {
+        recipe.steps = Some(steps.clone());
+    }
+    if let Some(tipo) = &update.tipo {
+        recipe.tipo = Some(tipo.clone());
+    }
+    if let Some(calories) = update.calories {
+        recipe.calories = Some(calories);
+    }
+    if let Some(carbohydrates) = update.carbohydrates {
+        recipe.carbohydrates = Some(carbohydrates);
+    }
+    if let Some(fat) = update.fat {
+        recipe.fat = Some(fat);
+    }
+    if let Some(protein) = update.protein {
+        recipe.protein = Some(protein);
+    }
+    if let Some(servings) = update.servings {
+        recipe.servings = Some(servings).filter(|servings| *servings > 0);
+    }
+    if let Some(yield_unit) = &update.yield_unit {
+        recipe.yield_unit = Some(yield_unit.clone());
+    }
+    if let Some(meal_type) = &update.meal_type {
+        recipe.meal_type = Some(meal_type.clone());
+    }
+    if let Some(time) = &update.time {
+        recipe.time = Some(time.clone());
+    }
+    if let Some(ingredients) = &update.ingredients {
+        let stored = ingredients
+            .iter()
+            .map(|i| {
+                let parsed = parse_amount(&i.amount);
+                Ingredient {
+                    name: i.name.to_lowercase(),
+                    tipo: Some(i.tipo.clone().unwrap_or_default().to_lowercase()),
+                    amount: i.amount.clone(),
+                    quantity: parsed.quantity,
+                    unit: parsed.unit,
+                }
+            })
+            .collect();
+        recipe.ingredients = Some(stored);
+    }
+}
+
+fn matches_filters(recipe: &Recipe, list: &RecipeQuery) -> bool {
+    fn in_range<T: PartialOrd>(value: Option<T>, min: Option<T>, max: Option<T>) -> bool {
+        match value {
+            Some(value) => min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max),
+            None => min.is_none() && max.is_none(),
+        }
+    }
+
+    (list.meal_type.is_none() || recipe.meal_type == list.meal_type)
+        && (list.tipo.is_none() || recipe.tipo == list.tipo)
+        && in_range(recipe.calories, list.min_calories, list.max_calories)
+        && in_range(
+            recipe.carbohydrates,
+            list.min_carbohydrates,
+            list.max_carbohydrates,
+        )
+        && in_range(recipe.fat, list.min_fat, list.max_fat)
+        && in_range(recipe.protein, list.min_protein, list.max_protein)
+        && !uses_any(recipe, &list.exclude)
+}
+
+fn minutes(recipe: &Recipe) -> Option<u32> {
+    recipe.time.as_deref().and_then(recipe_minutes)
+}
+
+// Missing values go last when ascending and first when descending, same as neo4j orders nulls.
+fn compare_recipes(a: &Recipe, b: &Recipe, sort: RecipeSort) -> Ordering {
+    fn nulls_last<T: PartialOrd>(a: Option<T>, b: Option<T>) -> Ordering {
+        match (a, b) {
+            (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+    }
+
+    let by_field = match sort {
+        RecipeSort::Name => a.name.cmp(&b.name),
+        RecipeSort::Calories => nulls_last(a.calories, b.calories),
+        RecipeSort::Protein => nulls_last(a.protein, b.protein),
+        RecipeSort::Time => nulls_last(minutes(a), minutes(b)),
+        RecipeSort::Created => nulls_last(a.created, b.created),
+    };
+    by_field.then_with(|| a.id.cmp(&b.id))
+}
+
+// Filters, sorts and cuts the page the same way `paged_recipe_queries` does for neo4j.
+fn paginate(recipes: Vec<Recipe>, list: &RecipeQuery) -> (Vec<Recipe>, PageInfo) {
+    let mut matching: Vec<_> = recipes
+        .into_iter()
+        .filter(|r| matches_filters(r, list))
+        .collect();
+    let sort = list.sort();
+    // Times that don't parse go last whichever way it's sorted, see `order_by`
+    let last = |r: &Recipe| sort == RecipeSort::Time && minutes(r).is_none();
+    matching.sort_by(|a, b| {
+        let mut ordering = compare_recipes(a, b, sort);
+        if list.descending() {
+            ordering = ordering.reverse();
+        }
+        last(a).cmp(&last(b)).then(ordering)
+    });
+    let total = matching.len();
+    let page = matching
+        .into_iter()
+        .skip(list.offset())
+        .take(list.limit())
+        .collect();
+    (page, list.page_info(total))
+}
+
+#[async_trait]
+impl RecipeStore for MemoryStore {
+    async fn all_recipes(&self) -> ApiResult<Vec<Recipe>> {
+        Ok(self.data().recipes.clone())
+    }
+
+    async fn create_recipe(&self, u_id: &str, recipe: &Recipe) -> ApiResult<Uuid> {
+        let mut data = self.data();
+        let recipe_uuid = Uuid::new_v4();
+        if !data.users.iter().any(|u| u.id == parse_id(u_id)) {
+            return Ok(recipe_uuid);
+        }
+        let mut stored = Recipe {
+            id: Some(recipe_uuid),
+            ingredients: Some(Vec::new()),
+            created: Some(Utc::now().naive_utc()),
+            times_cooked: Some(0),
+            rating_count: Some(0),
+            ..Recipe::default()
+        };
+        apply_update(&mut stored, &RecipeUpdate::from(recipe.clone()));
+        data.recipes.push(stored);
+        data.owns.insert(recipe_uuid, u_id.to_string());
+        Ok(recipe_uuid)
+    }
+
+    async fn update_recipe(
+        &self,
+        u_id: &str,
+        r_id: &str,
+        update: &RecipeUpdate,
+    ) -> ApiResult<bool> {
+        let mut data = self.data();
+        let r_id = match parse_id(r_id) {
+            Some(id) if data.owns(u_id, id) => id,
+            _ => return Ok(false),
+        };
+        if let Some(recipe) = data.recipes.iter_mut().find(|r| r.id == Some(r_id)) {
+            apply_update(recipe, update);
+        }
+        Ok(true)
+    }
+
+    async fn remove_recipe(&self, u_id: &str, r_id: &str) -> ApiResult<()> {
+        let mut data = self.data();
+        let r_id = match parse_id(r_id) {
+            Some(id) if data.owns(u_id, id) => id,
+            _ => return Ok(()),
+        };
+        data.recipes.retain(|r| r.id != Some(r_id));
+        data.owns.remove(&r_id);
+        data.likes.retain(|(_, liked)| *liked != r_id);
+        data.favorites.retain(|(_, favorite)| *favorite != r_id);
+        data.ratings.retain(|rated| rated.recipe != r_id);
+        data.chosen.retain(|chosen| chosen.recipe != r_id);
+        data.history.retain(|planned| planned.recipe != r_id);
+        data.cooked.retain(|cooked| cooked.recipe != r_id);
+        Ok(())
+    }
+
+    async fn get_recipe(&self, u_id: &str, r_id: &str) -> ApiResult<Option<Recipe>> {
+        let data = self.data();
+        Ok(parse_id(r_id).and_then(|r_id| {
+            data.recipe(r_id)
+                .filter(|r| data.owns(u_id, r_id) || is_public(r))
+                .cloned()
+        }))
+    }
+
+    async fn get_public_recipe(&self, r_id: &str) -> ApiResult<Option<Recipe>> {
+        let data = self.data();
+        Ok(parse_id(r_id)
+            .and_then(|r_id| data.recipe(r_id))
+            .filter(|r| is_public(r))
+            .cloned())
+    }
+
+    async fn find_recipe(&self, r_id: &str) -> ApiResult<Option<Recipe>> {
+        let data = self.data();
+        Ok(parse_id(r_id).and_then(|r_id| data.recipe(r_id)).cloned())
+    }
+
+    async fn user_recipes(&self, u_id: &str) -> ApiResult<Vec<Recipe>> {
+        let data = self.data();
+        Ok(data
+            .recipes
+            .iter()
+            .filter(|r| {
+                let r_id = r.id.unwrap();
+                data.owns(u_id, r_id) || data.likes(u_id, r_id)
+            })
+            .cloned()
+            .collect())
+    }
+
+    async fn recipe_list(&self, u_id: &str, list: &RecipeQuery) -> ApiResult<RecipeVec> {
+        let data = self.data();
+        let mut rel_struct = RecipeRelationships {
+            owns: Vec::new(),
+            likes: Vec::new(),
+        };
+        let mut recipes_vector = Vec::new();
+
+        for recipe in &data.recipes {
+            let r_id = recipe.id.unwrap();
+            let owned = data.owns(u_id, r_id);
+            let liked = data.likes(u_id, r_id);
+            if owned {
+                rel_struct.owns.push(r_id)
+            }
+            if liked {
+                rel_struct.likes.push(r_id)
+            }
+            if owned || liked || is_public(recipe) {
+                recipes_vector.push(recipe.clone())
+            }
+        }
+        let (recipes, page) = paginate(recipes_vector, list);
+
+        Ok(RecipeVec {
+            recipes,
+            rels: Option::from(rel_struct),
+            page: Option::from(page),
+        })
+    }
+
+    async fn public_recipes(&self, list: &RecipeQuery) -> ApiResult<RecipeVec> {
+        let data = self.data();
+        let public = data
+            .recipes
+            .iter()
+            .filter(|r| is_public(r))
+            .cloned()
+            .collect();
+        let (recipes, page) = paginate(public, list);
+
+        Ok(RecipeVec {
+            recipes,
+            rels: None,
+            page: Option::from(page),
+        })
+    }
+
+    async fn recipes_by_ingredient(
+        &self,
+        u_id: &str,
+        ingredient: &str,
+        list: &RecipeQuery,
+    ) -> ApiResult<RecipeVec> {
+        let data = self.data();
+        let matching = data
+            .recipes
+            .iter()
+            .filter(|r| data.owns(u_id, r.id.unwrap()) || is_public(r))
+            .filter(|r| r.ingredients.iter().flatten().any(|i| i.name == ingredient))
+            .cloned()
+            .collect();
+        let (recipes, page) = paginate(matching, list);
+
+        Ok(RecipeVec {
+            recipes,
+            rels: None,
+            page: Option::from(page),
+        })
+    }
+
+    async fn search_recipes(
+        &self,
+        u_id: &str,
+        terms: &[String],
+        excluded: &[String],
+        limit: usize,
+    ) -> ApiResult<Vec<(Recipe, f32)>> {
+        let data = self.data();
+        let mut hits: Vec<_> = data
+            .recipes
+            .iter()
+            .filter(|r| {
+                let r_id = r.id.unwrap();
+                data.owns(u_id, r_id) || data.likes(u_id, r_id) || is_public(r)
+            })
+            .filter(|r| !uses_any(r, excluded))
+            .map(|r| (r.clone(), recipe_score(r, terms)))
+            .filter(|(_, score)| *score > 0.0)
+            .collect();
+        hits.sort_by(|(a, a_score), (b, b_score)| {
+            b_score
+                .partial_cmp(a_score)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    async fn cookable_recipes(
+        &self,
+        u_id: &str,
+        on_hand: &[String],
+        excluded: &[String],
+        limit: usize,
+    ) -> ApiResult<Vec<CookableRecipe>> {
+        let data = self.data();
+        let mut cookable: Vec<_> = data
+            .recipes
+            .iter()
+            .filter(|r| {
+                let r_id = r.id.unwrap();
+                data.owns(u_id, r_id) || data.likes(u_id, r_id) || is_public(r)
+            })
+            .filter(|r| uses_any(r, on_hand) && !uses_any(r, excluded))
+            .map(|r| cookable_recipe(r.clone(), on_hand))
+            .collect();
+        cookable.sort_by(|a, b| {
+            b.coverage
+                .partial_cmp(&a.coverage)
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.missing.len().cmp(&b.missing.len()))
+                .then_with(|| a.recipe.id.cmp(&b.recipe.id))
+        });
+        cookable.truncate(limit);
+        Ok(cookable)
+    }
+
+    async fn set_allergens(&self, ingredient: &str, allergens: &[String]) -> ApiResult<bool> {
+        let mut data = self.data();
+        if !data.uses_ingredient(ingredient) {
+            return Ok(false);
+        }
+        data.allergens
+            .insert(ingredient.to_string(), allergens.to_vec());
+        Ok(true)
+    }
+
+    async fn allergens(&self, ingredient: &str) -> ApiResult<Vec<String>> {
+        Ok(self
+            .data()
+            .allergens
+            .get(ingredient)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn set_density(&self, ingredient: &str, density: f64) -> ApiResult<bool> {
+        let mut data = self.data();
+        if !data.uses_ingredient(ingredient) {
+            return Ok(false);
+        }
+        data.densities.insert(ingredient.to_string(), density);
+        Ok(true)
+    }
+
+    async fn ingredient_densities(&self, names: &[String]) -> ApiResult<HashMap<String, f64>> {
+        let data = self.data();
+        Ok(names
+            .iter()
+            .filter_map(|name| {
+                data.densities
+                    .get(name)
+                    .map(|density| (name.clone(), *density))
+            })
+            .collect())
+    }
+
+    async fn resolve_exclusions(&self, names: &[String]) -> ApiResult<Vec<String>> {
+        let data = self.data();
+        let mut resolved = names.to_vec();
+        for (ingredient, allergens) in &data.allergens {
+            if allergens.iter().any(|a| names.contains(a)) && !resolved.contains(ingredient) {
+                resolved.push(ingredient.clone());
+            }
+        }
+        Ok(resolved)
+    }
+
+    async fn like_recipe(&self, u_id: &str, r_id: &str) -> ApiResult<LikeResult> {
+        let mut data = self.data();
+        let r_id = match parse_id(r_id) {
+            Some(id) => id,
+            None => return Ok(LikeResult::Liked),
+        };
+        if data.owns(u_id, r_id) {
+            return Ok(LikeResult::Owned);
+        }
+        let like = (u_id.to_string(), r_id);
+        if data.likes.remove(&like) {
+            return Ok(LikeResult::Unliked);
+        }
+        if data.recipe(r_id).map(is_public).unwrap_or(false) {
+            data.likes.insert(like);
+        }
+        Ok(LikeResult::Liked)
+    }
+
+    async fn rate_recipe(
+        &self,
+        u_id: &str,
+        r_id: &str,
+        rating: &RatingForm,
+        at: NaiveDateTime,
+    ) -> ApiResult<RatingResult> {
+        let mut data = self.data();
+        let r_id = match parse_id(r_id) {
+            Some(id) => id,
+            None => return Err(ApiError::not_found("Recipe")),
+        };
+        if data.owns(u_id, r_id) {
+            return Ok(RatingResult::Owned);
+        }
+        if !data.recipe(r_id).map(is_public).unwrap_or(false) {
+            return Err(ApiError::not_found("Recipe"));
+        }
+        let result = match data
+            .ratings
+            .iter_mut()
+            .find(|rated| rated.user == u_id && rated.recipe == r_id)
+        {
+            Some(rated) => {
+                rated.stars = rating.stars;
+                rated.review = rating.review.clone();
+                rated.at = at;
+                RatingResult::Updated
+            }
+            None => {
+                data.ratings.push(Rated {
+                    user: u_id.to_string(),
+                    recipe: r_id,
+                    stars: rating.stars,
+                    review: rating.review.clone(),
+                    at,
+                });
+                RatingResult::Rated
+            }
+        };
+        data.update_rating_totals(r_id);
+        Ok(result)
+    }
+
+    async fn remove_rating(&self, u_id: &str, r_id: &str) -> ApiResult<()> {
+        let mut data = self.data();
+        if let Some(r_id) = parse_id(r_id) {
+            data.ratings
+                .retain(|rated| !(rated.user == u_id && rated.recipe == r_id));
+            data.update_rating_totals(r_id);
+        }
+        Ok(())
+    }
+
+    async fn reviews(&self, r_id: &str, page: &ReviewQuery) -> ApiResult<Reviews> {
+        let data = self.data();
+        let r_id = parse_id(r_id);
+        let mut reviews: Vec<Review> = data
+            .ratings
+            .iter()
+            .filter(|rated| Some(rated.recipe) == r_id)
+            .filter_map(|rated| {
+                let username = data
+                    .users
+                    .iter()
+                    .find(|u| u.id == parse_id(&rated.user))?
+                    .username
+                    .clone();
+                Some(Review {
+                    username,
+                    stars: rated.stars,
+                    review: rated.review.clone()?,
+                    at: rated.at,
+                })
+            })
+            .collect();
+        reviews.sort_by(|a, b| b.at.cmp(&a.at).then_with(|| a.username.cmp(&b.username)));
+        let total = reviews.len();
+        Ok(Reviews {
+            reviews: reviews
+                .into_iter()
+                .skip(page.offset())
+                .take(page.limit())
+                .collect(),
+            page: page.page_info(total),
+        })
+    }
+
+    async fn like_counts(&self, r_ids: &[String]) -> ApiResult<HashMap<Uuid, usize>> {
+        let data = self.data();
+        let mut counts = HashMap::new();
+        for r_id in r_ids.iter().filter_map(|id| parse_id(id)) {
+            let likes = data
+                .likes
+                .iter()
+                .filter(|(_, liked)| *liked == r_id)
+                .count();
+            if likes > 0 {
+                counts.insert(r_id, likes);
+            }
+        }
+        Ok(counts)
+    }
+
+    async fn toggle_favorite(&self, u_id: &str, r_id: &str) -> ApiResult<bool> {
+        let mut data = self.data();
+        let favorite = match parse_id(r_id) {
+            Some(id) => (u_id.to_string(), id),
+            None => return Ok(false),
+        };
+        if data.favorites.remove(&favorite) {
+            return Ok(false);
+        }
+        data.favorites.insert(favorite);
+        Ok(true)
+    }
+
+    async fn favorites(&self, u_id: &str) -> ApiResult<Vec<Uuid>> {
+        Ok(self
+            .data()
+            .favorites
+            .iter()
+            .filter(|(user, _)| user == u_id)
+            .map(|(_, r_id)| *r_id)
+            .collect())
+    }
+
+    async fn choose_recipes(
+        &self,
+        u_id: &str,
+        entries: &[PlanEntry],
+        // Only the graph keeps it, for the history and the migrations
+        _created: NaiveDateTime,
+    ) -> ApiResult<()> {
+        let mut data = self.data();
+        for entry in entries {
+            let r_id = match parse_id(&entry.recipe_id) {
+                Some(id) if data.recipe(id).is_some() => id,
+                _ => continue,
+            };
+            data.chosen.push(Chosen {
+                user: u_id.to_string(),
+                id: Uuid::new_v4(),
+                recipe: r_id,
+                date: entry.date,
+                slot: entry.slot,
+            });
+        }
+        Ok(())
+    }
+
+    async fn chosen_recipes(&self, u_id: &str) -> ApiResult<Vec<Recipe>> {
+        let meals = self.chosen_meals(u_id).await?;
+        Ok(meals.into_iter().map(|meal| meal.recipe).collect())
+    }
+
+    async fn chosen_meals(&self, u_id: &str) -> ApiResult<Vec<PlannedMeal>> {
+        let data = self.data();
+        Ok(data.planned_meals(&data.chosen, u_id))
+    }
+
+    async fn reset_chosen(&self, u_id: &str) -> ApiResult<()> {
+        self.data().chosen.retain(|chosen| chosen.user != u_id);
+        Ok(())
+    }
+
+    async fn meal_plan(
+        &self,
+        u_id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> ApiResult<Vec<PlannedMeal>> {
+        let data = self.data();
+        let mut meals = data.planned_meals(&data.chosen, u_id);
+        meals.retain(|meal| meal.date >= from && meal.date < to);
+        Ok(meals)
+    }
+
+    async fn move_planned_meal(&self, u_id: &str, m_id: &str, to: &PlanSlot) -> ApiResult<bool> {
+        let mut data = self.data();
+        Ok(match data.chosen_mut(u_id, m_id) {
+            Some(chosen) => {
+                chosen.date = to.date;
+                chosen.slot = to.slot;
+                true
+            }
+            None => false,
+        })
+    }
+
+    async fn swap_planned_meals(&self, u_id: &str, first: &str, second: &str) -> ApiResult<bool> {
+        let mut data = self.data();
+        let (date, slot) = match data.chosen_mut(u_id, second) {
+            Some(chosen) if first != second => (chosen.date, chosen.slot),
+            _ => return Ok(false),
+        };
+        let (date, slot) = match data.chosen_mut(u_id, first) {
+            Some(chosen) => (
+                std::mem::replace(&mut chosen.date, date),
+                std::mem::replace(&mut chosen.slot, slot),
+            ),
+            None => return Ok(false),
+        };
+        if let Some(chosen) = data.chosen_mut(u_id, second) {
+            chosen.date = date;
+            chosen.slot = slot;
+        }
+        Ok(true)
+    }
+
+    async fn clear_plan_day(&self, u_id: &str, date: NaiveDate) -> ApiResult<()> {
+        self.data()
+            .chosen
+            .retain(|chosen| chosen.user != u_id || chosen.date != date);
+        Ok(())
+    }
+
+    async fn archive_plan(
+        &self,
+        u_id: &str,
+        before: NaiveDate,
+        _archived: NaiveDateTime,
+    ) -> ApiResult<usize> {
+        let mut data = self.data();
+        let (expired, kept) = std::mem::take(&mut data.chosen)
+            .into_iter()
+            .partition::<Vec<_>, _>(|chosen| chosen.user == u_id && chosen.date < before);
+        data.chosen = kept;
+        let archived = expired.len();
+        data.history.extend(expired);
+        Ok(archived)
+    }
+
+    async fn plan_history(
+        &self,
+        u_id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> ApiResult<Vec<PlannedMeal>> {
+        let data = self.data();
+        let mut meals = data.planned_meals(&data.history, u_id);
+        meals.retain(|meal| meal.date >= from && meal.date < to);
+        Ok(meals)
+    }
+
+    async fn log_cooked(&self, u_id: &str, r_id: &str, cooked: &CookEntry) -> ApiResult<Uuid> {
+        let mut data = self.data();
+        let c_id = Uuid::new_v4();
+        let r_id = match parse_id(r_id) {
+            Some(id) => id,
+            None => return Ok(c_id),
+        };
+        if let Some(recipe) = data.recipes.iter_mut().find(|r| r.id == Some(r_id)) {
+            recipe.times_cooked = Some(recipe.times_cooked.unwrap_or(0) + 1);
+            recipe.last_cooked = recipe.last_cooked.max(Some(cooked.at));
+            data.cooked.push(Cooked {
+                user: u_id.to_string(),
+                id: c_id,
+                recipe: r_id,
+                entry: cooked.clone(),
+            });
+        }
+        Ok(c_id)
+    }
+
+    async fn cook_log(
+        &self,
+        u_id: &str,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> ApiResult<Vec<CookedRecipe>> {
+        let data = self.data();
+        let mut cooked: Vec<CookedRecipe> = data
+            .cooked
+            .iter()
+            .filter(|cooked| cooked.user == u_id)
+            .filter(|cooked| cooked.entry.at >= from && cooked.entry.at < to)
+            .filter_map(|cooked| {
+                data.recipe(cooked.recipe).map(|recipe| CookedRecipe {
+                    id: cooked.id,
+                    cooked: cooked.entry.clone(),
+                    recipe: recipe.clone(),
+                })
+            })
+            .collect();
+        cooked.sort_by_key(|cooked| cooked.cooked.at);
+        Ok(cooked)
+    }
+}
+
+#[async_trait]
+impl UserStore for MemoryStore {
+    async fn all_users(&self) -> ApiResult<Vec<User>> {
+        Ok(self.data().users.clone())
+    }
+
+    async fn get_user(&self, u_id: &str) -> ApiResult<Option<User>> {
+        let u_id = match parse_id(u_id) {
+            Some(id) => id,
+            None => return Ok(None),
+        };
+        let data = self.data();
+        Ok(data.users.iter().find(|u| u.id == Some(u_id)).cloned())
+    }
+
+    async fn get_user_by_name(&self, username: &str) -> ApiResult<Option<User>> {
+        let data = self.data();
+        Ok(data.users.iter().find(|u| u.username == username).cloned())
+    }
+
+    // Checked under the same lock as the push so two signups can't both get through, same as
+    // the constraints do for neo4j.
+    async fn create_user(&self, user: &User) -> ApiResult<()> {
+        let mut data = self.data();
+        if data.users.iter().any(|u| u.username == user.username) {
+            return Err(user_conflict("username"));
+        }
+        if data
+            .users
+            .iter()
+            .any(|u| u.email.is_some() && u.email == user.email)
+        {
+            return Err(user_conflict("email"));
+        }
+        data.users.push(user.clone());
+        Ok(())
+    }
+
+    async fn exclusions(&self, u_id: &str) -> ApiResult<Vec<String>> {
+        Ok(self
+            .data()
+            .exclusions
+            .get(u_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn set_exclusions(&self, u_id: &str, exclude: &[String]) -> ApiResult<()> {
+        self.data()
+            .exclusions
+            .insert(u_id.to_string(), exclude.to_vec());
+        Ok(())
+    }
+
+    async fn plan_settings(&self, u_id: &str) -> ApiResult<PlanSettings> {
+        Ok(self
+            .data()
+            .plan_settings
+            .get(u_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn set_plan_settings(&self, u_id: &str, settings: &PlanSettings) -> ApiResult<()> {
+        self.data()
+            .plan_settings
+            .insert(u_id.to_string(), settings.clone());
+        Ok(())
+    }
+
+    async fn picker_weights(&self, u_id: &str) -> ApiResult<PickerWeights> {
+        Ok(self
+            .data()
+            .picker_weights
+            .get(u_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    async fn set_picker_weights(&self, u_id: &str, weights: &PickerWeights) -> ApiResult<()> {
+        self.data()
+            .picker_weights
+            .insert(u_id.to_string(), weights.clone());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ShoppingListStore for MemoryStore {
+    async fn shopping_list(&self, u_id: &str) -> ApiResult<Option<SavedShoppingList>> {
+        Ok(self.data().shopping_lists.get(u_id).cloned())
+    }
+
+    async fn save_shopping_list(&self, u_id: &str, list: &SavedShoppingList) -> ApiResult<()> {
+        self.data()
+            .shopping_lists
+            .insert(u_id.to_string(), list.clone());
+        Ok(())
+    }
+
+    async fn remove_shopping_list(&self, u_id: &str) -> ApiResult<()> {
+        self.data().shopping_lists.remove(u_id);
+        Ok(())
+    }
+
+    async fn add_shopping_item(&self, u_id: &str, item: &ShoppingListItem) -> ApiResult<bool> {
+        Ok(match self.data().shopping_lists.get_mut(u_id) {
+            Some(list) => {
+                list.items.push(item.clone());
+                true
+            }
+            None => false,
+        })
+    }
+
+    async fn update_shopping_item(
+        &self,
+        u_id: &str,
+        item_id: &str,
+        update: &ShoppingItemUpdate,
+    ) -> ApiResult<bool> {
+        let mut data = self.data();
+        let item = match data.shopping_item_mut(u_id, item_id) {
+            Some(item) => item,
+            None => return Ok(false),
+        };
+        if let Some(name) = &update.name {
+            item.name = name.clone();
+            item.manual = true;
+        }
+        if let Some(tipo) = &update.tipo {
+            item.tipo = tipo.clone();
+            item.manual = true;
+        }
+        if let Some(amount) = &update.amount {
+            item.amount = amount.clone();
+            item.manual = true;
+        }
+        if let Some(checked) = update.checked {
+            item.checked = checked;
+        }
+        Ok(true)
+    }
+
+    async fn toggle_shopping_item(&self, u_id: &str, item_id: &str) -> ApiResult<bool> {
+        let mut data = self.data();
+        Ok(match data.shopping_item_mut(u_id, item_id) {
+            Some(item) => {
+                item.checked = !item.checked;
+                true
+            }
+            None => false,
+        })
+    }
+
+    async fn remove_shopping_item(&self, u_id: &str, item_id: &str) -> ApiResult<bool> {
+        let item_id = match parse_id(item_id) {
+            Some(id) => id,
+            None => return Ok(false),
+        };
+        let mut data = self.data();
+        let list = match data.shopping_lists.get_mut(u_id) {
+            Some(list) => list,
+            None => return Ok(false),
+        };
+        let before = list.items.len();
+        list.items.retain(|item| item.id != item_id);
+        Ok(list.items.len() < before)
+    }
+}
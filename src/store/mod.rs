@@ -0,0 +1,257 @@
+pub mod memory;
+pub mod neo4j;
+#[cfg(test)]
+mod tests;
+
+use crate::errors::{ApiError, ApiResult};
+use crate::models::{
+    CookEntry, CookableRecipe, CookedRecipe, LikeResult, PickerWeights, PlanEntry, PlanSettings,
+    PlanSlot, PlannedMeal, RatingForm, RatingResult, Recipe, RecipeQuery, RecipeUpdate, RecipeVec,
+    ReviewQuery, Reviews, SavedShoppingList, ShoppingItemUpdate, ShoppingListItem, User,
+};
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime};
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub use memory::MemoryStore;
+pub use neo4j::Neo4jStore;
+
+// This is what gets managed by rocket. Routes and guards only ever talk to the store so we can
+// swap neo4j for the in memory one without touching them.
+pub type StorePool = Arc<dyn Store>;
+
+// Everything the recipe routes need. Ids are the uuid strings we get from the cookies and the
+// urls, the store decides what to do with ids that don't exist.
+#[async_trait]
+pub trait RecipeStore: Send + Sync {
+    async fn all_recipes(&self) -> ApiResult<Vec<Recipe>>;
+
+    // Returns the id of the new recipe
+    async fn create_recipe(&self, u_id: &str, recipe: &Recipe) -> ApiResult<Uuid>;
+
+    // Returns false if the user doesn't own the recipe, in which case nothing is changed.
+    async fn update_recipe(&self, u_id: &str, r_id: &str, update: &RecipeUpdate)
+        -> ApiResult<bool>;
+
+    async fn remove_recipe(&self, u_id: &str, r_id: &str) -> ApiResult<()>;
+
+    // A recipe the user owns or any public one
+    async fn get_recipe(&self, u_id: &str, r_id: &str) -> ApiResult<Option<Recipe>>;
+
+    async fn get_public_recipe(&self, r_id: &str) -> ApiResult<Option<Recipe>>;
+
+    // Any recipe at all, used by the share links
+    async fn find_recipe(&self, r_id: &str) -> ApiResult<Option<Recipe>>;
+
+    // Recipes the user OWNS or LIKES
+    async fn user_recipes(&self, u_id: &str) -> ApiResult<Vec<Recipe>>;
+
+    // Recipes the user OWNS or LIKES plus the public ones, with the relationships filled in. The
+    // relationships cover every recipe, not just the ones in the page.
+    async fn recipe_list(&self, u_id: &str, list: &RecipeQuery) -> ApiResult<RecipeVec>;
+
+    async fn public_recipes(&self, list: &RecipeQuery) -> ApiResult<RecipeVec>;
+
+    // The user's own recipes and the public ones using the ingredient
+    async fn recipes_by_ingredient(
+        &self,
+        u_id: &str,
+        ingredient: &str,
+        list: &RecipeQuery,
+    ) -> ApiResult<RecipeVec>;
+
+    // Recipes the user OWNS or LIKES plus the public ones matching the terms from `search_terms`,
+    // best match first along with its score. Recipes using an excluded ingredient are left out.
+    async fn search_recipes(
+        &self,
+        u_id: &str,
+        terms: &[String],
+        excluded: &[String],
+        limit: usize,
+    ) -> ApiResult<Vec<(Recipe, f32)>>;
+
+    // Recipes the user OWNS or LIKES plus the public ones using any of the ingredients at hand,
+    // the names come from `on_hand_names`. The ones covered the most go first, ties go to the
+    // recipe missing fewer ingredients.
+    async fn cookable_recipes(
+        &self,
+        u_id: &str,
+        on_hand: &[String],
+        excluded: &[String],
+        limit: usize,
+    ) -> ApiResult<Vec<CookableRecipe>>;
+
+    // Returns false if no recipe uses an ingredient with that name.
+    async fn set_allergens(&self, ingredient: &str, allergens: &[String]) -> ApiResult<bool>;
+
+    async fn allergens(&self, ingredient: &str) -> ApiResult<Vec<String>>;
+
+    // Returns false if no recipe uses an ingredient with that name.
+    async fn set_density(&self, ingredient: &str, density: f64) -> ApiResult<bool>;
+
+    // Only the ingredients that have a density set are in the map
+    async fn ingredient_densities(&self, names: &[String]) -> ApiResult<HashMap<String, f64>>;
+
+    // The names plus every ingredient tagged with one of them as an allergen.
+    async fn resolve_exclusions(&self, names: &[String]) -> ApiResult<Vec<String>>;
+
+    async fn like_recipe(&self, u_id: &str, r_id: &str) -> ApiResult<LikeResult>;
+
+    // How many users like each of the recipes, recipes nobody likes can be left out
+    async fn like_counts(&self, r_ids: &[String]) -> ApiResult<HashMap<Uuid, usize>>;
+
+    // Sets the user's rating of a public recipe and updates the recipe's `average_rating` and
+    // `rating_count`. The route checks the stars. Recipes that don't exist or that are private
+    // to someone else are not found, nothing gets saved for them.
+    async fn rate_recipe(
+        &self,
+        u_id: &str,
+        r_id: &str,
+        rating: &RatingForm,
+        at: NaiveDateTime,
+    ) -> ApiResult<RatingResult>;
+
+    async fn remove_rating(&self, u_id: &str, r_id: &str) -> ApiResult<()>;
+
+    // The ratings of the recipe that came with a review, newest first
+    async fn reviews(&self, r_id: &str, page: &ReviewQuery) -> ApiResult<Reviews>;
+
+    // Flips the recipe in or out of the user's favorites, returns whether it's one now. The
+    // route checks the user can see the recipe.
+    async fn toggle_favorite(&self, u_id: &str, r_id: &str) -> ApiResult<bool>;
+
+    async fn favorites(&self, u_id: &str) -> ApiResult<Vec<Uuid>>;
+
+    // Puts the recipes in the user's meal plan, entries for recipes that don't exist are skipped.
+    async fn choose_recipes(
+        &self,
+        u_id: &str,
+        entries: &[PlanEntry],
+        created: NaiveDateTime,
+    ) -> ApiResult<()>;
+
+    // Every recipe in the meal plan in plan order, a recipe planned twice shows up twice
+    async fn chosen_recipes(&self, u_id: &str) -> ApiResult<Vec<Recipe>>;
+
+    // The meal plan itself, same order as `chosen_recipes`
+    async fn chosen_meals(&self, u_id: &str) -> ApiResult<Vec<PlannedMeal>>;
+
+    // Empties the whole meal plan
+    async fn reset_chosen(&self, u_id: &str) -> ApiResult<()>;
+
+    // The planned meals from `from` up to but not including `to`, by day and slot
+    async fn meal_plan(
+        &self,
+        u_id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> ApiResult<Vec<PlannedMeal>>;
+
+    // Returns false if the user has no planned meal with that id
+    async fn move_planned_meal(&self, u_id: &str, m_id: &str, to: &PlanSlot) -> ApiResult<bool>;
+
+    // The two meals trade days and slots. Returns false if either of them isn't in the user's plan.
+    async fn swap_planned_meals(&self, u_id: &str, first: &str, second: &str) -> ApiResult<bool>;
+
+    async fn clear_plan_day(&self, u_id: &str, date: NaiveDate) -> ApiResult<()>;
+
+    // Moves the plan days before `before` to the history, returns how many meals moved
+    async fn archive_plan(
+        &self,
+        u_id: &str,
+        before: NaiveDate,
+        archived: NaiveDateTime,
+    ) -> ApiResult<usize>;
+
+    // Archived meals from `from` up to but not including `to`, by day and slot
+    async fn plan_history(
+        &self,
+        u_id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> ApiResult<Vec<PlannedMeal>>;
+
+    // Adds an entry to the user's cook log and counts it in the recipe's `times_cooked` and
+    // `last_cooked`, returns the id of the entry. The route checks the user can see the recipe.
+    async fn log_cooked(&self, u_id: &str, r_id: &str, cooked: &CookEntry) -> ApiResult<Uuid>;
+
+    // The user's cook log from `from` up to but not including `to`, oldest first
+    async fn cook_log(
+        &self,
+        u_id: &str,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> ApiResult<Vec<CookedRecipe>>;
+}
+
+// The users we hand back keep the password hash in `password`, it's up to the caller to not leak
+// it anywhere.
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    async fn all_users(&self) -> ApiResult<Vec<User>>;
+
+    async fn get_user(&self, u_id: &str) -> ApiResult<Option<User>>;
+
+    async fn get_user_by_name(&self, username: &str) -> ApiResult<Option<User>>;
+
+    // Usernames and emails are unique, taking one that's in use fails with `user_conflict`.
+    async fn create_user(&self, user: &User) -> ApiResult<()>;
+
+    // Ingredient names or allergens the user excludes from every listing
+    async fn exclusions(&self, u_id: &str) -> ApiResult<Vec<String>>;
+
+    async fn set_exclusions(&self, u_id: &str, exclude: &[String]) -> ApiResult<()>;
+
+    // The defaults for users that never changed them
+    async fn plan_settings(&self, u_id: &str) -> ApiResult<PlanSettings>;
+
+    async fn set_plan_settings(&self, u_id: &str, settings: &PlanSettings) -> ApiResult<()>;
+
+    // The defaults for users that never changed them
+    async fn picker_weights(&self, u_id: &str) -> ApiResult<PickerWeights>;
+
+    async fn set_picker_weights(&self, u_id: &str, weights: &PickerWeights) -> ApiResult<()>;
+}
+
+// Every user has at most one list. Changes are made on the whole list and saved back, the
+// lists are small enough for that.
+#[async_trait]
+pub trait ShoppingListStore: Send + Sync {
+    async fn shopping_list(&self, u_id: &str) -> ApiResult<Option<SavedShoppingList>>;
+
+    // Replaces whatever list the user had
+    async fn save_shopping_list(&self, u_id: &str, list: &SavedShoppingList) -> ApiResult<()>;
+
+    async fn remove_shopping_list(&self, u_id: &str) -> ApiResult<()>;
+
+    // The item changes below only write the one item, so two of them at the same time can't undo
+    // each other. They return false when the user has no list or the list has no such item.
+    async fn add_shopping_item(&self, u_id: &str, item: &ShoppingListItem) -> ApiResult<bool>;
+
+    // Changing anything but `checked` makes the item a manual one
+    async fn update_shopping_item(
+        &self,
+        u_id: &str,
+        item_id: &str,
+        update: &ShoppingItemUpdate,
+    ) -> ApiResult<bool>;
+
+    async fn toggle_shopping_item(&self, u_id: &str, item_id: &str) -> ApiResult<bool>;
+
+    async fn remove_shopping_item(&self, u_id: &str, item_id: &str) -> ApiResult<bool>;
+}
+
+// What new_user sends back when the username or email is already taken. These are the statuses
+// the frontend checks for so they stay as they were.
+pub fn user_conflict(field: &str) -> ApiError {
+    if field == "username" {
+        return ApiError::Forbidden("Username already in use".to_string());
+    }
+    ApiError::Unauthorized("Email already in use".to_string())
+}
+
+pub trait Store: RecipeStore + UserStore + ShoppingListStore {}
+
+impl<T: RecipeStore + UserStore + ShoppingListStore> Store for T {}
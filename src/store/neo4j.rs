@@ -0,0 +1,1419 @@
+use crate::errors::{ApiError, ApiResult};
+use crate::helpers::recipes::{
+    cookable_recipe, excluded_clause, finish_txn, format_cooked_recipe, format_planned_meal,
+    format_recipes, format_review, optional, paged_recipe_queries, recipes_query,
+    set_recipe_ingredients, steps_text, update_recipe_in_db, with_minutes, RATING_TOTALS,
+    RECIPE_INGREDIENTS,
+};
+use crate::helpers::search::fulltext_query;
+use crate::helpers::shopping::format_shopping_list;
+use crate::helpers::users::{format_user, get_user_from_db};
+use crate::models::{
+    CookEntry, CookableRecipe, CookedRecipe, GraphPool, LikeResult, PageInfo, PickerWeights,
+    PlanEntry, PlanSettings, PlanSlot, PlannedMeal, RatingForm, RatingResult, Recipe, RecipeQuery,
+    RecipeRelationships, RecipeUpdate, RecipeVec, ReviewQuery, Reviews, SavedShoppingList,
+    ShoppingItemUpdate, ShoppingListItem, User,
+};
+use crate::store::{user_conflict, RecipeStore, ShoppingListStore, UserStore};
+use async_trait::async_trait;
+use chrono::{NaiveDate, NaiveDateTime, Utc};
+use neo4rs::*;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+pub struct Neo4jStore {
+    graph: GraphPool,
+}
+
+impl Neo4jStore {
+    pub fn new(graph: GraphPool) -> Self {
+        Neo4jStore { graph }
+    }
+
+    // Runs a query built with `recipes_query`, the ingredients come back in the same rows
+    async fn fetch_recipes(&self, recipes_query: Query) -> ApiResult<Vec<Recipe>> {
+        let mut result = self.graph.execute(recipes_query).await?;
+
+        let mut recipes_vector = Vec::new();
+
+        while let Some(row) = result.next().await? {
+            recipes_vector.push(format_recipes(row)?)
+        }
+        Ok(recipes_vector)
+    }
+
+    // Runs a `recipes_query` that also returns the CHOSEN relationship as `c`. Slots are sorted
+    // here since their order isn't the alphabetical one.
+    async fn fetch_planned_meals(&self, plan_query: Query) -> ApiResult<Vec<PlannedMeal>> {
+        let mut result = self.graph.execute(plan_query).await?;
+        let mut meals = Vec::new();
+        while let Some(row) = result.next().await? {
+            meals.push(format_planned_meal(row)?);
+        }
+        meals.sort_by_key(|meal| (meal.date, meal.slot));
+        Ok(meals)
+    }
+
+    // Runs the two queries from `paged_recipe_queries`
+    async fn fetch_page(
+        &self,
+        page_query: Query,
+        count_query: Query,
+        list: &RecipeQuery,
+    ) -> ApiResult<(Vec<Recipe>, PageInfo)> {
+        let recipes = self.fetch_recipes(page_query).await?;
+        let mut count = self.graph.execute(count_query).await?;
+        let total = count
+            .next()
+            .await?
+            .and_then(|row| row.get::<i64>("total"))
+            .unwrap_or(0);
+        Ok((recipes, list.page_info(total as usize)))
+    }
+
+    async fn fetch_recipe(&self, recipe_query: Query) -> ApiResult<Option<Recipe>> {
+        Ok(self.fetch_recipes(recipe_query).await?.into_iter().next())
+    }
+}
+
+// Both writes below run inside the transaction they're handed, see `finish_txn`.
+async fn create_recipe_in_txn(
+    txn: &Txn,
+    u_id: &str,
+    recipe_uuid: Uuid,
+    recipe: &Recipe,
+) -> ApiResult<()> {
+    let create = query(&format!(
+        "MATCH (u:User) WHERE u.id = $uid \
+        MERGE (u)-[:OWNS]->(:Recipe {{id: $id, name: $name, public: $public, \
+        tipo: $tipo, steps: $steps, steps_text: $steps_text, calories: $calories, \
+        carbohydrates: $carbohydrates, fat: $fat, protein: $protein, \
+        servings: $servings, yield_unit: $yield_unit, meal_type: $meal_type, time: $time, \
+        minutes: {}, created: $created}})",
+        optional("minutes", "")
+    ))
+    .param("uid", u_id)
+    .param("id", recipe_uuid.to_string())
+    .param("name", recipe.name.clone())
+    .param("public", recipe.public.unwrap_or(false))
+    .param("tipo", recipe.tipo.clone().unwrap_or_default())
+    .param("steps", recipe.steps.clone().unwrap_or_default())
+    .param(
+        "steps_text",
+        steps_text(recipe.steps.as_deref().unwrap_or_default()),
+    )
+    .param("calories", recipe.calories.unwrap_or(0))
+    .param("carbohydrates", recipe.carbohydrates.unwrap_or(0.0))
+    .param("fat", recipe.fat.unwrap_or(0.0))
+    .param("protein", recipe.protein.unwrap_or(0.0))
+    .param("servings", recipe.servings.unwrap_or(0))
+    .param("yield_unit", recipe.yield_unit.clone().unwrap_or_default())
+    .param("meal_type", recipe.meal_type.clone().unwrap_or_default())
+    .param("time", recipe.time.clone().unwrap_or_default())
+    .param("created", Utc::now().naive_utc());
+    txn.run(with_minutes(create, recipe.time.as_deref()))
+        .await?;
+
+    if let Some(ingredients) = &recipe.ingredients {
+        set_recipe_ingredients(txn, &recipe_uuid.to_string(), ingredients).await?;
+    }
+    Ok(())
+}
+
+async fn update_recipe_in_txn(
+    txn: &Txn,
+    u_id: &str,
+    r_id: &str,
+    update: &RecipeUpdate,
+) -> ApiResult<bool> {
+    let mut res = txn
+        .execute(
+            query(
+                "MATCH (u:User)-[:OWNS]->(r:Recipe) \
+            WHERE u.id = $u_id AND r.id = $r_id \
+            RETURN r",
+            )
+            .param("u_id", u_id)
+            .param("r_id", r_id),
+        )
+        .await?;
+    if res.next().await?.is_none() {
+        return Ok(false);
+    }
+    update_recipe_in_db(txn, r_id, update).await?;
+    Ok(true)
+}
+
+async fn remove_shopping_list_in_txn(txn: &Txn, u_id: &str) -> ApiResult<()> {
+    txn.run(
+        query(
+            "MATCH (u:User)-[:HAS_LIST]->(l:ShoppingList) WHERE u.id = $u_id \
+        OPTIONAL MATCH (l)-[:CONTAINS]->(i:ShoppingItem) \
+        DETACH DELETE l, i",
+        )
+        .param("u_id", u_id),
+    )
+    .await?;
+    Ok(())
+}
+
+// Same trick as `set_recipe_ingredients`, the items go as one list per field. The position keeps
+// them in the order they were saved.
+async fn save_shopping_list_in_txn(
+    txn: &Txn,
+    u_id: &str,
+    list: &SavedShoppingList,
+) -> ApiResult<()> {
+    remove_shopping_list_in_txn(txn, u_id).await?;
+
+    let items = &list.items;
+    let ids: Vec<String> = items.iter().map(|i| i.id.to_string()).collect();
+    let names: Vec<String> = items.iter().map(|i| i.name.clone()).collect();
+    let tipos: Vec<String> = items.iter().map(|i| i.tipo.clone()).collect();
+    let amounts: Vec<String> = items.iter().map(|i| i.amount.clone()).collect();
+    let checked: Vec<bool> = items.iter().map(|i| i.checked).collect();
+    let manual: Vec<bool> = items.iter().map(|i| i.manual).collect();
+
+    txn.run(
+        query(
+            "MATCH (u:User) WHERE u.id = $u_id \
+        CREATE (u)-[:HAS_LIST]->(l:ShoppingList {id: $id, created: $created}) \
+        WITH l \
+        UNWIND range(0, size($ids) - 1) AS idx \
+        CREATE (l)-[:CONTAINS]->(:ShoppingItem {id: $ids[idx], name: $names[idx], \
+        tipo: $tipos[idx], amount: $amounts[idx], checked: $checked[idx], \
+        manual: $manual[idx], position: idx})",
+        )
+        .param("u_id", u_id)
+        .param("id", list.id.to_string())
+        .param("created", list.created)
+        .param("ids", ids)
+        .param("names", names)
+        .param("tipos", tipos)
+        .param("amounts", amounts)
+        .param("checked", checked)
+        .param("manual", manual),
+    )
+    .await?;
+
+    if let Some(plan) = &list.plan {
+        txn.run(
+            query("MATCH (l:ShoppingList) WHERE l.id = $id SET l.plan = $plan")
+                .param("id", list.id.to_string())
+                .param("plan", plan.clone()),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+// The one item of the user's list the item queries work on, they all return how many they
+// touched as `changed`
+const SHOPPING_ITEM: &str = "MATCH (u:User)-[:HAS_LIST]->(:ShoppingList)-[:CONTAINS]->\
+    (i:ShoppingItem) WHERE u.id = $u_id AND i.id = $i_id";
+
+async fn any_changed(mut rows: RowStream) -> ApiResult<bool> {
+    let changed = rows
+        .next()
+        .await?
+        .and_then(|row| row.get::<i64>("changed"))
+        .unwrap_or(0);
+    Ok(changed > 0)
+}
+
+// Which of the unique user fields another user already has, the username if both are. Users
+// without an email are stored with '' which doesn't count as taken.
+fn taken_user_query(user: &User) -> Query {
+    query(
+        "MATCH (u:User) WHERE u.username = $uname OR ($mail <> '' AND u.email = $mail) \
+        RETURN u.username = $uname AS username ORDER BY username DESC LIMIT 1",
+    )
+    .param("uname", user.username.clone())
+    .param("mail", user.email.clone().unwrap_or_default())
+}
+
+async fn taken_user_field(mut rows: RowStream) -> ApiResult<Option<&'static str>> {
+    Ok(rows.next().await?.map(|row| {
+        if row.get::<bool>("username").unwrap_or(false) {
+            "username"
+        } else {
+            "email"
+        }
+    }))
+}
+
+async fn create_user_in_txn(txn: &Txn, user: &User) -> ApiResult<()> {
+    if let Some(field) = taken_user_field(txn.execute(taken_user_query(user)).await?).await? {
+        return Err(user_conflict(field));
+    }
+    txn.run(
+        query(
+            "CREATE (:User {username: $uname, id: $uid, password: $pass, email: $mail, \
+        role: $role})",
+        )
+        .param("uname", user.username.clone())
+        .param("uid", user.id.unwrap().to_string())
+        .param("pass", user.password.clone())
+        .param("mail", user.email.clone().unwrap_or_default())
+        .param("role", user.role.clone().unwrap_or_default()),
+    )
+    .await?;
+    Ok(())
+}
+
+#[async_trait]
+impl RecipeStore for Neo4jStore {
+    async fn all_recipes(&self) -> ApiResult<Vec<Recipe>> {
+        self.fetch_recipes(recipes_query("MATCH (r:Recipe)")).await
+    }
+
+    async fn create_recipe(&self, u_id: &str, recipe: &Recipe) -> ApiResult<Uuid> {
+        let recipe_uuid = Uuid::new_v4();
+        let txn = self.graph.start_txn().await?;
+        let result = create_recipe_in_txn(&txn, u_id, recipe_uuid, recipe).await;
+        finish_txn(txn, result).await?;
+        Ok(recipe_uuid)
+    }
+
+    async fn update_recipe(
+        &self,
+        u_id: &str,
+        r_id: &str,
+        update: &RecipeUpdate,
+    ) -> ApiResult<bool> {
+        let txn = self.graph.start_txn().await?;
+        let result = update_recipe_in_txn(&txn, u_id, r_id, update).await;
+        finish_txn(txn, result).await
+    }
+
+    async fn remove_recipe(&self, u_id: &str, r_id: &str) -> ApiResult<()> {
+        self.graph
+            .run(
+                query(
+                    "MATCH (u:User)-[:OWNS]->(r:Recipe) \
+                WHERE u.id = $u_id AND r.id = $r_id \
+                DETACH DELETE r",
+                )
+                .param("u_id", u_id)
+                .param("r_id", r_id),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_recipe(&self, u_id: &str, r_id: &str) -> ApiResult<Option<Recipe>> {
+        self.fetch_recipe(
+            recipes_query(
+                "MATCH (u:User)-[:OWNS]->(r:Recipe) \
+           WHERE (u.id = $u_id AND r.id = $r_id) OR (r.id = $r_id AND r.public = true)",
+            )
+            .param("u_id", u_id)
+            .param("r_id", r_id),
+        )
+        .await
+    }
+
+    async fn get_public_recipe(&self, r_id: &str) -> ApiResult<Option<Recipe>> {
+        self.fetch_recipe(
+            recipes_query(
+                "MATCH (r:Recipe) \
+           WHERE r.id = $r_id AND r.public = true",
+            )
+            .param("r_id", r_id),
+        )
+        .await
+    }
+
+    async fn find_recipe(&self, r_id: &str) -> ApiResult<Option<Recipe>> {
+        self.fetch_recipe(recipes_query("MATCH (r:Recipe) WHERE r.id = $r_id").param("r_id", r_id))
+            .await
+    }
+
+    async fn user_recipes(&self, u_id: &str) -> ApiResult<Vec<Recipe>> {
+        self.fetch_recipes(
+            recipes_query("MATCH (r:Recipe)-[:OWNS|:LIKES]-(u:User) WHERE u.id = $id")
+                .param("id", u_id),
+        )
+        .await
+    }
+
+    async fn recipe_list(&self, u_id: &str, list: &RecipeQuery) -> ApiResult<RecipeVec> {
+        let mut relationships = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (u:User)-[c:OWNS|LIKES]->(r:Recipe) \
+            WHERE u.id = $u_id \
+            RETURN r.id AS id, type(c) AS rel",
+                )
+                .param("u_id", u_id),
+            )
+            .await?;
+
+        let mut rel_struct = RecipeRelationships {
+            owns: Vec::new(),
+            likes: Vec::new(),
+        };
+
+        while let Some(row) = relationships.next().await? {
+            let id = row
+                .get::<String>("id")
+                .and_then(|id| Uuid::parse_str(&id).ok())
+                .ok_or_else(|| ApiError::Database("Malformed recipe id".to_string()))?;
+            match row.get::<String>("rel").as_deref() {
+                Some("OWNS") => rel_struct.owns.push(id),
+                Some("LIKES") => rel_struct.likes.push(id),
+                _ => {}
+            }
+        }
+
+        // Everything the user OWNS or LIKES plus every public recipe
+        let (page_query, count_query) = paged_recipe_queries(
+            "MATCH (r:Recipe) \
+            WHERE r.public = true OR EXISTS { MATCH (u:User)-[:OWNS|LIKES]->(r) WHERE u.id = $u_id }",
+            list,
+        );
+        let (recipes, page) = self
+            .fetch_page(
+                page_query.param("u_id", u_id),
+                count_query.param("u_id", u_id),
+                list,
+            )
+            .await?;
+
+        Ok(RecipeVec {
+            recipes,
+            rels: Option::from(rel_struct),
+            page: Option::from(page),
+        })
+    }
+
+    async fn public_recipes(&self, list: &RecipeQuery) -> ApiResult<RecipeVec> {
+        let (page_query, count_query) =
+            paged_recipe_queries("MATCH (r:Recipe) WHERE r.public = true", list);
+        let (recipes, page) = self.fetch_page(page_query, count_query, list).await?;
+
+        Ok(RecipeVec {
+            recipes,
+            rels: None,
+            page: Option::from(page),
+        })
+    }
+
+    async fn recipes_by_ingredient(
+        &self,
+        u_id: &str,
+        ingredient: &str,
+        list: &RecipeQuery,
+    ) -> ApiResult<RecipeVec> {
+        // The user's own recipes plus the public ones
+        let (page_query, count_query) = paged_recipe_queries(
+            "MATCH (r:Recipe)-[:USES]->(i:Ingredient) \
+            WHERE i.name = $ing \
+            AND (r.public = true OR EXISTS { MATCH (u:User)-[:OWNS]->(r) WHERE u.id = $id })",
+            list,
+        );
+        let (recipes, page) = self
+            .fetch_page(
+                page_query.param("id", u_id).param("ing", ingredient),
+                count_query.param("id", u_id).param("ing", ingredient),
+                list,
+            )
+            .await?;
+
+        Ok(RecipeVec {
+            recipes,
+            rels: None,
+            page: Option::from(page),
+        })
+    }
+
+    async fn search_recipes(
+        &self,
+        u_id: &str,
+        terms: &[String],
+        excluded: &[String],
+        limit: usize,
+    ) -> ApiResult<Vec<(Recipe, f32)>> {
+        // Both fulltext indexes from the 004 migration, a recipe's score adds up its own hit and
+        // the hits of the ingredients it uses.
+        let search_query = query(&format!(
+            "CALL {{ \
+                CALL db.index.fulltext.queryNodes('recipe_text', $search) YIELD node, score \
+                RETURN node AS r, score \
+                UNION ALL \
+                CALL db.index.fulltext.queryNodes('ingredient_names', $search) YIELD node, score \
+                MATCH (r:Recipe)-[:USES]->(node) \
+                RETURN r, score \
+            }} \
+            WITH r, sum(score) AS score \
+            WHERE (r.public = true OR EXISTS {{ MATCH (u:User)-[:OWNS|LIKES]->(r) WHERE u.id = $u_id }}) \
+            AND {} \
+            WITH r, score ORDER BY score DESC, r.id LIMIT $limit \
+            RETURN r, score, {}",
+            excluded_clause("exclude"),
+            RECIPE_INGREDIENTS
+        ))
+        .param("search", fulltext_query(terms))
+        .param("u_id", u_id)
+        .param("exclude", excluded.to_vec())
+        .param("limit", limit as i64);
+
+        let mut result = self.graph.execute(search_query).await?;
+        let mut hits = Vec::new();
+        while let Some(row) = result.next().await? {
+            let score = row.get::<f64>("score").unwrap_or(0.0);
+            hits.push((format_recipes(row)?, score as f32));
+        }
+        Ok(hits)
+    }
+
+    async fn cookable_recipes(
+        &self,
+        u_id: &str,
+        on_hand: &[String],
+        excluded: &[String],
+        limit: usize,
+    ) -> ApiResult<Vec<CookableRecipe>> {
+        // Only the ordering is worked out here, `cookable_recipe` fills in the coverage and the
+        // missing ingredients from the rows. Names are counted instead of nodes since the same
+        // name can be merged with different tipos.
+        let recipes = self
+            .fetch_recipes(
+                query(&format!(
+                    "MATCH (r:Recipe)-[:USES]->(i:Ingredient) \
+                WHERE i.name IN $on_hand \
+                AND (r.public = true OR EXISTS {{ MATCH (u:User)-[:OWNS|LIKES]->(r) WHERE u.id = $u_id }}) \
+                AND {} \
+                WITH DISTINCT r \
+                MATCH (r)-[:USES]->(ing:Ingredient) \
+                WITH r, count(DISTINCT ing.name) AS total, \
+                count(DISTINCT CASE WHEN ing.name IN $on_hand THEN ing.name END) AS have \
+                WITH r ORDER BY toFloat(have) / total DESC, total - have, r.id LIMIT $limit \
+                RETURN r, {}",
+                    excluded_clause("exclude"),
+                    RECIPE_INGREDIENTS
+                ))
+                .param("on_hand", on_hand.to_vec())
+                .param("u_id", u_id)
+                .param("exclude", excluded.to_vec())
+                .param("limit", limit as i64),
+            )
+            .await?;
+
+        Ok(recipes
+            .into_iter()
+            .map(|recipe| cookable_recipe(recipe, on_hand))
+            .collect())
+    }
+
+    // Ingredients are merged by name and tipo so there can be more than one node with the name,
+    // all of them get the allergens.
+    async fn set_allergens(&self, ingredient: &str, allergens: &[String]) -> ApiResult<bool> {
+        let mut result = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (i:Ingredient) WHERE i.name = $name \
+                SET i.allergens = $allergens \
+                RETURN count(i) AS tagged",
+                )
+                .param("name", ingredient)
+                .param("allergens", allergens.to_vec()),
+            )
+            .await?;
+        let tagged = result
+            .next()
+            .await?
+            .and_then(|row| row.get::<i64>("tagged"))
+            .unwrap_or(0);
+        Ok(tagged > 0)
+    }
+
+    async fn allergens(&self, ingredient: &str) -> ApiResult<Vec<String>> {
+        let mut result = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (i:Ingredient) WHERE i.name = $name \
+                UNWIND coalesce(i.allergens, []) AS allergen \
+                RETURN collect(DISTINCT allergen) AS allergens",
+                )
+                .param("name", ingredient),
+            )
+            .await?;
+        Ok(result
+            .next()
+            .await?
+            .and_then(|row| row.get::<Vec<String>>("allergens"))
+            .unwrap_or_default())
+    }
+
+    async fn set_density(&self, ingredient: &str, density: f64) -> ApiResult<bool> {
+        let mut result = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (i:Ingredient) WHERE i.name = $name \
+                SET i.density = $density \
+                RETURN count(i) AS updated",
+                )
+                .param("name", ingredient)
+                .param("density", density),
+            )
+            .await?;
+        let updated = result
+            .next()
+            .await?
+            .and_then(|row| row.get::<i64>("updated"))
+            .unwrap_or(0);
+        Ok(updated > 0)
+    }
+
+    async fn ingredient_densities(&self, names: &[String]) -> ApiResult<HashMap<String, f64>> {
+        let mut result = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (i:Ingredient) \
+                WHERE i.name IN $names AND i.density IS NOT NULL \
+                RETURN i.name AS name, i.density AS density",
+                )
+                .param("names", names.to_vec()),
+            )
+            .await?;
+        let mut densities = HashMap::new();
+        while let Some(row) = result.next().await? {
+            if let (Some(name), Some(density)) = (row.get::<String>("name"), row.get("density")) {
+                densities.insert(name, density);
+            }
+        }
+        Ok(densities)
+    }
+
+    async fn resolve_exclusions(&self, names: &[String]) -> ApiResult<Vec<String>> {
+        let mut result = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (i:Ingredient) \
+                WHERE any(allergen IN coalesce(i.allergens, []) WHERE allergen IN $names) \
+                RETURN DISTINCT i.name AS name",
+                )
+                .param("names", names.to_vec()),
+            )
+            .await?;
+        let mut resolved = names.to_vec();
+        while let Some(row) = result.next().await? {
+            if let Some(name) = row.get::<String>("name") {
+                if !resolved.contains(&name) {
+                    resolved.push(name);
+                }
+            }
+        }
+        Ok(resolved)
+    }
+
+    async fn like_recipe(&self, u_id: &str, r_id: &str) -> ApiResult<LikeResult> {
+        let mut recipe_stream = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (r:Recipe)-[c:LIKES|OWNS]-(u:User) WHERE u.id = $u_id AND r.id = $r_id \
+                RETURN r, c",
+                )
+                .param("u_id", u_id)
+                .param("r_id", r_id),
+            )
+            .await?;
+
+        let recipe_row = recipe_stream.next().await?;
+        if recipe_row.is_none() {
+            self.graph
+                .run(
+                    query(
+                        "MATCH (u:User), (r:Recipe) \
+                    WHERE u.id = $u_id AND (r.id = $r_id AND r.public = true) \
+                    MERGE (u)-[:LIKES]->(r)",
+                    )
+                    .param("u_id", u_id)
+                    .param("r_id", r_id),
+                )
+                .await?;
+            return Ok(LikeResult::Liked);
+        }
+        let relationship_node = recipe_row
+            .unwrap()
+            .get::<Relation>("c")
+            .ok_or_else(|| ApiError::Database("Missing relationship".to_string()))?;
+        if &relationship_node.typ() == "OWNS" {
+            return Ok(LikeResult::Owned);
+        }
+        self.graph
+            .run(
+                query(
+                    "MATCH (u:User)-[l:LIKES]->(r:Recipe) \
+                WHERE u.id = $u_id AND r.id = $r_id \
+                DETACH DELETE l",
+                )
+                .param("u_id", u_id)
+                .param("r_id", r_id),
+            )
+            .await?;
+        Ok(LikeResult::Unliked)
+    }
+
+    async fn rate_recipe(
+        &self,
+        u_id: &str,
+        r_id: &str,
+        rating: &RatingForm,
+        at: NaiveDateTime,
+    ) -> ApiResult<RatingResult> {
+        let mut result = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (u:User), (r:Recipe) WHERE u.id = $u_id AND r.id = $r_id \
+                RETURN EXISTS { MATCH (u)-[:OWNS]->(r) } AS owned, \
+                    EXISTS { MATCH (u)-[:RATED]->(r) } AS rated, \
+                    coalesce(r.public, false) AS public",
+                )
+                .param("u_id", u_id)
+                .param("r_id", r_id),
+            )
+            .await?;
+        let (owned, rated, public) = match result.next().await? {
+            Some(row) => (
+                row.get::<bool>("owned").unwrap_or(false),
+                row.get::<bool>("rated").unwrap_or(false),
+                row.get::<bool>("public").unwrap_or(false),
+            ),
+            None => return Err(ApiError::not_found("Recipe")),
+        };
+        if owned {
+            return Ok(RatingResult::Owned);
+        }
+        if !public {
+            return Err(ApiError::not_found("Recipe"));
+        }
+        // The recipe is checked again in case it stopped being public since
+        let rows = self
+            .graph
+            .execute(
+                query(&format!(
+                    "MATCH (u:User), (r:Recipe) \
+                    WHERE u.id = $u_id AND r.id = $r_id AND r.public = true \
+                    MERGE (u)-[rated:RATED]->(r) \
+                    SET rated.stars = $stars, rated.at = $at, \
+                        rated.review = {} \
+                    {} \
+                    RETURN count(*) AS changed",
+                    optional("review", ""),
+                    RATING_TOTALS
+                ))
+                .param("u_id", u_id)
+                .param("r_id", r_id)
+                .param("stars", i64::from(rating.stars))
+                .param("at", at)
+                .param("review", rating.review.clone().unwrap_or_default())
+                .param("review_set", rating.review.is_some()),
+            )
+            .await?;
+        if !any_changed(rows).await? {
+            return Err(ApiError::not_found("Recipe"));
+        }
+        if rated {
+            return Ok(RatingResult::Updated);
+        }
+        Ok(RatingResult::Rated)
+    }
+
+    async fn remove_rating(&self, u_id: &str, r_id: &str) -> ApiResult<()> {
+        self.graph
+            .run(
+                query(&format!(
+                    "MATCH (u:User)-[old:RATED]->(r:Recipe) WHERE u.id = $u_id AND r.id = $r_id \
+                    DELETE old \
+                    {}",
+                    RATING_TOTALS
+                ))
+                .param("u_id", u_id)
+                .param("r_id", r_id),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn reviews(&self, r_id: &str, page: &ReviewQuery) -> ApiResult<Reviews> {
+        let matched = "MATCH (u:User)-[rated:RATED]->(r:Recipe) \
+            WHERE r.id = $id AND rated.review IS NOT NULL";
+        let mut result = self
+            .graph
+            .execute(
+                query(&format!(
+                    "{} RETURN u.username AS username, rated \
+                    ORDER BY rated.at DESC, u.username SKIP $offset LIMIT $limit",
+                    matched
+                ))
+                .param("id", r_id)
+                .param("offset", page.offset() as i64)
+                .param("limit", page.limit() as i64),
+            )
+            .await?;
+        let mut reviews = Vec::new();
+        while let Some(row) = result.next().await? {
+            reviews.push(format_review(row)?);
+        }
+        let mut count = self
+            .graph
+            .execute(query(&format!("{} RETURN count(rated) AS total", matched)).param("id", r_id))
+            .await?;
+        let total = count
+            .next()
+            .await?
+            .and_then(|row| row.get::<i64>("total"))
+            .unwrap_or(0);
+        Ok(Reviews {
+            reviews,
+            page: page.page_info(total as usize),
+        })
+    }
+
+    async fn like_counts(&self, r_ids: &[String]) -> ApiResult<HashMap<Uuid, usize>> {
+        let mut result = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (:User)-[l:LIKES]->(r:Recipe) WHERE r.id IN $ids \
+                RETURN r.id AS id, count(l) AS likes",
+                )
+                .param("ids", r_ids.to_vec()),
+            )
+            .await?;
+        let mut counts = HashMap::new();
+        while let Some(row) = result.next().await? {
+            let id = row
+                .get::<String>("id")
+                .and_then(|id| Uuid::parse_str(&id).ok());
+            if let (Some(id), Some(likes)) = (id, row.get::<i64>("likes")) {
+                counts.insert(id, likes as usize);
+            }
+        }
+        Ok(counts)
+    }
+
+    async fn toggle_favorite(&self, u_id: &str, r_id: &str) -> ApiResult<bool> {
+        let mut result = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (u:User)-[f:FAVORITE]->(r:Recipe) WHERE u.id = $u_id AND r.id = $r_id \
+                DELETE f \
+                RETURN count(*) AS removed",
+                )
+                .param("u_id", u_id)
+                .param("r_id", r_id),
+            )
+            .await?;
+        let removed = result
+            .next()
+            .await?
+            .and_then(|row| row.get::<i64>("removed"))
+            .unwrap_or(0);
+        if removed > 0 {
+            return Ok(false);
+        }
+        self.graph
+            .run(
+                query(
+                    "MATCH (u:User), (r:Recipe) WHERE u.id = $u_id AND r.id = $r_id \
+                MERGE (u)-[:FAVORITE]->(r)",
+                )
+                .param("u_id", u_id)
+                .param("r_id", r_id),
+            )
+            .await?;
+        Ok(true)
+    }
+
+    async fn favorites(&self, u_id: &str) -> ApiResult<Vec<Uuid>> {
+        let mut result = self
+            .graph
+            .execute(
+                query("MATCH (u:User)-[:FAVORITE]->(r:Recipe) WHERE u.id = $id RETURN r.id AS id")
+                    .param("id", u_id),
+            )
+            .await?;
+        let mut favorites = Vec::new();
+        while let Some(row) = result.next().await? {
+            if let Some(id) = row
+                .get::<String>("id")
+                .and_then(|id| Uuid::parse_str(&id).ok())
+            {
+                favorites.push(id);
+            }
+        }
+        Ok(favorites)
+    }
+
+    async fn choose_recipes(
+        &self,
+        u_id: &str,
+        entries: &[PlanEntry],
+        created: NaiveDateTime,
+    ) -> ApiResult<()> {
+        // Parallel lists since the driver can't send maps, one CHOSEN per index
+        let ids: Vec<String> = entries.iter().map(|_| Uuid::new_v4().to_string()).collect();
+        let r_ids: Vec<String> = entries.iter().map(|e| e.recipe_id.clone()).collect();
+        let dates: Vec<NaiveDate> = entries.iter().map(|e| e.date).collect();
+        let slots: Vec<String> = entries
+            .iter()
+            .map(|e| e.slot.as_str().to_string())
+            .collect();
+        self.graph
+            .run(
+                query(
+                    "MATCH (u:User {id: $id}) \
+                UNWIND range(0, size($r_ids) - 1) AS idx \
+                MATCH (r:Recipe {id: $r_ids[idx]}) \
+                CREATE (u)-[:CHOSEN {id: $ids[idx], date: $dates[idx], slot: $slots[idx], \
+                    created: $exp}]->(r)",
+                )
+                .param("id", u_id)
+                .param("ids", ids)
+                .param("r_ids", r_ids)
+                .param("dates", dates)
+                .param("slots", slots)
+                .param("exp", created),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn chosen_recipes(&self, u_id: &str) -> ApiResult<Vec<Recipe>> {
+        let meals = self.chosen_meals(u_id).await?;
+        Ok(meals.into_iter().map(|meal| meal.recipe).collect())
+    }
+
+    async fn chosen_meals(&self, u_id: &str) -> ApiResult<Vec<PlannedMeal>> {
+        self.fetch_planned_meals(
+            query(&format!(
+                "MATCH (u:User)-[c:CHOSEN]->(r:Recipe) WHERE u.id = $id RETURN r, c, {}",
+                RECIPE_INGREDIENTS
+            ))
+            .param("id", u_id),
+        )
+        .await
+    }
+
+    async fn reset_chosen(&self, u_id: &str) -> ApiResult<()> {
+        self.graph
+            .run(
+                query(
+                    "MATCH (u:User)-[c:CHOSEN]->() \
+                WHERE u.id = $u_id \
+                DETACH DELETE c",
+                )
+                .param("u_id", u_id),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn meal_plan(
+        &self,
+        u_id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> ApiResult<Vec<PlannedMeal>> {
+        self.fetch_planned_meals(
+            query(&format!(
+                "MATCH (u:User)-[c:CHOSEN]->(r:Recipe) \
+                WHERE u.id = $id AND c.date >= $from AND c.date < $to \
+                RETURN r, c, {}",
+                RECIPE_INGREDIENTS
+            ))
+            .param("id", u_id)
+            .param("from", from)
+            .param("to", to),
+        )
+        .await
+    }
+
+    async fn move_planned_meal(&self, u_id: &str, m_id: &str, to: &PlanSlot) -> ApiResult<bool> {
+        let mut result = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (u:User)-[c:CHOSEN]->() \
+                WHERE u.id = $u_id AND c.id = $m_id \
+                SET c.date = $date, c.slot = $slot \
+                RETURN count(c) AS moved",
+                )
+                .param("u_id", u_id)
+                .param("m_id", m_id)
+                .param("date", to.date)
+                .param("slot", to.slot.as_str()),
+            )
+            .await?;
+        let moved = result
+            .next()
+            .await?
+            .and_then(|row| row.get::<i64>("moved"))
+            .unwrap_or(0);
+        Ok(moved > 0)
+    }
+
+    async fn swap_planned_meals(&self, u_id: &str, first: &str, second: &str) -> ApiResult<bool> {
+        // Both relationships in one MATCH are never the same one, so swapping a meal with itself
+        // finds nothing.
+        let mut result = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (u:User)-[a:CHOSEN]->(), (u)-[b:CHOSEN]->() \
+                WHERE u.id = $u_id AND a.id = $first AND b.id = $second \
+                WITH a, b, a.date AS date, a.slot AS slot \
+                SET a.date = b.date, a.slot = b.slot, b.date = date, b.slot = slot \
+                RETURN count(a) AS swapped",
+                )
+                .param("u_id", u_id)
+                .param("first", first)
+                .param("second", second),
+            )
+            .await?;
+        let swapped = result
+            .next()
+            .await?
+            .and_then(|row| row.get::<i64>("swapped"))
+            .unwrap_or(0);
+        Ok(swapped > 0)
+    }
+
+    async fn clear_plan_day(&self, u_id: &str, date: NaiveDate) -> ApiResult<()> {
+        self.graph
+            .run(
+                query(
+                    "MATCH (u:User)-[c:CHOSEN]->() \
+                WHERE u.id = $u_id AND c.date = $date \
+                DELETE c",
+                )
+                .param("u_id", u_id)
+                .param("date", date),
+            )
+            .await?;
+        Ok(())
+    }
+
+    // The history is kept as PLANNED relationships with the same properties CHOSEN had
+    async fn archive_plan(
+        &self,
+        u_id: &str,
+        before: NaiveDate,
+        archived: NaiveDateTime,
+    ) -> ApiResult<usize> {
+        let mut result = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (u:User)-[c:CHOSEN]->(r:Recipe) \
+                WHERE u.id = $u_id AND c.date < $before \
+                CREATE (u)-[:PLANNED {id: c.id, date: c.date, slot: c.slot, \
+                    created: c.created, archived: $archived}]->(r) \
+                DELETE c \
+                RETURN count(*) AS archived",
+                )
+                .param("u_id", u_id)
+                .param("before", before)
+                .param("archived", archived),
+            )
+            .await?;
+        let archived = result
+            .next()
+            .await?
+            .and_then(|row| row.get::<i64>("archived"))
+            .unwrap_or(0);
+        Ok(archived as usize)
+    }
+
+    async fn plan_history(
+        &self,
+        u_id: &str,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> ApiResult<Vec<PlannedMeal>> {
+        self.fetch_planned_meals(
+            query(&format!(
+                "MATCH (u:User)-[c:PLANNED]->(r:Recipe) \
+                WHERE u.id = $id AND c.date >= $from AND c.date < $to \
+                RETURN r, c, {}",
+                RECIPE_INGREDIENTS
+            ))
+            .param("id", u_id)
+            .param("from", from)
+            .param("to", to),
+        )
+        .await
+    }
+
+    // The counts live on the recipe so every query returning one has them without asking
+    async fn log_cooked(&self, u_id: &str, r_id: &str, cooked: &CookEntry) -> ApiResult<Uuid> {
+        let c_id = Uuid::new_v4();
+        self.graph
+            .run(
+                query(&format!(
+                    "MATCH (u:User), (r:Recipe) WHERE u.id = $u_id AND r.id = $r_id \
+                CREATE (u)-[:COOKED {{id: $c_id, at: $at, \
+                    servings: {}, notes: {}, rating: {}}}]->(r) \
+                SET r.times_cooked = coalesce(r.times_cooked, 0) + 1, \
+                    r.last_cooked = CASE WHEN r.last_cooked IS NULL OR r.last_cooked < $at \
+                    THEN $at ELSE r.last_cooked END",
+                    optional("servings", ""),
+                    optional("notes", ""),
+                    optional("rating", "")
+                ))
+                .param("u_id", u_id)
+                .param("r_id", r_id)
+                .param("c_id", c_id.to_string())
+                .param("at", cooked.at)
+                .param("servings", i64::from(cooked.servings.unwrap_or(0)))
+                .param("servings_set", cooked.servings.is_some())
+                .param("notes", cooked.notes.clone().unwrap_or_default())
+                .param("notes_set", cooked.notes.is_some())
+                .param("rating", i64::from(cooked.rating.unwrap_or(0)))
+                .param("rating_set", cooked.rating.is_some()),
+            )
+            .await?;
+        Ok(c_id)
+    }
+
+    async fn cook_log(
+        &self,
+        u_id: &str,
+        from: NaiveDateTime,
+        to: NaiveDateTime,
+    ) -> ApiResult<Vec<CookedRecipe>> {
+        let mut result = self
+            .graph
+            .execute(
+                query(&format!(
+                    "MATCH (u:User)-[c:COOKED]->(r:Recipe) \
+                    WHERE u.id = $id AND c.at >= $from AND c.at < $to \
+                    RETURN r, c, {} ORDER BY c.at",
+                    RECIPE_INGREDIENTS
+                ))
+                .param("id", u_id)
+                .param("from", from)
+                .param("to", to),
+            )
+            .await?;
+        let mut cooked = Vec::new();
+        while let Some(row) = result.next().await? {
+            cooked.push(format_cooked_recipe(row)?);
+        }
+        Ok(cooked)
+    }
+}
+
+#[async_trait]
+impl UserStore for Neo4jStore {
+    async fn all_users(&self) -> ApiResult<Vec<User>> {
+        let mut result = self.graph.execute(query("MATCH (u:User) RETURN u")).await?;
+
+        let mut users = Vec::new();
+
+        while let Some(row) = result.next().await? {
+            if let Some(node) = row.get::<Node>("u") {
+                users.push(format_user(node)?)
+            }
+        }
+        Ok(users)
+    }
+
+    async fn get_user(&self, u_id: &str) -> ApiResult<Option<User>> {
+        get_user_from_db(self.graph.clone(), u_id)
+            .await?
+            .map(format_user)
+            .transpose()
+    }
+
+    async fn get_user_by_name(&self, username: &str) -> ApiResult<Option<User>> {
+        let mut result = self
+            .graph
+            .execute(
+                query("MATCH (u:User) WHERE u.username = $name RETURN u").param("name", username),
+            )
+            .await?;
+
+        let row = result.next().await?;
+        row.and_then(|row| row.get::<Node>("u"))
+            .map(format_user)
+            .transpose()
+    }
+
+    // Duplicates are checked in the same transaction as the write. Another sign up can still get
+    // in between, then the constraints from the 001 migration turn the write down and checking
+    // again tells which field it was.
+    async fn create_user(&self, user: &User) -> ApiResult<()> {
+        let txn = self.graph.start_txn().await?;
+        let result = create_user_in_txn(&txn, user).await;
+        let result = finish_txn(txn, result).await;
+        if let Err(ApiError::Database(_)) = result {
+            let rows = self.graph.execute(taken_user_query(user)).await?;
+            if let Some(field) = taken_user_field(rows).await? {
+                return Err(user_conflict(field));
+            }
+        }
+        result
+    }
+
+    async fn exclusions(&self, u_id: &str) -> ApiResult<Vec<String>> {
+        let mut result = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (u:User) WHERE u.id = $id RETURN coalesce(u.exclusions, []) AS exclude",
+                )
+                .param("id", u_id),
+            )
+            .await?;
+        Ok(result
+            .next()
+            .await?
+            .and_then(|row| row.get::<Vec<String>>("exclude"))
+            .unwrap_or_default())
+    }
+
+    async fn set_exclusions(&self, u_id: &str, exclude: &[String]) -> ApiResult<()> {
+        self.graph
+            .run(
+                query("MATCH (u:User) WHERE u.id = $id SET u.exclusions = $exclude")
+                    .param("id", u_id)
+                    .param("exclude", exclude.to_vec()),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn plan_settings(&self, u_id: &str) -> ApiResult<PlanSettings> {
+        let mut result = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (u:User) WHERE u.id = $id \
+                RETURN u.retention_days AS retention_days, u.week_start AS week_start, \
+                u.time_zone AS time_zone",
+                )
+                .param("id", u_id),
+            )
+            .await?;
+        let defaults = PlanSettings::default();
+        Ok(match result.next().await? {
+            Some(row) => PlanSettings {
+                retention_days: row
+                    .get::<i64>("retention_days")
+                    .map(|days| days as u16)
+                    .unwrap_or(defaults.retention_days),
+                week_start: row
+                    .get::<String>("week_start")
+                    .and_then(|day| day.parse().ok())
+                    .unwrap_or(defaults.week_start),
+                time_zone: row.get::<String>("time_zone").unwrap_or(defaults.time_zone),
+            },
+            None => defaults,
+        })
+    }
+
+    async fn set_plan_settings(&self, u_id: &str, settings: &PlanSettings) -> ApiResult<()> {
+        self.graph
+            .run(
+                query(
+                    "MATCH (u:User) WHERE u.id = $id \
+                SET u.retention_days = $retention_days, u.week_start = $week_start, \
+                u.time_zone = $time_zone",
+                )
+                .param("id", u_id)
+                .param("retention_days", settings.retention_days as i64)
+                .param("week_start", settings.week_start.to_string())
+                .param("time_zone", settings.time_zone.as_str()),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn picker_weights(&self, u_id: &str) -> ApiResult<PickerWeights> {
+        let mut result = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (u:User) WHERE u.id = $id \
+                RETURN u.picker_likes AS likes, u.picker_recency AS recency, \
+                u.picker_favorites AS favorites",
+                )
+                .param("id", u_id),
+            )
+            .await?;
+        let defaults = PickerWeights::default();
+        Ok(match result.next().await? {
+            Some(row) => PickerWeights {
+                likes: row.get::<f64>("likes").unwrap_or(defaults.likes),
+                recency: row.get::<f64>("recency").unwrap_or(defaults.recency),
+                favorites: row.get::<f64>("favorites").unwrap_or(defaults.favorites),
+            },
+            None => defaults,
+        })
+    }
+
+    async fn set_picker_weights(&self, u_id: &str, weights: &PickerWeights) -> ApiResult<()> {
+        self.graph
+            .run(
+                query(
+                    "MATCH (u:User) WHERE u.id = $id \
+                SET u.picker_likes = $likes, u.picker_recency = $recency, \
+                u.picker_favorites = $favorites",
+                )
+                .param("id", u_id)
+                .param("likes", weights.likes)
+                .param("recency", weights.recency)
+                .param("favorites", weights.favorites),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ShoppingListStore for Neo4jStore {
+    async fn shopping_list(&self, u_id: &str) -> ApiResult<Option<SavedShoppingList>> {
+        let mut result = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (u:User)-[:HAS_LIST]->(l:ShoppingList) WHERE u.id = $u_id \
+                RETURN l, [(l)-[:CONTAINS]->(i:ShoppingItem) | \
+                [i.id, i.name, i.tipo, i.amount, toString(i.checked), toString(i.manual), \
+                toString(i.position)]] AS items",
+                )
+                .param("u_id", u_id),
+            )
+            .await?;
+        result.next().await?.map(format_shopping_list).transpose()
+    }
+
+    async fn save_shopping_list(&self, u_id: &str, list: &SavedShoppingList) -> ApiResult<()> {
+        let txn = self.graph.start_txn().await?;
+        let result = save_shopping_list_in_txn(&txn, u_id, list).await;
+        finish_txn(txn, result).await
+    }
+
+    async fn remove_shopping_list(&self, u_id: &str) -> ApiResult<()> {
+        let txn = self.graph.start_txn().await?;
+        let result = remove_shopping_list_in_txn(&txn, u_id).await;
+        finish_txn(txn, result).await
+    }
+
+    // Goes after the last item, see `save_shopping_list_in_txn` for the positions
+    async fn add_shopping_item(&self, u_id: &str, item: &ShoppingListItem) -> ApiResult<bool> {
+        let rows = self
+            .graph
+            .execute(
+                query(
+                    "MATCH (u:User)-[:HAS_LIST]->(l:ShoppingList) WHERE u.id = $u_id \
+                OPTIONAL MATCH (l)-[:CONTAINS]->(other:ShoppingItem) \
+                WITH l, coalesce(max(other.position), -1) + 1 AS position \
+                CREATE (l)-[:CONTAINS]->(:ShoppingItem {id: $id, name: $name, tipo: $tipo, \
+                    amount: $amount, checked: $checked, manual: $manual, position: position}) \
+                RETURN count(l) AS changed",
+                )
+                .param("u_id", u_id)
+                .param("id", item.id.to_string())
+                .param("name", item.name.clone())
+                .param("tipo", item.tipo.clone())
+                .param("amount", item.amount.clone())
+                .param("checked", item.checked)
+                .param("manual", item.manual),
+            )
+            .await?;
+        any_changed(rows).await
+    }
+
+    async fn update_shopping_item(
+        &self,
+        u_id: &str,
+        item_id: &str,
+        update: &ShoppingItemUpdate,
+    ) -> ApiResult<bool> {
+        let mut set_clauses = Vec::new();
+        if update.name.is_some() {
+            set_clauses.push("i.name = $name");
+        }
+        if update.tipo.is_some() {
+            set_clauses.push("i.tipo = $tipo");
+        }
+        if update.amount.is_some() {
+            set_clauses.push("i.amount = $amount");
+        }
+        if !set_clauses.is_empty() {
+            set_clauses.push("i.manual = true");
+        }
+        if update.checked.is_some() {
+            set_clauses.push("i.checked = $checked");
+        }
+        let set = if set_clauses.is_empty() {
+            String::new()
+        } else {
+            format!("SET {}", set_clauses.join(", "))
+        };
+
+        let mut item_query = query(&format!(
+            "{} {} RETURN count(i) AS changed",
+            SHOPPING_ITEM, set
+        ))
+        .param("u_id", u_id)
+        .param("i_id", item_id);
+        if let Some(name) = &update.name {
+            item_query = item_query.param("name", name.clone());
+        }
+        if let Some(tipo) = &update.tipo {
+            item_query = item_query.param("tipo", tipo.clone());
+        }
+        if let Some(amount) = &update.amount {
+            item_query = item_query.param("amount", amount.clone());
+        }
+        if let Some(checked) = update.checked {
+            item_query = item_query.param("checked", checked);
+        }
+        any_changed(self.graph.execute(item_query).await?).await
+    }
+
+    async fn toggle_shopping_item(&self, u_id: &str, item_id: &str) -> ApiResult<bool> {
+        let rows = self
+            .graph
+            .execute(
+                query(&format!(
+                    "{} SET i.checked = NOT i.checked RETURN count(i) AS changed",
+                    SHOPPING_ITEM
+                ))
+                .param("u_id", u_id)
+                .param("i_id", item_id),
+            )
+            .await?;
+        any_changed(rows).await
+    }
+
+    async fn remove_shopping_item(&self, u_id: &str, item_id: &str) -> ApiResult<bool> {
+        let rows = self
+            .graph
+            .execute(
+                query(&format!(
+                    "{} DETACH DELETE i RETURN count(*) AS changed",
+                    SHOPPING_ITEM
+                ))
+                .param("u_id", u_id)
+                .param("i_id", item_id),
+            )
+            .await?;
+        any_changed(rows).await
+    }
+}
@@ -0,0 +1,615 @@
+use super::*;
+use crate::helpers::plan::archive_expired_plans;
+use crate::models::{
+    CookEntry, Ingredient, MealSlot, PlanEntry, RecipeSort, SavedShoppingList, ShoppingItemUpdate,
+    ShoppingListItem, SortOrder,
+};
+use chrono::Datelike;
+
+// Everything that broke the old format! queries or would have injected cypher into them
+const HOSTILE: &[&str] = &[
+    "Grandma's \"best\" soup",
+    "back\\slash \\\" and \\n",
+    "\"}) DETACH DELETE n //",
+    "'}) MATCH (n) DETACH DELETE n //",
+    "$name {id: 'x'}, public: true",
+    "`backticks` ; MATCH (n) RETURN n",
+    "line\nbreak\ttab\r\n",
+    "ünïcödé 🍲 \u{0}",
+];
+
+// The in memory store always, plus neo4j when DB_URI is set. The neo4j run writes to that
+// database so point it at a throwaway one.
+async fn stores() -> Vec<StorePool> {
+    let mut stores: Vec<StorePool> = vec![Arc::new(MemoryStore::new())];
+    if std::env::var("DB_URI").is_ok() {
+        let graph = crate::connect_graph().await;
+        crate::migrate(&graph).await;
+        stores.push(Arc::new(Neo4jStore::new(graph)));
+    }
+    stores
+}
+
+async fn new_user(store: &StorePool) -> String {
+    let id = Uuid::new_v4();
+    store
+        .create_user(&User {
+            id: Some(id),
+            username: format!("user-{}", id),
+            password: "not a real hash".to_string(),
+            email: Some(format!("{}@example.com", id)),
+            role: None,
+        })
+        .await
+        .unwrap();
+    id.to_string()
+}
+
+// Every field the store writes gets something hostile or at the edge of its type
+fn hostile_recipe(text: &str) -> Recipe {
+    Recipe {
+        name: text.to_string(),
+        public: Some(true),
+        steps: Some(vec![
+            text.to_string(),
+            format!("1. {}", text),
+            String::new(),
+        ]),
+        tipo: Some(text.to_string()),
+        calories: Some(u16::MAX),
+        carbohydrates: Some(12.5),
+        fat: Some(0.25),
+        protein: Some(-31.75),
+        servings: Some(u16::MAX),
+        yield_unit: Some(text.to_string()),
+        meal_type: Some(text.to_string()),
+        time: Some(text.to_string()),
+        ingredients: Some(vec![Ingredient {
+            name: text.to_string(),
+            tipo: Some(text.to_string()),
+            amount: text.to_string(),
+            quantity: None,
+            unit: None,
+        }]),
+        ..Recipe::default()
+    }
+}
+
+// Ingredient names and tipos are stored lowercased on purpose, everything else comes back as sent
+fn assert_round_trip(sent: &Recipe, stored: &Recipe) {
+    assert_eq!(stored.name, sent.name);
+    assert_eq!(stored.public, sent.public);
+    assert_eq!(stored.steps, sent.steps);
+    assert_eq!(stored.tipo, sent.tipo);
+    assert_eq!(stored.calories, sent.calories);
+    assert_eq!(stored.carbohydrates, sent.carbohydrates);
+    assert_eq!(stored.fat, sent.fat);
+    assert_eq!(stored.protein, sent.protein);
+    assert_eq!(stored.servings, sent.servings);
+    assert_eq!(stored.yield_unit, sent.yield_unit);
+    assert_eq!(stored.meal_type, sent.meal_type);
+    assert_eq!(stored.time, sent.time);
+    assert!(stored.created.is_some());
+
+    let sent_ingredients = sent.ingredients.as_ref().unwrap();
+    let stored_ingredients = stored.ingredients.as_ref().unwrap();
+    assert_eq!(stored_ingredients.len(), sent_ingredients.len());
+    for (sent, stored) in sent_ingredients.iter().zip(stored_ingredients) {
+        assert_eq!(stored.name, sent.name.to_lowercase());
+        assert_eq!(
+            stored.tipo,
+            sent.tipo.as_ref().map(|tipo| tipo.to_lowercase())
+        );
+        assert_eq!(stored.amount, sent.amount);
+    }
+}
+
+#[rocket::async_test]
+async fn hostile_strings_are_stored_unchanged() {
+    for store in stores().await {
+        let u_id = new_user(&store).await;
+        for text in HOSTILE {
+            let sent = hostile_recipe(text);
+            let r_id = store.create_recipe(&u_id, &sent).await.unwrap();
+            let stored = store
+                .get_recipe(&u_id, &r_id.to_string())
+                .await
+                .unwrap()
+                .expect("the recipe was created");
+            assert_round_trip(&sent, &stored);
+        }
+        // Nothing the strings said got run, the user and their recipes are all still there
+        assert_eq!(
+            store.user_recipes(&u_id).await.unwrap().len(),
+            HOSTILE.len()
+        );
+    }
+}
+
+#[rocket::async_test]
+async fn hostile_updates_are_stored_unchanged() {
+    for store in stores().await {
+        let u_id = new_user(&store).await;
+        let plain = Recipe {
+            name: "Plain".to_string(),
+            ..Recipe::default()
+        };
+        let r_id = store
+            .create_recipe(&u_id, &plain)
+            .await
+            .unwrap()
+            .to_string();
+        for text in HOSTILE {
+            let sent = hostile_recipe(text);
+            let updated = store
+                .update_recipe(&u_id, &r_id, &RecipeUpdate::from(sent.clone()))
+                .await
+                .unwrap();
+            assert!(updated);
+            let stored = store.get_recipe(&u_id, &r_id).await.unwrap().unwrap();
+            assert_round_trip(&sent, &stored);
+        }
+    }
+}
+
+// The bool, u16 and f32 params at the other end of their range, plus a private recipe with no
+// steps or ingredients at all
+#[rocket::async_test]
+async fn typed_params_round_trip() {
+    for store in stores().await {
+        let u_id = new_user(&store).await;
+        let sent = Recipe {
+            name: "Zeroes".to_string(),
+            public: Some(false),
+            steps: Some(Vec::new()),
+            calories: Some(0),
+            carbohydrates: Some(0.0),
+            fat: Some(f32::MAX),
+            protein: Some(f32::MIN_POSITIVE),
+            servings: Some(1),
+            ingredients: Some(Vec::new()),
+            ..Recipe::default()
+        };
+        let r_id = store.create_recipe(&u_id, &sent).await.unwrap();
+        let stored = store
+            .get_recipe(&u_id, &r_id.to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.public, Some(false));
+        assert_eq!(stored.steps, Some(Vec::new()));
+        assert_eq!(stored.calories, Some(0));
+        assert_eq!(stored.carbohydrates, Some(0.0));
+        assert_eq!(stored.fat, Some(f32::MAX));
+        assert_eq!(stored.protein, Some(f32::MIN_POSITIVE));
+        assert_eq!(stored.servings, Some(1));
+        assert_eq!(
+            stored.ingredients.map(|ingredients| ingredients.len()),
+            Some(0)
+        );
+        // Private recipes stay out of the public listing
+        assert!(store
+            .get_public_recipe(&r_id.to_string())
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
+
+// Both stores turn a taken username or email down the same way, whichever field it was
+#[rocket::async_test]
+async fn taken_usernames_and_emails_are_refused() {
+    for store in stores().await {
+        let u_id = new_user(&store).await;
+        let user = |username: String, email: String| User {
+            id: Some(Uuid::new_v4()),
+            username,
+            password: "not a real hash".to_string(),
+            email: Some(email),
+            role: None,
+        };
+        let taken_name = user(format!("user-{}", u_id), format!("{}@other.com", u_id));
+        assert!(matches!(
+            store.create_user(&taken_name).await,
+            Err(ApiError::Forbidden(_))
+        ));
+        let taken_mail = user(format!("other-{}", u_id), format!("{}@example.com", u_id));
+        assert!(matches!(
+            store.create_user(&taken_mail).await,
+            Err(ApiError::Unauthorized(_))
+        ));
+    }
+}
+
+// "1h 30m" is more than "45 min" even though it sorts first as text, and "a while" has no minutes
+// so it goes last both ways
+#[rocket::async_test]
+async fn time_sorts_by_minutes() {
+    for store in stores().await {
+        let u_id = new_user(&store).await;
+        for (name, time) in &[
+            ("Stew", "1h 30m"),
+            ("Salad", "a while"),
+            ("Soup", "45 min"),
+            ("Toast", "5"),
+        ] {
+            let recipe = Recipe {
+                name: name.to_string(),
+                time: Some(time.to_string()),
+                ..Recipe::default()
+            };
+            store.create_recipe(&u_id, &recipe).await.unwrap();
+        }
+        for (order, expected) in &[
+            (SortOrder::Asc, ["Toast", "Soup", "Stew", "Salad"]),
+            (SortOrder::Desc, ["Stew", "Soup", "Toast", "Salad"]),
+        ] {
+            let list = RecipeQuery {
+                sort: Some(RecipeSort::Time),
+                order: Some(*order),
+                ..RecipeQuery::default()
+            };
+            let names: Vec<_> = store
+                .recipe_list(&u_id, &list)
+                .await
+                .unwrap()
+                .recipes
+                .into_iter()
+                .map(|recipe| recipe.name)
+                .collect();
+            assert_eq!(names, expected);
+        }
+    }
+}
+
+// Every pick is its own entry, whenever it was picked
+#[rocket::async_test]
+async fn chosen_meals_are_the_whole_plan() {
+    for store in stores().await {
+        let u_id = new_user(&store).await;
+        assert!(store.chosen_meals(&u_id).await.unwrap().is_empty());
+        let recipe = Recipe {
+            name: "Soup".to_string(),
+            ..Recipe::default()
+        };
+        let r_id = store.create_recipe(&u_id, &recipe).await.unwrap();
+        let monday = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+        let picked = monday.and_hms_opt(9, 0, 0).unwrap();
+        for (date, slot) in &[(monday, MealSlot::Dinner), (monday, MealSlot::Lunch)] {
+            let entries = [PlanEntry {
+                recipe_id: r_id.to_string(),
+                date: *date,
+                slot: *slot,
+            }];
+            store.choose_recipes(&u_id, &entries, picked).await.unwrap();
+        }
+        let mut meals: Vec<_> = store
+            .chosen_meals(&u_id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|meal| (meal.date, meal.slot, meal.recipe.name))
+            .collect();
+        meals.sort();
+        assert_eq!(
+            meals,
+            vec![
+                (monday, MealSlot::Lunch, "Soup".to_string()),
+                (monday, MealSlot::Dinner, "Soup".to_string()),
+            ]
+        );
+    }
+}
+
+fn shopping_item(name: &str) -> ShoppingListItem {
+    ShoppingListItem {
+        id: Uuid::new_v4(),
+        name: name.to_string(),
+        tipo: "verdura".to_string(),
+        amount: "2".to_string(),
+        checked: false,
+        manual: false,
+    }
+}
+
+// Each change lands on its own item and leaves the rest of the list as it was
+#[rocket::async_test]
+async fn shopping_items_change_one_at_a_time() {
+    for store in stores().await {
+        let u_id = new_user(&store).await;
+        let tomato = shopping_item("tomato");
+        let onion = shopping_item("onion");
+        let (tomato_id, onion_id) = (tomato.id.to_string(), onion.id.to_string());
+        // Nothing to change without a list
+        assert!(!store.add_shopping_item(&u_id, &tomato).await.unwrap());
+        assert!(!store.toggle_shopping_item(&u_id, &tomato_id).await.unwrap());
+
+        let list = SavedShoppingList {
+            id: Uuid::new_v4(),
+            created: chrono::Utc::now().naive_utc(),
+            plan: None,
+            items: vec![tomato],
+        };
+        store.save_shopping_list(&u_id, &list).await.unwrap();
+        let mut added = onion;
+        added.manual = true;
+        assert!(store.add_shopping_item(&u_id, &added).await.unwrap());
+        assert!(store.toggle_shopping_item(&u_id, &tomato_id).await.unwrap());
+        let update = ShoppingItemUpdate {
+            amount: Some("3".to_string()),
+            ..ShoppingItemUpdate::default()
+        };
+        assert!(store
+            .update_shopping_item(&u_id, &tomato_id, &update)
+            .await
+            .unwrap());
+        let missing = Uuid::new_v4().to_string();
+        assert!(!store
+            .update_shopping_item(&u_id, &missing, &update)
+            .await
+            .unwrap());
+
+        let items = store.shopping_list(&u_id).await.unwrap().unwrap().items;
+        let summary: Vec<_> = items
+            .iter()
+            .map(|i| (i.name.as_str(), i.amount.as_str(), i.checked, i.manual))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![("tomato", "3", true, true), ("onion", "2", false, true)]
+        );
+
+        assert!(store.remove_shopping_item(&u_id, &onion_id).await.unwrap());
+        assert!(!store.remove_shopping_item(&u_id, &onion_id).await.unwrap());
+        let items = store.shopping_list(&u_id).await.unwrap().unwrap().items;
+        assert_eq!(items.len(), 1);
+    }
+}
+
+// Nothing is saved for recipes the user can't see, the route already checks but the recipe can
+// change between the check and the write
+#[rocket::async_test]
+async fn rating_private_or_missing_recipes_is_not_found() {
+    for store in stores().await {
+        let owner = new_user(&store).await;
+        let rater = new_user(&store).await;
+        let private = Recipe {
+            name: "Secret".to_string(),
+            public: Some(false),
+            ..Recipe::default()
+        };
+        let r_id = store.create_recipe(&owner, &private).await.unwrap();
+        let rating = RatingForm {
+            stars: 5,
+            review: None,
+        };
+        let at = chrono::Utc::now().naive_utc();
+        for r_id in &[r_id.to_string(), Uuid::new_v4().to_string()] {
+            assert!(matches!(
+                store.rate_recipe(&rater, r_id, &rating, at).await,
+                Err(ApiError::NotFound(_))
+            ));
+        }
+        let stored = store
+            .get_recipe(&owner, &r_id.to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(stored.rating_count.unwrap_or(0), 0);
+    }
+}
+
+// Cooking counts on the recipe whatever order the entries come in, and the log window includes
+// `from` but not `to`
+#[rocket::async_test]
+async fn cooking_counts_and_the_log_window() {
+    for store in stores().await {
+        let u_id = new_user(&store).await;
+        let recipe = Recipe {
+            name: "Soup".to_string(),
+            ..Recipe::default()
+        };
+        let r_id = store
+            .create_recipe(&u_id, &recipe)
+            .await
+            .unwrap()
+            .to_string();
+        let monday = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+        let midnight = |days| {
+            (monday + chrono::Duration::days(days))
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        };
+        let cooked_at = [
+            midnight(2),
+            midnight(0),
+            midnight(1) + chrono::Duration::hours(19),
+        ];
+        for at in &cooked_at {
+            let cooked = CookEntry {
+                at: *at,
+                servings: Some(2),
+                notes: None,
+                rating: None,
+            };
+            store.log_cooked(&u_id, &r_id, &cooked).await.unwrap();
+        }
+        let stored = store.get_recipe(&u_id, &r_id).await.unwrap().unwrap();
+        assert_eq!(stored.times_cooked, Some(3));
+        assert_eq!(stored.last_cooked, Some(midnight(2)));
+
+        let log: Vec<_> = store
+            .cook_log(&u_id, midnight(0), midnight(2))
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|cooked| (cooked.cooked.at, cooked.recipe.name))
+            .collect();
+        assert_eq!(
+            log,
+            vec![
+                (cooked_at[1], "Soup".to_string()),
+                (cooked_at[2], "Soup".to_string()),
+            ]
+        );
+        let someone_else = new_user(&store).await;
+        let theirs = store.cook_log(&someone_else, midnight(0), midnight(7));
+        assert!(theirs.await.unwrap().is_empty());
+    }
+}
+
+// One recipe per entry, planned that many days after Monday 2026-03-02
+async fn plan_recipes(store: &StorePool, u_id: &str, entries: &[(&str, i64, MealSlot)]) {
+    let monday = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+    for (name, day, slot) in entries {
+        let recipe = Recipe {
+            name: name.to_string(),
+            ..Recipe::default()
+        };
+        let r_id = store.create_recipe(u_id, &recipe).await.unwrap();
+        let entry = [PlanEntry {
+            recipe_id: r_id.to_string(),
+            date: monday + chrono::Duration::days(*day),
+            slot: *slot,
+        }];
+        let picked = monday.and_hms_opt(9, 0, 0).unwrap();
+        store.choose_recipes(u_id, &entry, picked).await.unwrap();
+    }
+}
+
+fn days_and_names(meals: Vec<PlannedMeal>) -> Vec<(u32, MealSlot, String)> {
+    meals
+        .into_iter()
+        .map(|meal| (meal.date.day(), meal.slot, meal.recipe.name))
+        .collect()
+}
+
+#[rocket::async_test]
+async fn meals_move_swap_and_clear() {
+    for store in stores().await {
+        let u_id = new_user(&store).await;
+        plan_recipes(
+            &store,
+            &u_id,
+            &[
+                ("Soup", 0, MealSlot::Dinner),
+                ("Pasta", 1, MealSlot::Dinner),
+                ("Salad", 1, MealSlot::Lunch),
+            ],
+        )
+        .await;
+        let monday = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+        let week = || store.meal_plan(&u_id, monday, monday + chrono::Duration::days(7));
+        let meals = week().await.unwrap();
+        let id = |name: &str| {
+            let meal = meals.iter().find(|meal| meal.recipe.name == name);
+            meal.unwrap().id.to_string()
+        };
+        let missing = Uuid::new_v4().to_string();
+
+        let wednesday_lunch = PlanSlot {
+            date: monday + chrono::Duration::days(2),
+            slot: MealSlot::Lunch,
+        };
+        assert!(store
+            .move_planned_meal(&u_id, &id("Soup"), &wednesday_lunch)
+            .await
+            .unwrap());
+        assert!(!store
+            .move_planned_meal(&u_id, &missing, &wednesday_lunch)
+            .await
+            .unwrap());
+        assert!(store
+            .swap_planned_meals(&u_id, &id("Pasta"), &id("Salad"))
+            .await
+            .unwrap());
+        assert!(!store
+            .swap_planned_meals(&u_id, &id("Pasta"), &missing)
+            .await
+            .unwrap());
+        assert_eq!(
+            days_and_names(week().await.unwrap()),
+            vec![
+                (3, MealSlot::Lunch, "Pasta".to_string()),
+                (3, MealSlot::Dinner, "Salad".to_string()),
+                (4, MealSlot::Lunch, "Soup".to_string()),
+            ]
+        );
+
+        // Someone else's meals can't be touched
+        let someone_else = new_user(&store).await;
+        assert!(!store
+            .move_planned_meal(&someone_else, &id("Soup"), &wednesday_lunch)
+            .await
+            .unwrap());
+        store
+            .clear_plan_day(&someone_else, monday + chrono::Duration::days(1))
+            .await
+            .unwrap();
+        assert_eq!(week().await.unwrap().len(), 3);
+
+        store
+            .clear_plan_day(&u_id, monday + chrono::Duration::days(1))
+            .await
+            .unwrap();
+        assert_eq!(
+            days_and_names(week().await.unwrap()),
+            vec![(4, MealSlot::Lunch, "Soup".to_string())]
+        );
+    }
+}
+
+// Days before `retention_days` ago move to the history, everything after stays in the plan
+#[rocket::async_test]
+async fn expired_days_go_to_the_history() {
+    for store in stores().await {
+        let u_id = new_user(&store).await;
+        plan_recipes(
+            &store,
+            &u_id,
+            &[
+                ("Soup", 0, MealSlot::Dinner),
+                ("Pasta", 2, MealSlot::Dinner),
+                ("Salad", 1, MealSlot::Lunch),
+            ],
+        )
+        .await;
+        let settings = PlanSettings {
+            retention_days: 8,
+            ..PlanSettings::default()
+        };
+        store.set_plan_settings(&u_id, &settings).await.unwrap();
+        // 2026-03-04 is the first day kept
+        let now = NaiveDate::from_ymd_opt(2026, 3, 12)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap();
+        assert!(archive_expired_plans(&store, now).await.unwrap() >= 2);
+
+        let march = NaiveDate::from_ymd_opt(2026, 3, 1).unwrap();
+        let april = NaiveDate::from_ymd_opt(2026, 4, 1).unwrap();
+        assert_eq!(
+            days_and_names(store.meal_plan(&u_id, march, april).await.unwrap()),
+            vec![(4, MealSlot::Dinner, "Pasta".to_string())]
+        );
+        let history = store.plan_history(&u_id, march, april).await.unwrap();
+        assert_eq!(
+            days_and_names(history),
+            vec![
+                (2, MealSlot::Dinner, "Soup".to_string()),
+                (3, MealSlot::Lunch, "Salad".to_string()),
+            ]
+        );
+        let tuesday = NaiveDate::from_ymd_opt(2026, 3, 3).unwrap();
+        let window = store.plan_history(&u_id, tuesday, april).await.unwrap();
+        assert_eq!(
+            days_and_names(window),
+            vec![(3, MealSlot::Lunch, "Salad".to_string())]
+        );
+
+        // Running it again finds nothing new
+        archive_expired_plans(&store, now).await.unwrap();
+        let history = store.plan_history(&u_id, march, april).await.unwrap();
+        assert_eq!(history.len(), 2);
+    }
+}
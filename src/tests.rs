@@ -0,0 +1,703 @@
+use super::rocket;
+use crate::models::{Recipe, RecipeVec, User, ADMIN_ROLE};
+use crate::store::{MemoryStore, StorePool};
+use argon2::password_hash::{PasswordHasher, SaltString};
+use argon2::Argon2;
+use rand_core::OsRng;
+use rocket::http::{ContentType, Status};
+use rocket::local::asynchronous::Client;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+const PASSWORD: &str = "secretpassword";
+
+// The whole app on an empty in memory store. The client keeps the cookies between requests so
+// signing up leaves it logged in.
+async fn client() -> Client {
+    client_with(Arc::new(MemoryStore::new())).await
+}
+
+async fn client_with(store: StorePool) -> Client {
+    Client::tracked(rocket(store))
+        .await
+        .expect("valid rocket instance")
+}
+
+// Nothing in the API hands out the admin role so the user goes straight into the store
+async fn add_admin(store: &StorePool, username: &str) {
+    let salt = SaltString::generate(&mut OsRng);
+    let password = Argon2::default()
+        .hash_password_simple(PASSWORD.as_bytes(), salt.as_ref())
+        .unwrap()
+        .to_string();
+    store
+        .create_user(&User {
+            id: Some(uuid::Uuid::new_v4()),
+            username: username.to_string(),
+            password,
+            email: Some(format!("{}@example.com", username)),
+            role: Some(ADMIN_ROLE.to_string()),
+        })
+        .await
+        .unwrap();
+}
+
+async fn log_in(client: &Client, username: &str) {
+    let response = client
+        .post("/api/login")
+        .header(ContentType::JSON)
+        .body(json!({"username": username, "password": PASSWORD}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Accepted);
+}
+
+async fn sign_up(client: &Client, username: &str) {
+    let response = client
+        .post("/api/users/new")
+        .header(ContentType::JSON)
+        .body(
+            json!({
+                "username": username,
+                "password": PASSWORD,
+                "email": format!("{}@example.com", username),
+            })
+            .to_string(),
+        )
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+}
+
+fn recipe_json(name: &str) -> Value {
+    json!({
+        "name": name,
+        "public": false,
+        "steps": ["Boil the water", "Add the pasta"],
+        "tipo": "pasta",
+        "calories": 450,
+        "servings": 2,
+        "mealType": "dinner",
+        "time": "20 min",
+        "ingredients": [
+            {"name": "pasta", "amount": "200 g"},
+            {"name": "salt", "amount": "1 tsp"},
+        ],
+    })
+}
+
+async fn create_recipe(client: &Client, name: &str) -> String {
+    add_recipe(client, recipe_json(name)).await
+}
+
+// Like `create_recipe` with whatever else the test needs in the recipe
+async fn add_recipe(client: &Client, recipe: Value) -> String {
+    let name = recipe["name"].as_str().unwrap().to_string();
+    let response = client
+        .post("/api/recipes/new")
+        .header(ContentType::JSON)
+        .body(recipe.to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+    let list: RecipeVec = client
+        .get("/api/recipes/list")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    list.recipes
+        .into_iter()
+        .find(|recipe| recipe.name == name)
+        .and_then(|recipe| recipe.id)
+        .expect("the new recipe is in the list")
+        .to_string()
+}
+
+async fn get_recipe(client: &Client, r_id: &str) -> (Status, Option<Recipe>) {
+    let response = client
+        .get(format!("/api/recipes/{}", r_id))
+        .dispatch()
+        .await;
+    let status = response.status();
+    if status != Status::Ok {
+        return (status, None);
+    }
+    (status, response.into_json().await)
+}
+
+async fn chosen_names(client: &Client) -> Vec<String> {
+    let chosen: RecipeVec = client
+        .get("/api/recipes/chosen")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    chosen
+        .recipes
+        .into_iter()
+        .map(|recipe| recipe.name)
+        .collect()
+}
+
+#[rocket::async_test]
+async fn sign_up_and_log_in() {
+    let client = client().await;
+    sign_up(&client, "ann").await;
+    assert_eq!(
+        client.get("/api/recipes/list").dispatch().await.status(),
+        Status::Ok
+    );
+
+    let response = client.get("/api/logout").dispatch().await;
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(
+        client.get("/api/recipes/list").dispatch().await.status(),
+        Status::Unauthorized
+    );
+
+    let wrong = client
+        .post("/api/login")
+        .header(ContentType::JSON)
+        .body(json!({"username": "ann", "password": "wrongpassword"}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(wrong.status(), Status::Forbidden);
+    let unknown = client
+        .post("/api/login")
+        .header(ContentType::JSON)
+        .body(json!({"username": "bob", "password": PASSWORD}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(unknown.status(), Status::Unauthorized);
+
+    let login = client
+        .post("/api/login")
+        .header(ContentType::JSON)
+        .body(json!({"username": "ann", "password": PASSWORD}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(login.status(), Status::Accepted);
+    assert_eq!(
+        client.get("/api/recipes/list").dispatch().await.status(),
+        Status::Ok
+    );
+}
+
+#[rocket::async_test]
+async fn taken_usernames_are_refused() {
+    let client = client().await;
+    sign_up(&client, "ann").await;
+    let response = client
+        .post("/api/users/new")
+        .header(ContentType::JSON)
+        .body(
+            json!({"username": "ann", "password": PASSWORD, "email": "other@example.com"})
+                .to_string(),
+        )
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Forbidden);
+}
+
+#[rocket::async_test]
+async fn recipe_create_get_update_delete() {
+    let client = client().await;
+    sign_up(&client, "ann").await;
+    let r_id = create_recipe(&client, "Pasta").await;
+
+    let (status, recipe) = get_recipe(&client, &r_id).await;
+    assert_eq!(status, Status::Ok);
+    let recipe = recipe.unwrap();
+    assert_eq!(recipe.name, "Pasta");
+    assert_eq!(
+        recipe.steps.unwrap(),
+        vec!["Boil the water", "Add the pasta"]
+    );
+    assert_eq!(recipe.servings, Some(2));
+    assert_eq!(recipe.ingredients.unwrap().len(), 2);
+
+    // PUT replaces everything
+    let mut replaced = recipe_json("Soup");
+    replaced["steps"] = json!(["Simmer"]);
+    replaced["ingredients"] = json!([{"name": "stock", "amount": "1 l"}]);
+    let response = client
+        .put(format!("/api/recipes/{}", r_id))
+        .header(ContentType::JSON)
+        .body(replaced.to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let recipe = get_recipe(&client, &r_id).await.1.unwrap();
+    assert_eq!(recipe.name, "Soup");
+    assert_eq!(recipe.steps.unwrap(), vec!["Simmer"]);
+    let ingredients = recipe.ingredients.unwrap();
+    assert_eq!(ingredients.len(), 1);
+    assert_eq!(ingredients[0].name, "stock");
+
+    // PATCH only touches what was sent
+    let response = client
+        .patch(format!("/api/recipes/{}", r_id))
+        .header(ContentType::JSON)
+        .body(json!({"calories": 300}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let recipe = get_recipe(&client, &r_id).await.1.unwrap();
+    assert_eq!(recipe.calories, Some(300));
+    assert_eq!(recipe.name, "Soup");
+
+    let response = client
+        .delete(format!("/api/recipes/remove/{}", r_id))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(get_recipe(&client, &r_id).await.0, Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn only_owners_edit_recipes() {
+    let client = client().await;
+    sign_up(&client, "ann").await;
+    let r_id = create_recipe(&client, "Pasta").await;
+
+    client.get("/api/logout").dispatch().await;
+    sign_up(&client, "bob").await;
+    let response = client
+        .patch(format!("/api/recipes/{}", r_id))
+        .header(ContentType::JSON)
+        .body(json!({"name": "Mine now"}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+    // Private recipes aren't there for anyone else either
+    assert_eq!(get_recipe(&client, &r_id).await.0, Status::NotFound);
+}
+
+#[rocket::async_test]
+async fn choose_and_list_chosen() {
+    let client = client().await;
+    sign_up(&client, "ann").await;
+    let pasta = create_recipe(&client, "Pasta").await;
+    let soup = create_recipe(&client, "Soup").await;
+    assert!(chosen_names(&client).await.is_empty());
+
+    let response = client
+        .post("/api/recipes/weekly")
+        .header(ContentType::JSON)
+        .body(json!({ "ids": [soup, pasta] }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+    // Chosen in plan order, one dinner a day from today
+    assert_eq!(chosen_names(&client).await, vec!["Soup", "Pasta"]);
+
+    let response = client.delete("/api/recipes/weeklyreset").dispatch().await;
+    assert_eq!(response.status(), Status::NoContent);
+    assert!(chosen_names(&client).await.is_empty());
+}
+
+// Allergens and densities are shared by everyone's recipes
+#[rocket::async_test]
+async fn only_admins_change_ingredients() {
+    let store: StorePool = Arc::new(MemoryStore::new());
+    add_admin(&store, "root").await;
+    let client = client_with(store).await;
+    sign_up(&client, "ann").await;
+    create_recipe(&client, "Pasta").await;
+
+    let allergens = json!({"allergens": ["gluten"]}).to_string();
+    let density = json!({"density": 0.6}).to_string();
+    for status in &[Status::Forbidden, Status::NoContent] {
+        let response = client
+            .put("/api/recipes/ingredient/pasta/allergens")
+            .header(ContentType::JSON)
+            .body(&allergens)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), *status);
+        let response = client
+            .put("/api/recipes/ingredient/pasta/density")
+            .header(ContentType::JSON)
+            .body(&density)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), *status);
+        client.get("/api/logout").dispatch().await;
+        log_in(&client, "root").await;
+    }
+
+    let stored: Value = client
+        .get("/api/recipes/ingredient/pasta/allergens")
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(stored["allergens"], json!(["gluten"]));
+}
+
+async fn choose(client: &Client, ids: &[&str]) {
+    let response = client
+        .post("/api/recipes/weekly")
+        .header(ContentType::JSON)
+        .body(json!({ "ids": ids }).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+}
+
+async fn new_shopping_list(client: &Client) -> Value {
+    let response = client.post("/api/shopping-list/").dispatch().await;
+    assert_eq!(response.status(), Status::Created);
+    response.into_json().await.unwrap()
+}
+
+#[rocket::async_test]
+async fn shopping_items_keep_a_name() {
+    let client = client().await;
+    sign_up(&client, "ann").await;
+    let pasta = create_recipe(&client, "Pasta").await;
+    choose(&client, &[&pasta]).await;
+    let list = new_shopping_list(&client).await;
+    let item = list["items"][0]["id"].as_str().unwrap();
+
+    let response = client
+        .patch(format!("/api/shopping-list/items/{}", item))
+        .header(ContentType::JSON)
+        .body(json!({"name": "  "}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+async fn plan(client: &Client, r_id: &str, date: &str, slot: &str) {
+    let response = client
+        .post("/api/plan/")
+        .header(ContentType::JSON)
+        .body(json!({"recipeId": r_id, "date": date, "slot": slot}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+}
+
+async fn shopping_list(client: &Client) -> Value {
+    let response = client.get("/api/shopping-list/").dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json().await.unwrap()
+}
+
+// Clearing a day doesn't pick anything new but the list still has to catch up
+#[rocket::async_test]
+async fn changing_the_plan_outdates_the_list() {
+    let client = client().await;
+    sign_up(&client, "ann").await;
+    let pasta = create_recipe(&client, "Pasta").await;
+    let soup = create_recipe(&client, "Soup").await;
+    plan(&client, &pasta, "2026-03-02", "dinner").await;
+    plan(&client, &soup, "2026-03-03", "dinner").await;
+    assert_eq!(new_shopping_list(&client).await["outdated"], false);
+    assert_eq!(shopping_list(&client).await["outdated"], false);
+
+    let response = client.delete("/api/plan/day/2026-03-03").dispatch().await;
+    assert_eq!(response.status(), Status::NoContent);
+    assert_eq!(shopping_list(&client).await["outdated"], true);
+
+    let response = client
+        .post("/api/shopping-list/regenerate")
+        .dispatch()
+        .await;
+    let list: Value = response.into_json().await.unwrap();
+    assert_eq!(list["outdated"], false);
+}
+
+fn item_id(list: &Value, name: &str) -> String {
+    list["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|item| item["name"] == name)
+        .and_then(|item| item["id"].as_str())
+        .expect("the item is on the list")
+        .to_string()
+}
+
+#[rocket::async_test]
+async fn shopping_list_generate_toggle_regenerate() {
+    let client = client().await;
+    sign_up(&client, "ann").await;
+    let pasta = create_recipe(&client, "Pasta").await;
+    plan(&client, &pasta, "2026-03-02", "dinner").await;
+    let list = new_shopping_list(&client).await;
+    let salt = item_id(&list, "salt");
+
+    let response = client
+        .put(format!("/api/shopping-list/items/{}/toggle", salt))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let response = client
+        .post("/api/shopping-list/items")
+        .header(ContentType::JSON)
+        .body(json!({"name": " Wine ", "amount": "1 bottle"}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Created);
+
+    // A second pasta dinner doubles the pasta, salt stays checked and the wine stays
+    plan(&client, &pasta, "2026-03-03", "dinner").await;
+    let response = client
+        .post("/api/shopping-list/regenerate")
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    let list: Value = response.into_json().await.unwrap();
+    let items: Vec<_> = list["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| {
+            (
+                item["name"].as_str().unwrap().to_string(),
+                item["amount"].as_str().unwrap().to_string(),
+                item["checked"].as_bool().unwrap(),
+                item["manual"].as_bool().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        items,
+        vec![
+            ("wine".to_string(), "1 bottle".to_string(), false, true),
+            ("pasta".to_string(), "400 g".to_string(), false, false),
+            ("salt".to_string(), "2 tsp".to_string(), true, false),
+        ]
+    );
+    assert_eq!(item_id(&list, "salt"), salt);
+
+    let response = client
+        .put(format!(
+            "/api/shopping-list/items/{}/toggle",
+            uuid::Uuid::new_v4()
+        ))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NotFound);
+}
+
+async fn rate(client: &Client, r_id: &str, stars: u8, review: &str) -> Status {
+    client
+        .put(format!("/api/recipes/{}/rating", r_id))
+        .header(ContentType::JSON)
+        .body(json!({"stars": stars, "review": review}).to_string())
+        .dispatch()
+        .await
+        .status()
+}
+
+async fn reviews(client: &Client, r_id: &str, query: &str) -> Value {
+    let response = client
+        .get(format!("/api/recipes/public/{}/reviews?{}", r_id, query))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json().await.unwrap()
+}
+
+#[rocket::async_test]
+async fn rate_rerate_and_remove_ratings() {
+    let client = client().await;
+    sign_up(&client, "ann").await;
+    let soup = create_recipe(&client, "Soup").await;
+    let secret = create_recipe(&client, "Secret").await;
+    let response = client
+        .patch(format!("/api/recipes/{}", soup))
+        .header(ContentType::JSON)
+        .body(json!({"public": true}).to_string())
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    assert_eq!(rate(&client, &soup, 5, "").await, Status::Forbidden);
+
+    for (username, stars) in &[("bob", 4), ("cat", 2), ("dan", 3)] {
+        client.get("/api/logout").dispatch().await;
+        sign_up(&client, username).await;
+        let review = format!("{} stars from {}", stars, username);
+        assert_eq!(rate(&client, &soup, *stars, &review).await, Status::Created);
+    }
+    assert_eq!(rate(&client, &secret, 5, "").await, Status::NotFound);
+    // Rating again replaces it, dan's review goes away with a blank one
+    assert_eq!(rate(&client, &soup, 1, "  ").await, Status::Ok);
+    assert_eq!(rate(&client, &soup, 6, "").await, Status::BadRequest);
+
+    let recipe: Recipe = client
+        .get(format!("/api/recipes/public/{}", soup))
+        .dispatch()
+        .await
+        .into_json()
+        .await
+        .unwrap();
+    assert_eq!(recipe.rating_count, Some(3));
+    assert_eq!(recipe.average_rating, Some(7.0 / 3.0));
+
+    // Newest first, only the ratings with a review
+    let first = reviews(&client, &soup, "limit=1").await;
+    assert_eq!(first["page"]["total"], 2);
+    assert_eq!(first["reviews"][0]["username"], "cat");
+    let second = reviews(&client, &soup, "offset=1&limit=1").await;
+    assert_eq!(second["reviews"][0]["username"], "bob");
+    assert_eq!(second["reviews"][0]["review"], "4 stars from bob");
+
+    let response = client
+        .delete(format!("/api/recipes/{}/rating", soup))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NoContent);
+    client.get("/api/logout").dispatch().await;
+    log_in(&client, "bob").await;
+    let response = client
+        .delete(format!("/api/recipes/{}/rating", soup))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::NoContent);
+    let left = reviews(&client, &soup, "").await;
+    assert_eq!(left["page"]["total"], 1);
+    assert_eq!(left["reviews"][0]["username"], "cat");
+}
+
+#[rocket::async_test]
+async fn log_and_history_windows_go_forward() {
+    let client = client().await;
+    sign_up(&client, "ann").await;
+    for route in &["/api/recipes/cooked", "/api/plan/history"] {
+        for (query, status) in &[
+            ("from=2026-03-01&to=2026-03-08", Status::Ok),
+            ("from=2026-03-08&to=2026-03-08", Status::Ok),
+            ("from=2026-03-08&to=2026-03-01", Status::BadRequest),
+            ("from=2026-03-40", Status::BadRequest),
+        ] {
+            let response = client.get(format!("{}?{}", route, query)).dispatch().await;
+            assert_eq!(response.status(), *status, "{}?{}", route, query);
+        }
+    }
+}
+
+// Ann's soups and pastas, with nuts tagged as an allergen by an admin. Returns the client logged
+// in as ann.
+async fn recipes_to_search() -> Client {
+    let store: StorePool = Arc::new(MemoryStore::new());
+    add_admin(&store, "root").await;
+    let client = client_with(store).await;
+    sign_up(&client, "ann").await;
+    create_recipe(&client, "Pasta").await;
+    for (name, ingredients, step) in &[
+        ("Tomato soup", &["tomato", "onion"][..], "Blend it"),
+        (
+            "Tomato pasta",
+            &["tomato", "pasta", "salt"][..],
+            "Boil the pasta",
+        ),
+        (
+            "Peanut noodles",
+            &["peanuts", "pasta"][..],
+            "Serve with <b>tomato</b> & lime",
+        ),
+    ] {
+        let mut recipe = recipe_json(name);
+        recipe["tipo"] = json!("dinner");
+        recipe["steps"] = json!([step]);
+        recipe["ingredients"] = ingredients
+            .iter()
+            .map(|name| json!({"name": name, "amount": "100 g"}))
+            .collect();
+        add_recipe(&client, recipe).await;
+    }
+
+    client.get("/api/logout").dispatch().await;
+    log_in(&client, "root").await;
+    let status = client
+        .put("/api/recipes/ingredient/peanuts/allergens")
+        .header(ContentType::JSON)
+        .body(json!({"allergens": ["nuts"]}).to_string())
+        .dispatch()
+        .await
+        .status();
+    assert_eq!(status, Status::NoContent);
+    client.get("/api/logout").dispatch().await;
+    log_in(&client, "ann").await;
+    client
+}
+
+async fn search(client: &Client, query: &str) -> Value {
+    let response = client
+        .get(format!("/api/recipes/search?{}", query))
+        .dispatch()
+        .await;
+    assert_eq!(response.status(), Status::Ok);
+    response.into_json().await.unwrap()
+}
+
+fn hit_names(results: &Value, key: &str) -> Vec<String> {
+    results[key]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|hit| hit["recipe"]["name"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[rocket::async_test]
+async fn search_ranks_filters_and_highlights() {
+    let client = recipes_to_search().await;
+    let results = search(&client, "q=tomato%20soup").await;
+    assert_eq!(
+        hit_names(&results, "hits"),
+        ["Tomato soup", "Tomato pasta", "Peanut noodles"]
+    );
+    let best = &results["hits"][0]["snippets"][0];
+    assert_eq!(best["field"], "name");
+    assert_eq!(best["text"], "<mark>Tomato</mark> <mark>soup</mark>");
+    let steps = &results["hits"][2]["snippets"][0];
+    assert_eq!(steps["field"], "steps");
+    assert_eq!(
+        steps["text"],
+        "Serve with &lt;b&gt;<mark>tomato</mark>&lt;/b&gt; &amp; lime"
+    );
+
+    let results = search(&client, "q=tomato&exclude=onion").await;
+    assert_eq!(
+        hit_names(&results, "hits"),
+        ["Tomato pasta", "Peanut noodles"]
+    );
+    // Both score the same, their order is up to the ids
+    let results = search(&client, "q=tomato&exclude=nuts").await;
+    let mut names = hit_names(&results, "hits");
+    names.sort();
+    assert_eq!(names, ["Tomato pasta", "Tomato soup"]);
+    let response = client.get("/api/recipes/search?q=%20!").dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+}
+
+#[rocket::async_test]
+async fn cookable_recipes_leave_out_allergens() {
+    let client = recipes_to_search().await;
+    let cook = |query: &'static str| {
+        client
+            .post(format!("/api/recipes/cook?{}", query))
+            .header(ContentType::JSON)
+            .body(json!({"ingredients": ["Pasta", "salt"]}).to_string())
+            .dispatch()
+    };
+    let cookable: Value = cook("").await.into_json().await.unwrap();
+    assert_eq!(
+        hit_names(&cookable, "recipes"),
+        ["Pasta", "Tomato pasta", "Peanut noodles"]
+    );
+    assert_eq!(cookable["recipes"][0]["missing"], json!([]));
+    assert_eq!(cookable["recipes"][1]["missing"][0]["name"], "tomato");
+
+    let cookable: Value = cook("exclude=nuts").await.into_json().await.unwrap();
+    assert_eq!(hit_names(&cookable, "recipes"), ["Pasta", "Tomato pasta"]);
+}